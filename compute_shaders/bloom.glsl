@@ -0,0 +1,83 @@
+#version 450
+
+/*
+Bloom of glowing matter. The emission image is downsampled to half resolution, blurred horizontally & vertically
+and added over the canvas colors into the composited image, which is then drawn instead of the canvas.
+*/
+
+layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+#define MODE_DOWNSAMPLE 0
+#define MODE_BLUR 1
+#define MODE_COMPOSITE 2
+
+layout(set = 0, binding = 0, rgba16f) restrict uniform readonly image2D source_img;
+layout(set = 0, binding = 1, rgba16f) restrict uniform writeonly image2D target_img;
+layout(set = 0, binding = 2, rgba8) restrict uniform readonly image2D canvas_img;
+layout(set = 0, binding = 3, rgba8) restrict uniform writeonly image2D composited_img;
+
+layout(push_constant) uniform PushConstants {
+    // Axis blurred along
+    ivec2 direction;
+    uint mode;
+    int radius;
+    float intensity;
+} push_constants;
+
+vec4 load_source(ivec2 pos) {
+    return imageLoad(source_img, clamp(pos, ivec2(0), imageSize(source_img) - 1));
+}
+
+// Average of the 2x2 emission cells under a bloom cell
+void downsample(ivec2 pos) {
+    ivec2 source_pos = 2 * pos;
+    vec4 sum = load_source(source_pos) + load_source(source_pos + ivec2(1, 0)) +
+        load_source(source_pos + ivec2(0, 1)) + load_source(source_pos + ivec2(1, 1));
+    imageStore(target_img, pos, sum * 0.25);
+}
+
+// Gaussian blur along the push constant direction
+void blur(ivec2 pos) {
+    int radius = push_constants.radius;
+    float sigma = max(float(radius) * 0.5, 1.0);
+    vec4 sum = vec4(0.0);
+    float weights = 0.0;
+    for (int i = -radius; i <= radius; i++) {
+        float weight = exp(-float(i * i) / (2.0 * sigma * sigma));
+        sum += weight * load_source(pos + i * push_constants.direction);
+        weights += weight;
+    }
+    imageStore(target_img, pos, sum / weights);
+}
+
+// Bilinear sample of the half resolution bloom at a canvas position
+vec4 sample_bloom(ivec2 canvas_pos) {
+    vec2 pos = (vec2(canvas_pos) + 0.5) * 0.5 - 0.5;
+    ivec2 base = ivec2(floor(pos));
+    vec2 f = pos - floor(pos);
+    vec4 top = mix(load_source(base), load_source(base + ivec2(1, 0)), f.x);
+    vec4 bottom = mix(load_source(base + ivec2(0, 1)), load_source(base + ivec2(1, 1)), f.x);
+    return mix(top, bottom, f.y);
+}
+
+void composite(ivec2 pos) {
+    vec4 color = imageLoad(canvas_img, pos);
+    vec3 glow = push_constants.intensity * sample_bloom(pos).rgb;
+    imageStore(composited_img, pos, vec4(min(color.rgb + glow, vec3(1.0)), color.a));
+}
+
+void main() {
+    ivec2 pos = ivec2(gl_GlobalInvocationID.xy);
+    uint mode = push_constants.mode;
+    if (mode == MODE_COMPOSITE) {
+        if (all(lessThan(pos, imageSize(canvas_img)))) {
+            composite(pos);
+        }
+    } else if (all(lessThan(pos, imageSize(target_img)))) {
+        if (mode == MODE_DOWNSAMPLE) {
+            downsample(pos);
+        } else {
+            blur(pos);
+        }
+    }
+}
@@ -14,12 +14,80 @@ vec4 linear_from_srgba(vec4 srgba) {
     return vec4(linear_from_srgb(srgba.rgb * 255.0), srgba.a);
 }
 
+#define VIEW_MATTER 0
+#define VIEW_STATE 1
+#define VIEW_MATTER_ID 2
+#define VIEW_CHANGED 3
+#define VIEW_CHUNK_ACTIVITY 4
+
+// Whether any cell of the work group changed this step
+shared bool chunk_active;
+
+vec4 state_color(Matter matter) {
+    uint state = get_definition(matter).state;
+    if (state == state_powder) {
+        return vec4(0.9, 0.8, 0.2, 1.0);
+    } else if (state == state_liquid) {
+        return vec4(0.1, 0.4, 0.9, 1.0);
+    } else if (state == state_solid) {
+        return vec4(0.5, 0.5, 0.5, 1.0);
+    } else if (state == state_solid_gravity) {
+        return vec4(0.6, 0.4, 0.2, 1.0);
+    } else if (state == state_gas) {
+        return vec4(0.8, 0.9, 1.0, 1.0);
+    } else if (state == state_energy) {
+        return vec4(1.0, 0.3, 0.0, 1.0);
+    } else if (state == state_object) {
+        return vec4(0.7, 0.2, 0.9, 1.0);
+    }
+    return vec4(0.0, 0.0, 0.0, 1.0);
+}
+
+// Distinct color for each matter id
+vec4 id_color(Matter matter) {
+    if (is_empty(matter)) {
+        return vec4(0.0, 0.0, 0.0, 1.0);
+    }
+    uint h = pcg_hash(matter.matter);
+    vec3 rgb = vec3(float(h & uint(255)), float((h >> 8u) & uint(255)), float((h >> 16u) & uint(255))) / 255.0;
+    // Keep colors away from black
+    return vec4(0.25 + 0.75 * rgb, 1.0);
+}
+
 void write_color_to_image(ivec2 pos) {
     Matter matter = read_matter(pos);
+    vec4 color = matter_color_to_vec4(matter.color);
+    uint view = push_constants.debug_view;
+    if (view == VIEW_STATE) {
+        color = state_color(matter);
+    } else if (view == VIEW_MATTER_ID) {
+        color = id_color(matter);
+    } else if (view == VIEW_CHANGED || view == VIEW_CHUNK_ACTIVITY) {
+        bool changed = matter_in[get_index(pos)] != previous_matter[get_index(pos)];
+        if (gl_LocalInvocationIndex == 0) {
+            chunk_active = false;
+        }
+        barrier();
+        if (changed) {
+            chunk_active = true;
+        }
+        barrier();
+        if (view == VIEW_CHANGED) {
+            color = changed ? vec4(1.0) : vec4(color.rgb * 0.25, 1.0);
+        } else {
+            bool is_border = any(equal(gl_LocalInvocationID.xy, uvec2(0)));
+            vec3 tint = chunk_active ? vec3(0.0, 1.0, 0.0) : vec3(0.0);
+            color.rgb = mix(color.rgb * (chunk_active ? 1.0 : 0.5), tint, is_border ? 0.8 : 0.15);
+        }
+    }
     // Our swapchain is in SRGB color space (default by bevy_vulkano). The system tries to interpret our canvas image as such. But our canvas image is
     // UNORM (only way to ImageStore), thus we need to convert the colors to linear space. We are assuming that images
     // Are already in SRGB color space. When we render, the linear gets interpreted as SRGB.
-    write_image_color(pos, linear_from_srgba(matter_color_to_vec4(matter.color)));
+    vec4 linear_color = linear_from_srgba(color);
+    write_image_color(pos, linear_color);
+    // Debug views don't glow
+    float emissive = view == VIEW_MATTER ? get_definition(matter).emissive : 0.0;
+    write_image_emission(pos, linear_color.rgb * emissive);
 }
 
 void main() {
@@ -2,46 +2,64 @@
 
 #include "includes.glsl"
 
-// https://stackoverflow.com/questions/4200224/random-noise-functions-for-glsl
-float PHI = 1.61803398874989484820459; // Golden ratio
-float rand(in vec2 xy, in float seed){
-    return fract(tan(distance(xy * PHI, xy) * seed) * xy.x);
-}
+#define SHAPE_CIRCLE 0
+#define SHAPE_SQUARE 1
+#define SHAPE_SPRAY 2
+#define SHAPE_OUTLINE 3
+
+#define MODE_REPLACE 0
+#define MODE_REPLACE_EMPTY 1
+#define MODE_REPLACE_MATTER 2
+#define MODE_ERASE_MATTER 3
 
-vec4 vary_color_rgb(vec4 color, ivec2 seed_pos) {
-    // Just use the same seed (means same color for individual xy position)
-    float seed = 0.1;
-    float p = rand(seed_pos, seed);
-    float variation = -0.1 + 0.2 * p;
-    color.rgb += vec3(variation);
-    return color;
+// Is pos inside the brush shape centered at draw_pos
+bool is_inside_brush(ivec2 pos, ivec2 draw_pos, DrawPrimitive draw) {
+    vec2 diff = vec2(pos) - vec2(draw_pos);
+    float dist = round(length(diff));
+    float radius = draw.radius;
+    if (draw.shape == SHAPE_SQUARE) {
+        return max(abs(diff.x), abs(diff.y)) <= radius;
+    } else if (draw.shape == SHAPE_SPRAY) {
+        // Seeded by the stroke so that consecutive strokes spray different cells
+        uint seed = push_constants.sim_step ^ pcg_hash(floatBitsToUint(draw.start.x) ^
+            pcg_hash(floatBitsToUint(draw.start.y)));
+        return dist <= radius && random(pos, seed) < draw.spray_density;
+    } else if (draw.shape == SHAPE_OUTLINE) {
+        return dist <= radius && dist > radius - 1.0;
+    }
+    return dist <= radius;
 }
 
-uint variate_color(ivec2 pos, uint color) {
-    vec4 color_f32 = matter_color_to_vec4(color);
-    vec4 variated_color_f32 = vary_color_rgb(color_f32, pos);
-    uint rgb = ((uint(variated_color_f32.r * 255.0) & uint(255)) << uint(16)) |
-            ((uint(variated_color_f32.g * 255.0) & uint(255)) << uint(8)) |
-            (uint(variated_color_f32.b * 255.0) & uint(255));
-    return rgb;
+// May the brush change the current matter
+bool is_allowed_by_brush_mode(Matter current, DrawPrimitive draw) {
+    if (draw.mode == MODE_REPLACE_EMPTY) {
+        return is_empty(current);
+    } else if (draw.mode == MODE_REPLACE_MATTER || draw.mode == MODE_ERASE_MATTER) {
+        return current.matter == draw.mode_matter;
+    }
+    return true;
 }
 
-void draw_matter_circle(ivec2 pos, ivec2 draw_pos, float radius, Matter matter) {
-    int y_start = draw_pos.y - int(radius);
-    int y_end = draw_pos.y + int(radius);
-    int x_start = draw_pos.x - int(radius);
-    int x_end = draw_pos.x + int(radius);
-    if (pos.x >= x_start && pos.x <= x_end && pos.y >= y_start && pos.y <= y_end) {
-        vec2 diff = vec2(pos) - vec2(draw_pos);
-        float dist = length(diff);
-        if (round(dist) <= radius) {
-            // We vary color only if not empty
-            if (!is_empty(matter)) {
-                matter.color = variate_color(pos, matter.color);
-            }
-            write_matter_input(pos, matter);
+// Draw the primitive over current, returns whether current changed
+bool draw_matter_brush(ivec2 pos, ivec2 draw_pos, DrawPrimitive draw, inout Matter current) {
+    int y_start = draw_pos.y - int(draw.radius);
+    int y_end = draw_pos.y + int(draw.radius);
+    int x_start = draw_pos.x - int(draw.radius);
+    int x_end = draw_pos.x + int(draw.radius);
+    if (pos.x >= x_start && pos.x <= x_end && pos.y >= y_start && pos.y <= y_end &&
+        is_inside_brush(pos, draw_pos, draw) && is_allowed_by_brush_mode(current, draw)) {
+        Matter matter = new_matter(draw.matter);
+        if (draw.mode == MODE_ERASE_MATTER) {
+            matter = new_matter(empty_matter);
+        }
+        // We vary color only if not empty
+        if (!is_empty(matter)) {
+            matter.color = variate_color(pos, matter.color);
         }
+        current = matter;
+        return true;
     }
+    return false;
 }
 
 // Line v->w, point p
@@ -58,13 +76,23 @@ vec2 closest_point_on_line(vec2 v, vec2 w, vec2 p) {
     return projection;
 }
 
+// Dispatched over the area of all queued draws, which are drawn in order
 void main() {
-    ivec2 pos = get_current_sim_pos();
-    vec2 point_on_line = closest_point_on_line(push_constants.draw_pos_start, push_constants.draw_pos_end, pos);
-    draw_matter_circle(
-        pos,
-        ivec2(point_on_line),
-        push_constants.draw_radius,
-        new_matter(push_constants.draw_matter)
-    );
-}
\ No newline at end of file
+    ivec2 pos = draw_area_min + get_current_sim_pos();
+    if (any(greaterThan(pos, draw_area_max))) {
+        return;
+    }
+    Matter current = read_matter(pos);
+    bool changed = false;
+    for (uint i = 0; i < draw_count; i++) {
+        DrawPrimitive draw = draw_primitives[i];
+        if (any(lessThan(pos, draw.bounds_min)) || any(greaterThan(pos, draw.bounds_max))) {
+            continue;
+        }
+        vec2 point_on_line = closest_point_on_line(draw.start, draw.end, pos);
+        changed = draw_matter_brush(pos, ivec2(point_on_line), draw, current) || changed;
+    }
+    if (changed) {
+        write_matter_input(pos, current);
+    }
+}
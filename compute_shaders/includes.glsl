@@ -15,26 +15,52 @@ layout(constant_id = 10) const uint state_object = 1;
 
 layout(local_size_x_id = 11, local_size_y_id = 12, local_size_z = 1) in;
 
+#include "matter.glsl"
+
 /*
 Buffers
 */
 layout(set = 0, binding = 0) restrict buffer MatterInBuffer { uint matter_in[]; };
 layout(set = 0, binding = 1) restrict writeonly buffer MatterOutBuffer { uint matter_out[]; };
 layout(set = 0, binding = 2, rgba8) restrict uniform writeonly image2D canvas_img;
-layout(set = 0, binding = 3) restrict writeonly buffer QueryMatterBuffer { uint query_matter[]; };
+layout(set = 0, binding = 3) restrict readonly buffer MatterDefinitionsBuffer { MatterDefinition definitions[]; };
+
+// Must match `DrawPrimitive::to_gpu_words`
+struct DrawPrimitive {
+    vec2 start;
+    vec2 end;
+    float radius;
+    uint matter;
+    // See `Brush`
+    uint shape;
+    uint mode;
+    uint mode_matter;
+    float spray_density;
+    // Inclusive bounds of the cells the primitive may change
+    ivec2 bounds_min;
+    ivec2 bounds_max;
+};
+
+// Queued draws, see `draw_queue::draw_buffer`
+layout(std430, set = 0, binding = 4) restrict readonly buffer DrawPrimitivesBuffer {
+    uint draw_count;
+    ivec2 draw_area_min;
+    ivec2 draw_area_max;
+    DrawPrimitive draw_primitives[];
+};
+// Grid at the start of the step, only up to date with debug views that need it
+layout(set = 0, binding = 5) restrict readonly buffer PreviousMatterBuffer { uint previous_matter[]; };
+// Light emitted by each cell, blurred into bloom when rendering
+layout(set = 0, binding = 6, rgba16f) restrict uniform writeonly image2D emission_img;
 
 layout(push_constant) uniform PushConstants {
     uint sim_step;
     uint move_step;
-    vec2 draw_pos_start;
-    vec2 draw_pos_end;
-    float draw_radius;
-    uint draw_matter;
-    ivec2 query_pos;
+    // See `DebugView`
+    uint debug_view;
 } push_constants;
 
 #include "dirs.glsl"
-#include "matter.glsl"
 
 /*
 Utility functions to be used in the various kernels:
@@ -45,7 +71,7 @@ ivec2 get_current_sim_pos() {
 }
 
 int get_index(ivec2 pos) {
-    return pos.y * canvas_size_y + pos.x;
+    return pos.y * canvas_size_x + pos.x;
 }
 
 bool is_at_border_top(ivec2 pos) {
@@ -77,10 +103,6 @@ uint matter_to_uint(Matter matter) {
     return ((matter.color << uint(8)) | matter.matter);
 }
 
-void write_query_matter(Matter matter) {
-    query_matter[0] = matter_to_uint(matter);
-}
-
 void write_matter(ivec2 pos, Matter matter) {
     matter_out[get_index(pos)] = matter_to_uint(matter);
 }
@@ -93,6 +115,10 @@ void write_image_color(ivec2 pos, vec4 color) {
     imageStore(canvas_img, pos, color);
 }
 
+void write_image_emission(ivec2 pos, vec3 emission) {
+    imageStore(emission_img, pos, vec4(emission, 1.0));
+}
+
 ivec2 get_pos_at_dir(ivec2 pos, int dir) {
     return pos + OFFSETS[dir];
 }
@@ -113,14 +139,27 @@ bool is_empty(Matter matter) {
     return matter.matter == 0;
 }
 
+MatterDefinition get_definition(Matter m) {
+    return definitions[m.matter];
+}
+
 bool is_gravity(Matter m) {
-    return m.matter == 1 || m.matter == 3;
+    uint state = get_definition(m).state;
+    return state == state_powder || state == state_liquid;
 }
 
 bool falls_on_empty(Matter from, Matter to) {
     return is_gravity(from) && is_empty(to);
 }
 
+bool is_gas(Matter m) {
+    return get_definition(m).state == state_gas;
+}
+
+bool rises_on_empty(Matter from, Matter to) {
+    return is_gas(from) && is_empty(to);
+}
+
 bool slides_on_empty(Matter from_diagonal, Matter to_diagonal, Matter from_down, Matter side) {
     return is_gravity(from_diagonal) && !is_empty(from_down) && is_empty(to_diagonal) && is_empty(side);
 }
@@ -154,3 +193,40 @@ vec4 matter_color_to_vec4(uint color) {
         1.0);
 }
 
+// https://stackoverflow.com/questions/4200224/random-noise-functions-for-glsl
+float PHI = 1.61803398874989484820459; // Golden ratio
+float rand(in vec2 xy, in float seed){
+    return fract(tan(distance(xy * PHI, xy) * seed) * xy.x);
+}
+
+vec4 vary_color_rgb(vec4 color, ivec2 seed_pos) {
+    // Just use the same seed (means same color for individual xy position)
+    float seed = 0.1;
+    float p = rand(seed_pos, seed);
+    float variation = -0.1 + 0.2 * p;
+    color.rgb += vec3(variation);
+    return color;
+}
+
+uint variate_color(ivec2 pos, uint color) {
+    vec4 color_f32 = matter_color_to_vec4(color);
+    vec4 variated_color_f32 = vary_color_rgb(color_f32, pos);
+    uint rgb = ((uint(variated_color_f32.r * 255.0) & uint(255)) << uint(16)) |
+            ((uint(variated_color_f32.g * 255.0) & uint(255)) << uint(8)) |
+            (uint(variated_color_f32.b * 255.0) & uint(255));
+    return rgb;
+}
+
+// https://www.reedbeta.com/blog/hash-functions-for-gpu-rendering/
+uint pcg_hash(uint x) {
+    uint state = x * 747796405u + 2891336453u;
+    uint word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Random number in [0, 1). Same position & seed always give the same number, which keeps the
+// simulation deterministic.
+float random(ivec2 pos, uint seed) {
+    uint h = pcg_hash(uint(pos.x) ^ pcg_hash(uint(pos.y) ^ pcg_hash(seed)));
+    return float(h >> 8u) / 16777216.0;
+}
@@ -1,3 +1,24 @@
+#define MAX_TRANSITIONS 5
+
+// Must match `MatterDefinition::to_gpu_words`
+struct MatterReaction {
+    uint reacts;
+    uint direction;
+    float probability;
+    uint becomes;
+};
+
+struct MatterDefinition {
+    // Matter id with base color
+    uint matter;
+    uint state;
+    float weight;
+    uint characteristics;
+    MatterReaction reactions[MAX_TRANSITIONS];
+    // Glow intensity, 0 if the matter doesn't glow
+    float emissive;
+};
+
 struct Matter {
     uint matter;
     uint color;
@@ -0,0 +1,44 @@
+#version 450
+
+/*
+Counts the cells of each matter within a region of the grid. Each work group counts its cells in shared memory
+and adds them to the population buffer once.
+*/
+
+layout(constant_id = 0) const int canvas_size_x = 1;
+
+layout(local_size_x_id = 1, local_size_y_id = 2, local_size_z = 1) in;
+
+#define MAX_MATTERS 256
+
+layout(set = 0, binding = 0) restrict readonly buffer MatterInBuffer { uint matter_in[]; };
+layout(set = 0, binding = 1) restrict buffer PopulationBuffer { uint population[]; };
+
+layout(push_constant) uniform PushConstants {
+    // Inclusive bounds of the counted region
+    ivec2 region_min;
+    ivec2 region_max;
+} push_constants;
+
+shared uint group_population[MAX_MATTERS];
+
+void main() {
+    uint group_size = gl_WorkGroupSize.x * gl_WorkGroupSize.y;
+    for (uint i = gl_LocalInvocationIndex; i < MAX_MATTERS; i += group_size) {
+        group_population[i] = 0;
+    }
+    barrier();
+
+    ivec2 pos = push_constants.region_min + ivec2(gl_GlobalInvocationID.xy);
+    if (all(lessThanEqual(pos, push_constants.region_max))) {
+        uint matter = matter_in[pos.y * canvas_size_x + pos.x] & uint(255);
+        atomicAdd(group_population[matter], 1);
+    }
+    barrier();
+
+    for (uint i = gl_LocalInvocationIndex; i < MAX_MATTERS; i += group_size) {
+        if (group_population[i] > 0) {
+            atomicAdd(population[i], group_population[i]);
+        }
+    }
+}
@@ -0,0 +1,44 @@
+#version 450
+
+#include "includes.glsl"
+
+// Does a neighbor in one of the reaction's directions have a characteristic the reaction reacts to
+bool is_triggered(ivec2 pos, MatterReaction reaction) {
+    // Reactions without characteristics happen on their own
+    if (reaction.reacts == 0) {
+        return true;
+    }
+    for (int dir = 0; dir < 8; dir++) {
+        if ((reaction.direction & (uint(1) << uint(dir))) != 0) {
+            Matter neighbor = get_neighbor(pos, dir);
+            if ((get_definition(neighbor).characteristics & reaction.reacts) != 0) {
+                return true;
+            }
+        }
+    }
+    return false;
+}
+
+// First reaction that triggers and passes its probability changes the matter
+void react(ivec2 pos) {
+    Matter current = read_matter(pos);
+    MatterDefinition definition = get_definition(current);
+    Matter m = current;
+    for (int i = 0; i < MAX_TRANSITIONS; i++) {
+        MatterReaction reaction = definition.reactions[i];
+        uint seed = push_constants.sim_step * uint(MAX_TRANSITIONS) + uint(i);
+        if (reaction.probability > 0.0 && is_triggered(pos, reaction) &&
+            random(pos, seed) < reaction.probability) {
+            m = new_matter(definitions[reaction.becomes].matter);
+            if (!is_empty(m)) {
+                m.color = variate_color(pos, m.color);
+            }
+            break;
+        }
+    }
+    write_matter(pos, m);
+}
+
+void main() {
+    react(get_current_sim_pos());
+}
\ No newline at end of file
@@ -0,0 +1,200 @@
+use std::sync::Arc;
+
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    device::Queue,
+    format::Format,
+    image::{ImageAccess, ImageUsage, StorageImage},
+    pipeline::{ComputePipeline, Pipeline, PipelineBindPoint},
+};
+use vulkano_util::renderer::DeviceImageView;
+
+use crate::utils::{create_compute_pipeline, storage_image_desc};
+
+/// Largest blur radius in bloom cells (half a canvas cell each)
+pub const MAX_BLOOM_RADIUS: u32 = 32;
+/// Must match the local size in `bloom.glsl`
+const BLOOM_LOCAL_SIZE: u32 = 8;
+
+/// How glowing matter lights up its surroundings
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BloomSettings {
+    pub enabled: bool,
+    /// Brightness of the glow added over the canvas
+    pub intensity: f32,
+    /// How far the glow spreads in bloom cells
+    pub radius: u32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        BloomSettings {
+            enabled: true,
+            intensity: 1.0,
+            radius: 8,
+        }
+    }
+}
+
+#[repr(u32)]
+#[derive(Debug, Copy, Clone)]
+enum BloomMode {
+    Downsample = 0,
+    Blur = 1,
+    Composite = 2,
+}
+
+/// Images the bloom is computed in, sized after the canvas
+struct BloomImages {
+    size: [u32; 2],
+    /// Half resolution emission, blurred back & forth between the two
+    ping: DeviceImageView,
+    pong: DeviceImageView,
+    /// Canvas with the bloom added
+    composited: DeviceImageView,
+}
+
+/// Compute passes blurring the emission of glowing matter & adding it over the canvas colors
+pub struct BloomPass {
+    queue: Arc<Queue>,
+    pipeline: Arc<ComputePipeline>,
+    images: Option<BloomImages>,
+}
+
+impl BloomPass {
+    pub fn new(queue: Arc<Queue>) -> BloomPass {
+        let shader = bloom_cs::load(queue.device().clone()).unwrap();
+        let pipeline = create_compute_pipeline(
+            queue.clone(),
+            shader.entry_point("main").unwrap(),
+            vec![
+                (0, storage_image_desc()),
+                (1, storage_image_desc()),
+                (2, storage_image_desc()),
+                (3, storage_image_desc()),
+            ],
+            &(),
+        );
+        BloomPass {
+            queue,
+            pipeline,
+            images: None,
+        }
+    }
+
+    fn storage_image(&self, size: [u32; 2], format: Format) -> DeviceImageView {
+        StorageImage::general_purpose_image_view(
+            self.queue.clone(),
+            size,
+            format,
+            ImageUsage {
+                sampled: true,
+                storage: true,
+                ..ImageUsage::none()
+            },
+        )
+        .unwrap()
+    }
+
+    /// Bloom images for a canvas of size, recreated if the size changed
+    fn images(&mut self, size: [u32; 2]) -> &BloomImages {
+        if self
+            .images
+            .as_ref()
+            .map_or(true, |images| images.size != size)
+        {
+            let half_size = [(size[0] + 1) / 2, (size[1] + 1) / 2];
+            self.images = Some(BloomImages {
+                size,
+                ping: self.storage_image(half_size, Format::R16G16B16A16_SFLOAT),
+                pong: self.storage_image(half_size, Format::R16G16B16A16_SFLOAT),
+                composited: self.storage_image(size, Format::R8G8B8A8_UNORM),
+            });
+        }
+        self.images.as_ref().unwrap()
+    }
+
+    /// Append the bloom of emission over canvas to builder. Returns the image to draw in place of the
+    /// canvas.
+    pub fn record(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        canvas: DeviceImageView,
+        emission: DeviceImageView,
+        settings: &BloomSettings,
+    ) -> DeviceImageView {
+        let size = canvas.image().dimensions().width_height();
+        let pipeline = self.pipeline.clone();
+        let images = self.images(size);
+        let (ping, pong, composited) = (
+            images.ping.clone(),
+            images.pong.clone(),
+            images.composited.clone(),
+        );
+        let half_size = ping.image().dimensions().width_height();
+        let radius = settings.radius.clamp(1, MAX_BLOOM_RADIUS) as i32;
+        let mut dispatch = |mode: BloomMode,
+                            source: DeviceImageView,
+                            target: DeviceImageView,
+                            direction: [i32; 2],
+                            size: [u32; 2]| {
+            let pipeline_layout = pipeline.layout();
+            let desc_layout = pipeline_layout.set_layouts().get(0).unwrap();
+            let set = PersistentDescriptorSet::new(desc_layout.clone(), [
+                WriteDescriptorSet::image_view(0, source),
+                WriteDescriptorSet::image_view(1, target),
+                WriteDescriptorSet::image_view(2, canvas.clone()),
+                WriteDescriptorSet::image_view(3, composited.clone()),
+            ])
+            .unwrap();
+            let push_constants = bloom_cs::ty::PushConstants {
+                direction,
+                mode: mode as u32,
+                radius,
+                intensity: settings.intensity,
+            };
+            builder
+                .bind_pipeline_compute(pipeline.clone())
+                .bind_descriptor_sets(PipelineBindPoint::Compute, pipeline_layout.clone(), 0, set)
+                .push_constants(pipeline_layout.clone(), 0, push_constants)
+                .dispatch([
+                    (size[0] + BLOOM_LOCAL_SIZE - 1) / BLOOM_LOCAL_SIZE,
+                    (size[1] + BLOOM_LOCAL_SIZE - 1) / BLOOM_LOCAL_SIZE,
+                    1,
+                ])
+                .unwrap();
+        };
+        dispatch(
+            BloomMode::Downsample,
+            emission,
+            ping.clone(),
+            [0, 0],
+            half_size,
+        );
+        dispatch(
+            BloomMode::Blur,
+            ping.clone(),
+            pong.clone(),
+            [1, 0],
+            half_size,
+        );
+        dispatch(
+            BloomMode::Blur,
+            pong.clone(),
+            ping.clone(),
+            [0, 1],
+            half_size,
+        );
+        // Pong is only bound as the unused target
+        dispatch(BloomMode::Composite, ping, pong, [0, 0], size);
+        composited
+    }
+}
+
+mod bloom_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "compute_shaders/bloom.glsl"
+    }
+}
@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+use crate::matter::MatterId;
+
+/// Shape drawn around each point of a brush stroke
+#[repr(u32)]
+#[derive(EnumIter, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum BrushShape {
+    #[default]
+    Circle = 0,
+    Square = 1,
+    /// Circle where only a random part of the cells is drawn
+    Spray = 2,
+    /// Only the outline of a circle
+    Outline = 3,
+}
+
+/// Which cells a brush stroke is allowed to change
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum BrushMode {
+    /// Draw over everything
+    #[default]
+    Replace,
+    /// Draw only where the grid is empty
+    ReplaceEmpty,
+    /// Draw only over the given matter
+    ReplaceMatter(MatterId),
+    /// Erase only the given matter, the drawn matter is ignored
+    EraseMatter(MatterId),
+}
+
+impl BrushMode {
+    /// Modes selectable in the gui, modes with a matter use `target`
+    pub fn all(target: MatterId) -> [BrushMode; 4] {
+        [
+            BrushMode::Replace,
+            BrushMode::ReplaceEmpty,
+            BrushMode::ReplaceMatter(target),
+            BrushMode::EraseMatter(target),
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BrushMode::Replace => "Replace",
+            BrushMode::ReplaceEmpty => "Replace only empty",
+            BrushMode::ReplaceMatter(_) => "Replace only matter",
+            BrushMode::EraseMatter(_) => "Erase only matter",
+        }
+    }
+
+    /// The matter the mode is restricted to
+    pub fn target(&self) -> Option<MatterId> {
+        match self {
+            BrushMode::ReplaceMatter(matter) | BrushMode::EraseMatter(matter) => Some(*matter),
+            _ => None,
+        }
+    }
+
+    /// May a cell of current matter be changed (cpu side of `is_allowed_by_brush_mode` in
+    /// `draw_matter.glsl`)
+    pub fn allows(&self, current: MatterId) -> bool {
+        match self {
+            BrushMode::Replace => true,
+            BrushMode::ReplaceEmpty => current == MatterId::EMPTY,
+            BrushMode::ReplaceMatter(target) | BrushMode::EraseMatter(target) => current == *target,
+        }
+    }
+
+    /// Matter written to allowed cells when drawing matter
+    pub fn drawn_matter(&self, matter: MatterId) -> MatterId {
+        match self {
+            BrushMode::EraseMatter(_) => MatterId::EMPTY,
+            _ => matter,
+        }
+    }
+
+    /// Mode & target as passed to `draw_matter.glsl`
+    pub fn to_gpu(&self) -> (u32, u32) {
+        match self {
+            BrushMode::Replace => (0, 0),
+            BrushMode::ReplaceEmpty => (1, 0),
+            BrushMode::ReplaceMatter(matter) => (2, matter.0 as u32),
+            BrushMode::EraseMatter(matter) => (3, matter.0 as u32),
+        }
+    }
+}
+
+/// How matter is drawn
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct Brush {
+    pub shape: BrushShape,
+    pub mode: BrushMode,
+    /// Part of the cells a spray brush draws (0 - 1)
+    pub spray_density: f32,
+}
+
+impl Brush {
+    /// Same brush erasing instead of drawing. A mode restricted to a matter erases only that matter,
+    /// any other erases everything.
+    pub fn eraser(&self) -> Brush {
+        let mode = match self.mode {
+            BrushMode::ReplaceMatter(matter) | BrushMode::EraseMatter(matter) => {
+                BrushMode::EraseMatter(matter)
+            }
+            _ => BrushMode::Replace,
+        };
+        Brush { mode, ..*self }
+    }
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Brush {
+            shape: BrushShape::default(),
+            mode: BrushMode::default(),
+            spray_density: 0.1,
+        }
+    }
+}
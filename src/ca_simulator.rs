@@ -1,11 +1,11 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use bevy::math::{IVec2, Vec2};
+use bevy::math::{IVec2, UVec2, Vec2};
 use vulkano::{
     buffer::{BufferUsage, CpuAccessibleBuffer, DeviceLocalBuffer},
     command_buffer::{
-        AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer,
-        PrimaryCommandBuffer,
+        AutoCommandBufferBuilder, BufferCopy, CommandBufferUsage, CopyBufferInfo, FillBufferInfo,
+        PrimaryAutoCommandBuffer, PrimaryCommandBuffer,
     },
     descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
     device::Queue,
@@ -18,8 +18,23 @@ use vulkano::{
 use vulkano_util::renderer::DeviceImageView;
 
 use crate::{
-    matter::{MatterDefinition, MATTER_EMPTY, MatterId, MatterState},
-    utils::{create_compute_pipeline, storage_buffer_desc, storage_image_desc},
+    brush::{Brush, BrushMode},
+    conservation::{
+        population_changes, violating_tiles, ConservationViolation, MovementPass,
+        MAX_CONSERVATION_VIOLATIONS,
+    },
+    debug_view::DebugView,
+    draw_queue::{draw_buffer, DrawPrimitive, DRAW_HEADER_GPU_WORDS},
+    flood_fill::{cell_bounds, flood_fill_cells, variate_color, FilledRegion, FloodFill},
+    matter::{
+        MatterDefinition, MatterId, MatterRegistry, MatterState, MATTER_DEFINITION_GPU_WORDS,
+        MATTER_EMPTY, MAX_MATTERS,
+    },
+    population::Population,
+    selection::{CellRegion, PasteMode},
+    shapes::{Shape, ShapeStyle},
+    undo::RegionSnapshot,
+    utils::{clamp_to_canvas, create_compute_pipeline, storage_buffer_desc, storage_image_desc},
     CANVAS_SIZE_X, CANVAS_SIZE_Y, LOCAL_SIZE_X, LOCAL_SIZE_Y, NUM_WORK_GROUPS_X, NUM_WORK_GROUPS_Y,
 };
 
@@ -31,33 +46,116 @@ fn device_grid(
     DeviceLocalBuffer::array(
         compute_queue.device().clone(),
         (width * height) as DeviceSize,
-        BufferUsage::storage_buffer() | BufferUsage::transfer_dst(),
+        BufferUsage::storage_buffer() | BufferUsage::transfer_src() | BufferUsage::transfer_dst(),
         compute_queue.device().active_queue_families(),
     )
     .unwrap()
 }
 
+/// Definitions buffer indexed by matter id. Ids without a definition behave like empty.
+fn definitions_buffer(
+    compute_queue: &Arc<Queue>,
+    definitions: &[MatterDefinition],
+) -> Arc<CpuAccessibleBuffer<[u32]>> {
+    let mut words = vec![0; MAX_MATTERS * MATTER_DEFINITION_GPU_WORDS];
+    for definition in definitions {
+        let offset = definition.id.0 as usize * MATTER_DEFINITION_GPU_WORDS;
+        words[offset..offset + MATTER_DEFINITION_GPU_WORDS]
+            .copy_from_slice(&definition.to_gpu_words());
+    }
+    CpuAccessibleBuffer::from_iter(
+        compute_queue.device().clone(),
+        BufferUsage::storage_buffer(),
+        false,
+        words,
+    )
+    .unwrap()
+}
+
+/// Draw buffer for the given words
+fn draws_buffer(compute_queue: &Arc<Queue>, words: Vec<u32>) -> Arc<CpuAccessibleBuffer<[u32]>> {
+    CpuAccessibleBuffer::from_iter(
+        compute_queue.device().clone(),
+        BufferUsage::storage_buffer(),
+        false,
+        words,
+    )
+    .unwrap()
+}
+
+/// Population counts indexed by matter id
+fn population_buffer(compute_queue: &Arc<Queue>) -> Arc<CpuAccessibleBuffer<[u32]>> {
+    CpuAccessibleBuffer::from_iter(
+        compute_queue.device().clone(),
+        BufferUsage::storage_buffer() | BufferUsage::transfer_dst(),
+        true,
+        vec![0; MAX_MATTERS],
+    )
+    .unwrap()
+}
+
+/// Index of a cell in the grid
+fn grid_index(pos: IVec2) -> usize {
+    (pos.y as u32 * CANVAS_SIZE_X + pos.x as u32) as usize
+}
+
+/// Position of a cell index in the grid
+fn grid_pos(index: usize) -> IVec2 {
+    IVec2::new(
+        (index as u32 % CANVAS_SIZE_X) as i32,
+        (index as u32 / CANVAS_SIZE_X) as i32,
+    )
+}
+
+/// A copy of the grid kept on the gpu, used to rewind the simulation
+pub struct GridSnapshot {
+    pub sim_step: u32,
+    move_step: u32,
+    grid: Arc<DeviceLocalBuffer<[u32]>>,
+}
+
 /// Cellular automata simulation pipeline
 pub struct CASimulator {
     compute_queue: Arc<Queue>,
+    react_pipeline: Arc<ComputePipeline>,
     fall_pipeline: Arc<ComputePipeline>,
+    rise_pipeline: Arc<ComputePipeline>,
     slide_pipeline: Arc<ComputePipeline>,
     color_pipeline: Arc<ComputePipeline>,
     draw_matter_pipeline: Arc<ComputePipeline>,
-    query_matter_pipeline: Arc<ComputePipeline>,
+    population_pipeline: Arc<ComputePipeline>,
     // Shader matter inputs
     matter_in: Arc<DeviceLocalBuffer<[u32]>>,
     matter_out: Arc<DeviceLocalBuffer<[u32]>>,
-    query_matter: Arc<CpuAccessibleBuffer<[u32]>>,
+    /// Grid at the start of the step, for debug views that compare against it
+    previous_grid: Arc<DeviceLocalBuffer<[u32]>>,
+    matter_definitions: Arc<CpuAccessibleBuffer<[u32]>>,
+    /// Bound in place of queued draws for kernels other than draw
+    no_draws: Arc<CpuAccessibleBuffer<[u32]>>,
     image: DeviceImageView,
+    /// Light emitted by glowing matter, written with the canvas image
+    emission_image: DeviceImageView,
+    registry: MatterRegistry,
+    /// Draws waiting for the next time the grid is touched. Behind a mutex so that reads through a
+    /// shared reference can flush them.
+    pending_draws: Mutex<Vec<DrawPrimitive>>,
+    /// Counts of the population counted with the last step it was requested for
+    population_counts: Arc<CpuAccessibleBuffer<[u32]>>,
+    /// Region (min & size) to count the population of with the next step
+    population_request: Option<(IVec2, UVec2)>,
+    /// Step & region of the counts not yet taken
+    population_pending: Option<(u32, IVec2, UVec2)>,
+    /// Step movement passes one by one & check that each keeps the number of cells of every matter
+    pub check_conservation: bool,
+    /// Passes found to create or destroy matter, most recent last
+    pub conservation_violations: Vec<ConservationViolation>,
+    /// Grid before the checked pass, kept to reuse its memory
+    check_snapshot: Option<GridSnapshot>,
+    /// What the color kernel shows
+    pub debug_view: DebugView,
     //... push constants
     pub sim_step: u32,
     move_step: u32,
-    draw_radius: f32,
-    draw_matter: MatterDefinition,
-    draw_pos_start: Vec2,
-    draw_pos_end: Vec2,
-    query_pos: IVec2,
 }
 
 impl CASimulator {
@@ -69,13 +167,11 @@ impl CASimulator {
         assert_eq!(CANVAS_SIZE_Y % LOCAL_SIZE_Y, 0);
         let matter_in = device_grid(&compute_queue, CANVAS_SIZE_X, CANVAS_SIZE_Y);
         let matter_out = device_grid(&compute_queue, CANVAS_SIZE_X, CANVAS_SIZE_Y);
-        let query_matter = CpuAccessibleBuffer::from_iter(
-            compute_queue.device().clone(),
-            BufferUsage::storage_buffer() | BufferUsage::transfer_dst(),
-            false,
-            vec![MATTER_EMPTY.to_matter_with_color()],
-        )
-        .unwrap();
+        let previous_grid = device_grid(&compute_queue, CANVAS_SIZE_X, CANVAS_SIZE_Y);
+        let registry = MatterRegistry::builtin();
+        let matter_definitions = definitions_buffer(&compute_queue, registry.definitions());
+        let no_draws = draws_buffer(&compute_queue, vec![0; DRAW_HEADER_GPU_WORDS]);
+        let population_counts = population_buffer(&compute_queue);
 
         // Assumes all shaders that are loaded with specialication constants have the same constants
         let spec_const = fall_empty_cs::SpecializationConstants {
@@ -96,26 +192,36 @@ impl CASimulator {
 
         // Create pipelines
         let (
+            react_pipeline,
             fall_pipeline,
+            rise_pipeline,
             slide_pipeline,
             color_pipeline,
             draw_matter_pipeline,
-            query_matter_pipeline,
         ) = {
+            let react_shader = react_cs::load(compute_queue.device().clone()).unwrap();
             let fall_shader = fall_empty_cs::load(compute_queue.device().clone()).unwrap();
+            let rise_shader = rise_empty_cs::load(compute_queue.device().clone()).unwrap();
             let slide_shader = slide_down_empty_cs::load(compute_queue.device().clone()).unwrap();
             let color_shader = color_cs::load(compute_queue.device().clone()).unwrap();
             let draw_matter_shader = draw_matter_cs::load(compute_queue.device().clone()).unwrap();
-            let query_matter_shader =
-                query_matter_cs::load(compute_queue.device().clone()).unwrap();
             // This must match the shader & inputs in dispatch
             let descriptor_layout = [
                 (0, storage_buffer_desc()),
                 (1, storage_buffer_desc()),
                 (2, storage_image_desc()),
                 (3, storage_buffer_desc()),
+                (4, storage_buffer_desc()),
+                (5, storage_buffer_desc()),
+                (6, storage_image_desc()),
             ];
             (
+                create_compute_pipeline(
+                    compute_queue.clone(),
+                    react_shader.entry_point("main").unwrap(),
+                    descriptor_layout.to_vec(),
+                    &spec_const,
+                ),
                 create_compute_pipeline(
                     compute_queue.clone(),
                     fall_shader.entry_point("main").unwrap(),
@@ -124,30 +230,44 @@ impl CASimulator {
                 ),
                 create_compute_pipeline(
                     compute_queue.clone(),
-                    slide_shader.entry_point("main").unwrap(),
+                    rise_shader.entry_point("main").unwrap(),
                     descriptor_layout.to_vec(),
                     &spec_const,
                 ),
                 create_compute_pipeline(
                     compute_queue.clone(),
-                    color_shader.entry_point("main").unwrap(),
+                    slide_shader.entry_point("main").unwrap(),
                     descriptor_layout.to_vec(),
                     &spec_const,
                 ),
                 create_compute_pipeline(
                     compute_queue.clone(),
-                    draw_matter_shader.entry_point("main").unwrap(),
+                    color_shader.entry_point("main").unwrap(),
                     descriptor_layout.to_vec(),
                     &spec_const,
                 ),
                 create_compute_pipeline(
                     compute_queue.clone(),
-                    query_matter_shader.entry_point("main").unwrap(),
+                    draw_matter_shader.entry_point("main").unwrap(),
                     descriptor_layout.to_vec(),
                     &spec_const,
                 ),
             )
         };
+        let population_pipeline = {
+            let population_shader = population_cs::load(compute_queue.device().clone()).unwrap();
+            let spec_const = population_cs::SpecializationConstants {
+                canvas_size_x: CANVAS_SIZE_X as i32,
+                constant_1: LOCAL_SIZE_X,
+                constant_2: LOCAL_SIZE_Y,
+            };
+            create_compute_pipeline(
+                compute_queue.clone(),
+                population_shader.entry_point("main").unwrap(),
+                vec![(0, storage_buffer_desc()), (1, storage_buffer_desc())],
+                &spec_const,
+            )
+        };
         // Create color image
         let image = StorageImage::general_purpose_image_view(
             compute_queue.clone(),
@@ -161,24 +281,44 @@ impl CASimulator {
             },
         )
         .unwrap();
+        // Emission can be brighter than the canvas colors
+        let emission_image = StorageImage::general_purpose_image_view(
+            compute_queue.clone(),
+            [CANVAS_SIZE_X, CANVAS_SIZE_Y],
+            Format::R16G16B16A16_SFLOAT,
+            ImageUsage {
+                storage: true,
+                ..ImageUsage::none()
+            },
+        )
+        .unwrap();
         CASimulator {
             compute_queue,
+            react_pipeline,
             fall_pipeline,
+            rise_pipeline,
             slide_pipeline,
             color_pipeline,
             draw_matter_pipeline,
-            query_matter_pipeline,
+            population_pipeline,
             matter_in,
             matter_out,
-            query_matter,
+            previous_grid,
+            matter_definitions,
+            no_draws,
             image,
+            emission_image,
+            registry,
+            pending_draws: Mutex::new(vec![]),
+            population_counts,
+            population_request: None,
+            population_pending: None,
+            check_conservation: false,
+            conservation_violations: vec![],
+            check_snapshot: None,
+            debug_view: DebugView::default(),
             sim_step: 0,
             move_step: 0,
-            draw_radius: 0.0,
-            draw_matter: MatterDefinition::zero(),
-            draw_pos_start: Vec2::new(0.0, 0.0),
-            draw_pos_end: Vec2::new(0.0, 0.0),
-            query_pos: IVec2::new(0, 0),
         }
     }
 
@@ -187,6 +327,50 @@ impl CASimulator {
         self.image.clone()
     }
 
+    /// Get emission image for bloom when rendering
+    pub fn emission_image(&self) -> DeviceImageView {
+        self.emission_image.clone()
+    }
+
+    /// Replace the matters we simulate. Cells already in the grid keep their ids and take on the new
+    /// definitions. Cells of matters that the new registry doesn't have under the same id are emptied.
+    pub fn set_registry(&mut self, registry: MatterRegistry) {
+        let removed = self
+            .registry
+            .iter()
+            .filter(|&(id, name)| registry.id(name) != Some(id))
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        self.matter_definitions = definitions_buffer(&self.compute_queue, registry.definitions());
+        self.registry = registry;
+        if !removed.is_empty() {
+            let empty = self
+                .registry
+                .definition(MatterId::EMPTY)
+                .to_matter_with_color();
+            let mut grid = self.read_grid();
+            let mut changed = false;
+            for cell in grid.iter_mut() {
+                if removed.contains(&MatterDefinition::get_id_from_u32(*cell)) {
+                    *cell = empty;
+                    changed = true;
+                }
+            }
+            if changed {
+                self.write_region(IVec2::ZERO, UVec2::new(CANVAS_SIZE_X, CANVAS_SIZE_Y), &grid);
+            }
+        }
+    }
+
+    /// Give a matter a new name, keeping its id
+    pub fn rename_matter(&mut self, from: &str, to: &str) -> bool {
+        self.registry.rename(from, to)
+    }
+
+    pub fn registry(&self) -> &MatterRegistry {
+        &self.registry
+    }
+
     /// Are we within simulation bounds?
     fn is_inside(&self, pos: IVec2) -> bool {
         pos.x >= 0 && pos.x < CANVAS_SIZE_X as i32 && pos.y >= 0 && pos.y < CANVAS_SIZE_Y as i32
@@ -214,61 +398,436 @@ impl CASimulator {
         }
     }
 
-    /// Query matter at pos
-    pub fn query_matter(&mut self, pos: IVec2) -> Option<MatterId> {
+    /// Query matter at pos. Copies only that cell back to the cpu.
+    pub fn query_matter(&self, pos: IVec2) -> Option<MatterId> {
         if self.is_inside(pos) {
-            self.query_pos = pos;
-            // Build command buffer
-            let mut command_buffer_builder = self.command_buffer_builder();
-
-            // Dispatch
-            self.dispatch(
-                &mut command_buffer_builder,
-                self.query_matter_pipeline.clone(),
-                false,
-            );
-
-            // Execute & finish (wait)
-            self.execute(command_buffer_builder, true);
-
-            // Read result
-            let query_matter = self.query_matter.read().unwrap();
-            Some(MatterDefinition::get_id_from_u32(query_matter[0]))
+            let cell = self.read_region(pos, UVec2::ONE)[0];
+            Some(MatterDefinition::get_id_from_u32(cell))
         } else {
             None
         }
     }
 
-    /// Draw matter line with given radius
-    pub fn draw_matter(&mut self, start: Vec2, end: Vec2, radius: f32, matter: MatterId) {
-        // Update our variables to be used as push constants
-        self.draw_pos_start = start;
-        self.draw_pos_end = end;
-        self.draw_matter = MatterDefinition::new(matter);
-        self.draw_radius = radius;
+    /// Read the whole grid back to the cpu (row by row, packed matter & color)
+    pub fn read_grid(&self) -> Vec<u32> {
+        self.read_region(IVec2::ZERO, UVec2::new(CANVAS_SIZE_X, CANVAS_SIZE_Y))
+    }
+
+    /// Buffer copy regions (in bytes) between a grid region and a tightly packed region buffer
+    fn region_copies(min: IVec2, size: UVec2, to_grid: bool) -> Vec<BufferCopy> {
+        let cell_size = std::mem::size_of::<u32>() as DeviceSize;
+        // Full rows are contiguous in the grid
+        let (rows, row_len) = if size.x == CANVAS_SIZE_X {
+            (1, size.x * size.y)
+        } else {
+            (size.y, size.x)
+        };
+        (0..rows)
+            .map(|row| {
+                let grid_offset =
+                    ((min.y as u32 + row) * CANVAS_SIZE_X + min.x as u32) as DeviceSize;
+                let region_offset = (row * row_len) as DeviceSize;
+                let (src_offset, dst_offset) = if to_grid {
+                    (region_offset, grid_offset)
+                } else {
+                    (grid_offset, region_offset)
+                };
+                BufferCopy {
+                    src_offset: src_offset * cell_size,
+                    dst_offset: dst_offset * cell_size,
+                    size: row_len as DeviceSize * cell_size,
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+
+    /// Read a rectangular region of the grid back to the cpu (row by row, packed matter & color).
+    /// The region must be inside the canvas.
+    pub fn read_region(&self, min: IVec2, size: UVec2) -> Vec<u32> {
+        self.read_buffer_region(self.matter_in.clone(), min, size)
+    }
+
+    /// Read the grid of a snapshot back to the cpu
+    pub fn read_snapshot(&self, snapshot: &GridSnapshot) -> Vec<u32> {
+        let size = UVec2::new(CANVAS_SIZE_X, CANVAS_SIZE_Y);
+        self.read_buffer_region(snapshot.grid.clone(), IVec2::ZERO, size)
+    }
 
-        // Build command buffer
+    /// Read a rectangular region of a grid buffer back to the cpu
+    fn read_buffer_region(
+        &self,
+        grid: Arc<DeviceLocalBuffer<[u32]>>,
+        min: IVec2,
+        size: UVec2,
+    ) -> Vec<u32> {
+        assert!(self.is_inside(min) && self.is_inside(min + size.as_ivec2() - IVec2::ONE));
+        let readback = unsafe {
+            CpuAccessibleBuffer::<[u32]>::uninitialized_array(
+                self.compute_queue.device().clone(),
+                (size.x * size.y) as DeviceSize,
+                BufferUsage::transfer_dst(),
+                true,
+            )
+        }
+        .unwrap();
         let mut command_buffer_builder = self.command_buffer_builder();
+        self.record_draws(&mut command_buffer_builder);
+        command_buffer_builder
+            .copy_buffer(CopyBufferInfo {
+                regions: Self::region_copies(min, size, false).into_iter().collect(),
+                ..CopyBufferInfo::buffers(grid, readback.clone())
+            })
+            .unwrap();
+        // Execute & finish (wait)
+        self.execute(command_buffer_builder, true);
+        let cells = readback.read().unwrap().to_vec();
+        cells
+    }
 
-        // Dispatch
-        self.dispatch(
-            &mut command_buffer_builder,
-            self.draw_matter_pipeline.clone(),
+    /// Write a rectangular region of cells (row by row, packed matter & color) to the grid.
+    /// The region must be inside the canvas.
+    pub fn write_region(&mut self, min: IVec2, size: UVec2, cells: &[u32]) {
+        assert!(self.is_inside(min) && self.is_inside(min + size.as_ivec2() - IVec2::ONE));
+        assert_eq!(cells.len(), (size.x * size.y) as usize);
+        let upload = CpuAccessibleBuffer::from_iter(
+            self.compute_queue.device().clone(),
+            BufferUsage::transfer_src(),
             false,
-        );
+            cells.iter().copied(),
+        )
+        .unwrap();
+        let mut command_buffer_builder = self.command_buffer_builder();
+        self.record_draws(&mut command_buffer_builder);
+        command_buffer_builder
+            .copy_buffer(CopyBufferInfo {
+                regions: Self::region_copies(min, size, true).into_iter().collect(),
+                ..CopyBufferInfo::buffers(upload, self.matter_in.clone())
+            })
+            .unwrap();
+        // Execute & finish (no need to wait)
+        self.execute(command_buffer_builder, false);
+    }
+
+    /// Empty a rectangular region of the grid. The region must be inside the canvas.
+    pub fn clear_region(&mut self, min: IVec2, size: UVec2) {
+        let empty = self
+            .registry
+            .definition(MatterId::EMPTY)
+            .to_matter_with_color();
+        self.write_region(min, size, &vec![empty; (size.x * size.y) as usize]);
+    }
+
+    /// Write a block of cells with its min corner at pos, cells outside the canvas are dropped
+    pub fn paste_region(&mut self, pos: IVec2, region: &CellRegion, mode: PasteMode) {
+        let max = pos + region.size().as_ivec2() - IVec2::ONE;
+        let (min, size) = match clamp_to_canvas(pos, max) {
+            Some(clamped) => clamped,
+            None => return,
+        };
+        let mut cells = self.read_region(min, size);
+        for y in 0..size.y as i32 {
+            for x in 0..size.x as i32 {
+                let i = (y as u32 * size.x + x as u32) as usize;
+                let is_empty = MatterDefinition::get_id_from_u32(cells[i]) == MatterId::EMPTY;
+                if mode == PasteMode::Over || is_empty {
+                    cells[i] = region.cell(min - pos + IVec2::new(x, y));
+                }
+            }
+        }
+        self.write_region(min, size, &cells);
+    }
+
+    /// Copy the grid to a snapshot on the gpu. An old snapshot can be given to reuse its memory.
+    pub fn snapshot(&self, reuse: Option<GridSnapshot>) -> GridSnapshot {
+        let grid = match reuse {
+            Some(snapshot) => snapshot.grid,
+            None => device_grid(&self.compute_queue, CANVAS_SIZE_X, CANVAS_SIZE_Y),
+        };
+        let mut command_buffer_builder = self.command_buffer_builder();
+        self.record_draws(&mut command_buffer_builder);
+        command_buffer_builder
+            .copy_buffer(CopyBufferInfo::buffers(self.matter_in.clone(), grid.clone()))
+            .unwrap();
+        // Execute & finish (no need to wait)
+        self.execute(command_buffer_builder, false);
+        GridSnapshot {
+            sim_step: self.sim_step,
+            move_step: self.move_step,
+            grid,
+        }
+    }
+
+    /// Restore the step counters from a snapshot, leaving the grid as it is
+    pub fn restore_steps(&mut self, snapshot: &GridSnapshot) {
+        self.set_step_counters(snapshot.sim_step, snapshot.move_step);
+    }
+
+    /// Sim step & move step, the move step decides the order of sliding directions
+    pub fn step_counters(&self) -> (u32, u32) {
+        (self.sim_step, self.move_step)
+    }
+
+    pub fn set_step_counters(&mut self, sim_step: u32, move_step: u32) {
+        self.sim_step = sim_step;
+        self.move_step = move_step;
+    }
 
+    /// Restore the grid and step counters from a snapshot
+    pub fn restore(&mut self, snapshot: &GridSnapshot) {
+        // Draws queued before the restore would be overwritten anyway
+        self.pending_draws.get_mut().unwrap().clear();
+        let mut command_buffer_builder = self.command_buffer_builder();
+        command_buffer_builder
+            .copy_buffer(CopyBufferInfo::buffers(
+                snapshot.grid.clone(),
+                self.matter_in.clone(),
+            ))
+            .unwrap();
         // Execute & finish (no need to wait)
         self.execute(command_buffer_builder, false);
+        self.restore_steps(snapshot);
+    }
+
+    /// Draw matter line with given radius, brush shape & mode. The line is queued and drawn together
+    /// with other queued lines in a single dispatch the next time the grid is stepped, read or written.
+    pub fn draw_matter(
+        &mut self,
+        start: Vec2,
+        end: Vec2,
+        radius: f32,
+        matter: MatterId,
+        brush: Brush,
+    ) {
+        let matter = self.registry.definition(matter).to_matter_with_color();
+        self.pending_draws.get_mut().unwrap().push(DrawPrimitive {
+            start,
+            end,
+            radius,
+            matter,
+            brush,
+        });
+    }
+
+    /// Append a dispatch of all queued draws to our command buffer. The dispatch only covers the
+    /// area of the draws.
+    fn record_draws(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        let primitives = std::mem::take(&mut *self.pending_draws.lock().unwrap());
+        let (words, _, size) = match draw_buffer(&primitives) {
+            Some(buffer) => buffer,
+            None => return,
+        };
+        let draws = draws_buffer(&self.compute_queue, words);
+        let groups = [
+            (size.x + LOCAL_SIZE_X - 1) / LOCAL_SIZE_X,
+            (size.y + LOCAL_SIZE_Y - 1) / LOCAL_SIZE_Y,
+            1,
+        ];
+        self.record_dispatch(builder, self.draw_matter_pipeline.clone(), draws, groups);
+    }
+
+    /// Compute a flood fill at pos on the cpu without applying it. None if there is nothing to fill,
+    /// e.g. when the region already is of matter.
+    pub fn plan_flood_fill(
+        &self,
+        pos: IVec2,
+        matter: MatterId,
+        fill: &FloodFill,
+    ) -> Option<FilledRegion> {
+        if !self.is_inside(pos) {
+            return None;
+        }
+        let mut grid = self.read_grid();
+        if MatterDefinition::get_id_from_u32(grid[grid_index(pos)]) == matter {
+            return None;
+        }
+        let cells = flood_fill_cells(&grid, CANVAS_SIZE_X, pos, fill);
+        let (min, max) = cell_bounds(&cells, CANVAS_SIZE_X)?;
+        let size = (max - min + IVec2::ONE).as_uvec2();
+        let region = |grid: &[u32]| {
+            (0..size.y)
+                .flat_map(|y| {
+                    let start = grid_index(min + IVec2::new(0, y as i32));
+                    grid[start..start + size.x as usize].iter().copied()
+                })
+                .collect::<Vec<_>>()
+        };
+        let before = RegionSnapshot {
+            min,
+            size,
+            cells: region(&grid),
+        };
+        let matter_with_color = self.registry.definition(matter).to_matter_with_color();
+        for &i in &cells {
+            grid[i] = if matter == MatterId::EMPTY {
+                matter_with_color
+            } else {
+                variate_color(grid_pos(i), matter_with_color)
+            };
+        }
+        Some(FilledRegion {
+            before,
+            after: region(&grid),
+        })
+    }
+
+    /// Replace the connected region of same matter cells at pos with matter. Done on the cpu, only
+    /// the bounding box of the region is written back.
+    pub fn flood_fill(&mut self, pos: IVec2, matter: MatterId, fill: &FloodFill) {
+        if let Some(filled) = self.plan_flood_fill(pos, matter, fill) {
+            self.apply_flood_fill(&filled);
+        }
+    }
+
+    /// Write a planned flood fill to the grid
+    pub fn apply_flood_fill(&mut self, filled: &FilledRegion) {
+        self.write_region(filled.before.min, filled.before.size, &filled.after);
+    }
+
+    /// Draw a geometric shape of matter. Rasterized on the cpu, only the bounding box of the shape
+    /// is read & written back.
+    pub fn draw_shape(
+        &mut self,
+        shape: &Shape,
+        style: &ShapeStyle,
+        matter: MatterId,
+        mode: BrushMode,
+    ) {
+        let (min, max) = shape.bounds(style);
+        let (min, size) = match clamp_to_canvas(min, max) {
+            Some(region) => region,
+            None => return,
+        };
+        let mut cells = self.read_region(min, size);
+        let matter = mode.drawn_matter(matter);
+        let matter_with_color = self.registry.definition(matter).to_matter_with_color();
+        for pos in shape.rasterize(style) {
+            let offset = pos - min;
+            let i = (offset.y as u32 * size.x + offset.x as u32) as usize;
+            if !mode.allows(MatterDefinition::get_id_from_u32(cells[i])) {
+                continue;
+            }
+            cells[i] = if matter == MatterId::EMPTY {
+                matter_with_color
+            } else {
+                variate_color(pos, matter_with_color)
+            };
+        }
+        self.write_region(min, size, &cells);
+    }
+
+    /// Append a count of the cells of each matter in a region to our command buffer
+    fn record_population(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        counts: Arc<CpuAccessibleBuffer<[u32]>>,
+        min: IVec2,
+        size: UVec2,
+    ) {
+        assert!(self.is_inside(min) && self.is_inside(min + size.as_ivec2() - IVec2::ONE));
+        builder
+            .fill_buffer(FillBufferInfo::dst_buffer(counts.clone()))
+            .unwrap();
+        let pipeline = self.population_pipeline.clone();
+        let pipeline_layout = pipeline.layout();
+        let desc_layout = pipeline_layout.set_layouts().get(0).unwrap();
+        let set = PersistentDescriptorSet::new(desc_layout.clone(), [
+            WriteDescriptorSet::buffer(0, self.matter_in.clone()),
+            WriteDescriptorSet::buffer(1, counts),
+        ])
+        .unwrap();
+        let push_constants = population_cs::ty::PushConstants {
+            region_min: min.into(),
+            region_max: (min + size.as_ivec2() - IVec2::ONE).into(),
+        };
+        builder
+            .bind_pipeline_compute(pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, pipeline_layout.clone(), 0, set)
+            .push_constants(pipeline_layout.clone(), 0, push_constants)
+            .dispatch([
+                (size.x + LOCAL_SIZE_X - 1) / LOCAL_SIZE_X,
+                (size.y + LOCAL_SIZE_Y - 1) / LOCAL_SIZE_Y,
+                1,
+            ])
+            .unwrap();
+    }
+
+    /// Count the cells of each matter in a region (min & size) along with the next step. The counts
+    /// are read back with `take_population` after the step, counting adds no command buffer or wait
+    /// of its own.
+    pub fn request_population(&mut self, min: IVec2, size: UVec2) {
+        self.population_request = Some((min, size));
+    }
+
+    /// Population counted with an earlier step, none if there is no new count. Steps finish before
+    /// they return, so the counts are ready.
+    pub fn take_population(&mut self) -> Option<Population> {
+        let (sim_step, min, size) = self.population_pending.take()?;
+        let counts = self.population_counts.read().unwrap().to_vec();
+        Some(Population {
+            sim_step,
+            min,
+            size,
+            counts,
+        })
+    }
+
+    /// Count the cells of each matter in a region (min & size) now, waiting for the result
+    pub fn count_population(&self, min: IVec2, size: UVec2) -> Population {
+        let counts = population_buffer(&self.compute_queue);
+        let mut command_buffer_builder = self.command_buffer_builder();
+        self.record_draws(&mut command_buffer_builder);
+        self.record_population(&mut command_buffer_builder, counts.clone(), min, size);
+        // Execute & finish (wait)
+        self.execute(command_buffer_builder, true);
+        let counts = counts.read().unwrap().to_vec();
+        Population {
+            sim_step: self.sim_step,
+            min,
+            size,
+            counts,
+        }
     }
 
     /// Step simulation
     pub fn step(&mut self, move_steps: u32, is_paused: bool) {
         let mut command_buffer_builder = self.command_buffer_builder();
+        self.record_draws(&mut command_buffer_builder);
+        if self.debug_view.needs_previous_grid() {
+            command_buffer_builder
+                .copy_buffer(CopyBufferInfo::buffers(
+                    self.matter_in.clone(),
+                    self.previous_grid.clone(),
+                ))
+                .unwrap();
+        }
 
         if !is_paused {
+            // Reactions happen once per step, before matter moves
+            self.dispatch(
+                &mut command_buffer_builder,
+                self.react_pipeline.clone(),
+                true,
+            );
+            let passes = [
+                (MovementPass::Fall, self.fall_pipeline.clone()),
+                (MovementPass::Rise, self.rise_pipeline.clone()),
+                (MovementPass::Slide, self.slide_pipeline.clone()),
+            ];
             for _ in 0..move_steps {
-                self.step_movement(&mut command_buffer_builder, self.fall_pipeline.clone());
-                self.step_movement(&mut command_buffer_builder, self.slide_pipeline.clone());
+                for (pass, pipeline) in passes.iter().cloned() {
+                    if self.check_conservation {
+                        // Run what we have so far, then the pass on its own
+                        let builder = std::mem::replace(
+                            &mut command_buffer_builder,
+                            self.command_buffer_builder(),
+                        );
+                        self.execute(builder, false);
+                        self.check_movement(pass, pipeline);
+                    } else {
+                        self.step_movement(&mut command_buffer_builder, pipeline);
+                    }
+                }
             }
         }
 
@@ -279,10 +838,34 @@ impl CASimulator {
             false,
         );
 
+        // Count population of the stepped grid, read back later
+        let population_request = self.population_request.take();
+        if let Some((min, size)) = population_request {
+            let counts = self.population_counts.clone();
+            self.record_population(&mut command_buffer_builder, counts, min, size);
+        }
+
         // Execute & finish (no need to wait)
         self.execute(command_buffer_builder, false);
 
         self.sim_step += 1;
+        if let Some((min, size)) = population_request {
+            self.population_pending = Some((self.sim_step, min, size));
+        }
+    }
+
+    /// Color the canvas & emission images from the grid without stepping, e.g. before rendering a
+    /// screenshot
+    pub fn color(&mut self) {
+        let mut command_buffer_builder = self.command_buffer_builder();
+        self.record_draws(&mut command_buffer_builder);
+        self.dispatch(
+            &mut command_buffer_builder,
+            self.color_pipeline.clone(),
+            false,
+        );
+        // Execute & finish (wait)
+        self.execute(command_buffer_builder, true);
     }
 
     /// Step a movement pipeline. move_step affects the order of sliding direction
@@ -295,12 +878,68 @@ impl CASimulator {
         self.move_step += 1;
     }
 
+    /// Step a movement pipeline on its own & record a violation if it changed the number of cells of
+    /// any matter
+    fn check_movement(&mut self, pass: MovementPass, pipeline: Arc<ComputePipeline>) {
+        let canvas = UVec2::new(CANVAS_SIZE_X, CANVAS_SIZE_Y);
+        let snapshot = self.snapshot(self.check_snapshot.take());
+        let before = self.count_population(IVec2::ZERO, canvas);
+        let mut command_buffer_builder = self.command_buffer_builder();
+        self.step_movement(&mut command_buffer_builder, pipeline);
+        self.execute(command_buffer_builder, false);
+        let after = self.count_population(IVec2::ZERO, canvas);
+        let changes = population_changes(&before.counts, &after.counts);
+        if !changes.is_empty() {
+            let matters = changes.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+            let regions = violating_tiles(
+                &self.read_snapshot(&snapshot),
+                &self.read_grid(),
+                CANVAS_SIZE_X,
+                CANVAS_SIZE_Y,
+                &matters,
+            );
+            let violation = ConservationViolation {
+                sim_step: self.sim_step,
+                pass,
+                changes,
+                regions,
+            };
+            bevy::log::warn!("Mass conservation broken: {:?}", violation);
+            self.conservation_violations.push(violation);
+            if self.conservation_violations.len() > MAX_CONSERVATION_VIOLATIONS {
+                self.conservation_violations.remove(0);
+            }
+        }
+        self.check_snapshot = Some(snapshot);
+    }
+
     /// Append a pipeline dispatch to our command buffer
     fn dispatch(
         &mut self,
         builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
         pipeline: Arc<ComputePipeline>,
         swap: bool,
+    ) {
+        self.record_dispatch(
+            builder,
+            pipeline,
+            self.no_draws.clone(),
+            [NUM_WORK_GROUPS_X, NUM_WORK_GROUPS_Y, 1],
+        );
+
+        // Double buffering: Swap input and output so the output becomes the input for next frame
+        if swap {
+            std::mem::swap(&mut self.matter_in, &mut self.matter_out);
+        }
+    }
+
+    /// Append a pipeline dispatch of given work groups to our command buffer
+    fn record_dispatch(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        pipeline: Arc<ComputePipeline>,
+        draws: Arc<CpuAccessibleBuffer<[u32]>>,
+        groups: [u32; 3],
     ) {
         let pipeline_layout = pipeline.layout();
         let desc_layout = pipeline_layout.set_layouts().get(0).unwrap();
@@ -308,30 +947,31 @@ impl CASimulator {
             WriteDescriptorSet::buffer(0, self.matter_in.clone()),
             WriteDescriptorSet::buffer(1, self.matter_out.clone()),
             WriteDescriptorSet::image_view(2, self.image.clone()),
-            WriteDescriptorSet::buffer(3, self.query_matter.clone()),
+            WriteDescriptorSet::buffer(3, self.matter_definitions.clone()),
+            WriteDescriptorSet::buffer(4, draws),
+            WriteDescriptorSet::buffer(5, self.previous_grid.clone()),
+            WriteDescriptorSet::image_view(6, self.emission_image.clone()),
         ])
         .unwrap();
         // Assumes all shaders that are 'dispatched' have the same push constants
         let push_constants = fall_empty_cs::ty::PushConstants {
             sim_step: self.sim_step as u32,
             move_step: self.move_step as u32,
-            draw_pos_start: self.draw_pos_start.into(),
-            draw_pos_end: self.draw_pos_end.into(),
-            draw_radius: self.draw_radius,
-            draw_matter: self.draw_matter.to_matter_with_color(),
-            query_pos: self.query_pos.into(),
+            debug_view: self.debug_view as u32,
         };
         builder
             .bind_pipeline_compute(pipeline.clone())
             .bind_descriptor_sets(PipelineBindPoint::Compute, pipeline_layout.clone(), 0, set)
             .push_constants(pipeline_layout.clone(), 0, push_constants)
-            .dispatch([NUM_WORK_GROUPS_X, NUM_WORK_GROUPS_Y, 1])
+            .dispatch(groups)
             .unwrap();
+    }
+}
 
-        // Double buffering: Swap input and output so the output becomes the input for next frame
-        if swap {
-            std::mem::swap(&mut self.matter_in, &mut self.matter_out);
-        }
+mod react_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "compute_shaders/react.glsl"
     }
 }
 
@@ -342,6 +982,13 @@ mod fall_empty_cs {
     }
 }
 
+mod rise_empty_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "compute_shaders/rise_empty.glsl"
+    }
+}
+
 mod slide_down_empty_cs {
     vulkano_shaders::shader! {
         ty: "compute",
@@ -363,10 +1010,10 @@ mod draw_matter_cs {
     }
 }
 
-mod query_matter_cs {
+mod population_cs {
     vulkano_shaders::shader! {
         ty: "compute",
-        path: "compute_shaders/query_matter.glsl"
+        path: "compute_shaders/population.glsl"
     }
 }
 
@@ -375,10 +1022,17 @@ mod query_matter_cs {
 // you'll want to be doing more unit testing...
 #[cfg(test)]
 mod tests {
-    use bevy::math::IVec2;
+    use bevy::math::{IVec2, UVec2, Vec2};
     use vulkano_util::context::VulkanoContext;
 
-    use crate::{ca_simulator::CASimulator, matter::MatterId};
+    use crate::{
+        brush::{Brush, BrushMode, BrushShape},
+        ca_simulator::CASimulator,
+        matter::{MatterDefinition, MatterId, MatterPack, MatterState},
+        rewind::History,
+        selection::{CellRegion, PasteMode},
+        CANVAS_SIZE_X, CANVAS_SIZE_Y,
+    };
 
     fn test_setup() -> (VulkanoContext, CASimulator) {
         // Create vulkano context
@@ -393,18 +1047,475 @@ mod tests {
         let (_ctx, mut simulator) = test_setup();
         let pos = IVec2::new(10, 10);
         // Empty matter first
-        assert_eq!(simulator.query_matter(pos), Some(MatterId::Empty));
-        simulator.draw_matter(pos.as_vec2(), pos.as_vec2(), 0.5, MatterId::Sand);
+        assert_eq!(simulator.query_matter(pos), Some(MatterId::EMPTY));
+        simulator.draw_matter(pos.as_vec2(), pos.as_vec2(), 0.5, MatterId::SAND, Brush::default());
         // After drawing, We have Sand
-        assert_eq!(simulator.query_matter(pos), Some(MatterId::Sand));
+        assert_eq!(simulator.query_matter(pos), Some(MatterId::SAND));
+        // Regions read back what we write
+        let region = simulator.read_region(pos - IVec2::ONE, UVec2::new(3, 2));
+        assert_eq!(MatterDefinition::get_id_from_u32(region[4]), MatterId::SAND);
+        simulator.write_region(pos - IVec2::ONE, UVec2::new(3, 2), &region);
+        assert_eq!(simulator.read_region(pos - IVec2::ONE, UVec2::new(3, 2)), region);
         // Step once
         simulator.step(1, false);
         // Old position is empty
-        assert_eq!(simulator.query_matter(pos), Some(MatterId::Empty));
+        assert_eq!(simulator.query_matter(pos), Some(MatterId::EMPTY));
         // New position under has Sand
         assert_eq!(
             simulator.query_matter(pos + IVec2::new(0, -1)),
-            Some(MatterId::Sand)
+            Some(MatterId::SAND)
+        );
+    }
+
+    /// Count cells of matter in the grid
+    fn count_matter(simulator: &CASimulator, matter: MatterId) -> usize {
+        simulator
+            .read_grid()
+            .into_iter()
+            .filter(|&cell| MatterDefinition::get_id_from_u32(cell) == matter)
+            .count()
+    }
+
+    #[test]
+    fn test_acid_eats_rock() {
+        let (_ctx, mut simulator) = test_setup();
+        simulator.draw_matter(
+            Vec2::new(20.0, 20.0),
+            Vec2::new(80.0, 20.0),
+            5.0,
+            MatterId::ROCK,
+            Brush::default(),
+        );
+        simulator.draw_matter(
+            Vec2::new(30.0, 40.0),
+            Vec2::new(70.0, 40.0),
+            4.0,
+            MatterId::ACID,
+            Brush::default(),
         );
+        let rock = count_matter(&simulator, MatterId::ROCK);
+        let acid = count_matter(&simulator, MatterId::ACID);
+        for _ in 0..200 {
+            simulator.step(1, false);
+        }
+        // Both are used up
+        assert!(count_matter(&simulator, MatterId::ROCK) < rock);
+        assert!(count_matter(&simulator, MatterId::ACID) < acid);
+    }
+
+    #[test]
+    fn test_water_rusts_metal() {
+        let (_ctx, mut simulator) = test_setup();
+        simulator.draw_matter(
+            Vec2::new(20.0, 20.0),
+            Vec2::new(80.0, 20.0),
+            3.0,
+            MatterId::METAL,
+            Brush::default(),
+        );
+        simulator.draw_matter(
+            Vec2::new(20.0, 30.0),
+            Vec2::new(80.0, 30.0),
+            3.0,
+            MatterId::WATER,
+            Brush::default(),
+        );
+        assert_eq!(count_matter(&simulator, MatterId::RUST), 0);
+        for _ in 0..300 {
+            simulator.step(1, false);
+        }
+        assert!(count_matter(&simulator, MatterId::RUST) > 0);
+    }
+
+    #[test]
+    fn test_lava_boils_water() {
+        let (_ctx, mut simulator) = test_setup();
+        simulator.draw_matter(
+            Vec2::new(20.0, 10.0),
+            Vec2::new(80.0, 10.0),
+            3.0,
+            MatterId::WATER,
+            Brush::default(),
+        );
+        simulator.draw_matter(
+            Vec2::new(20.0, 17.0),
+            Vec2::new(80.0, 17.0),
+            3.0,
+            MatterId::LAVA,
+            Brush::default(),
+        );
+        for _ in 0..5 {
+            simulator.step(1, false);
+        }
+        assert!(count_matter(&simulator, MatterId::STEAM) > 0);
+        // Lava cools down to rock where it touches water
+        assert!(count_matter(&simulator, MatterId::ROCK) > 0);
+    }
+
+    /// Step a band of matter touching a band of another matter, if any. Gases are put below the
+    /// band so that they rise into it, other matters above.
+    fn touch_scenario(
+        ctx: &VulkanoContext,
+        matter: MatterId,
+        touching: Option<MatterId>,
+        steps: usize,
+    ) -> CASimulator {
+        let mut simulator = CASimulator::new(ctx.compute_queue());
+        let draw_band = |simulator: &mut CASimulator, y: f32, matter: MatterId| {
+            simulator.draw_matter(
+                Vec2::new(20.0, y),
+                Vec2::new(80.0, y),
+                3.0,
+                matter,
+                Brush::default(),
+            )
+        };
+        draw_band(&mut simulator, 20.0, matter);
+        if let Some(touching) = touching {
+            let is_gas = simulator.registry().definition(touching).state == MatterState::Gas;
+            draw_band(&mut simulator, if is_gas { 13.0 } else { 27.0 }, touching);
+        }
+        for _ in 0..steps {
+            simulator.step(1, false);
+        }
+        simulator
+    }
+
+    /// Matter touching the other turns into becomes within steps, more than it does on its own
+    fn assert_reacts(matter: MatterId, touching: MatterId, becomes: MatterId, steps: usize) {
+        let ctx = VulkanoContext::default();
+        let alone = touch_scenario(&ctx, matter, None, steps);
+        let touched = touch_scenario(&ctx, matter, Some(touching), steps);
+        let names = (
+            alone.registry().name(matter),
+            alone.registry().name(touching),
+        );
+        assert!(
+            count_matter(&touched, matter) < count_matter(&alone, matter),
+            "{} did not react to {}",
+            names.0,
+            names.1
+        );
+        // Emptied cells & cells that became the touching matter can't be told apart from the band
+        if becomes != touching && becomes != MatterId::EMPTY {
+            assert!(
+                count_matter(&touched, becomes) > count_matter(&alone, becomes),
+                "{} touching {} did not become {}",
+                names.0,
+                names.1,
+                alone.registry().name(becomes)
+            );
+        }
+    }
+
+    #[test]
+    fn test_acid_dissolves_sand() {
+        assert_reacts(MatterId::SAND, MatterId::ACID, MatterId::EMPTY, 50);
+    }
+
+    #[test]
+    fn test_lava_melts_sand() {
+        assert_reacts(MatterId::SAND, MatterId::LAVA, MatterId::LAVA, 50);
+    }
+
+    #[test]
+    fn test_ice_freezes_water() {
+        assert_reacts(MatterId::WATER, MatterId::ICE, MatterId::ICE, 50);
+    }
+
+    #[test]
+    fn test_spark_splits_water() {
+        assert_reacts(MatterId::WATER, MatterId::SPARK, MatterId::STEAM, 3);
+    }
+
+    #[test]
+    fn test_lava_melts_rock() {
+        assert_reacts(MatterId::ROCK, MatterId::LAVA, MatterId::LAVA, 50);
+    }
+
+    #[test]
+    fn test_water_cools_lava() {
+        assert_reacts(MatterId::LAVA, MatterId::WATER, MatterId::ROCK, 5);
+    }
+
+    #[test]
+    fn test_fire_melts_ice() {
+        assert_reacts(MatterId::ICE, MatterId::FIRE, MatterId::WATER, 5);
+    }
+
+    #[test]
+    fn test_ice_condenses_steam() {
+        assert_reacts(MatterId::STEAM, MatterId::ICE, MatterId::WATER, 20);
+    }
+
+    #[test]
+    fn test_water_extinguishes_fire() {
+        assert_reacts(MatterId::FIRE, MatterId::WATER, MatterId::SMOKE, 3);
+    }
+
+    #[test]
+    fn test_fire_ignites_oil() {
+        assert_reacts(MatterId::OIL, MatterId::FIRE, MatterId::FIRE, 3);
+    }
+
+    #[test]
+    fn test_fire_ignites_wood() {
+        assert_reacts(MatterId::WOOD, MatterId::FIRE, MatterId::FIRE, 10);
+    }
+
+    #[test]
+    fn test_acid_eats_wood() {
+        assert_reacts(MatterId::WOOD, MatterId::ACID, MatterId::EMPTY, 30);
+    }
+
+    #[test]
+    fn test_lava_melts_metal() {
+        assert_reacts(MatterId::METAL, MatterId::LAVA, MatterId::LAVA, 100);
+    }
+
+    #[test]
+    fn test_acid_eats_metal() {
+        assert_reacts(MatterId::METAL, MatterId::ACID, MatterId::EMPTY, 50);
+    }
+
+    #[test]
+    fn test_spark_charges_metal() {
+        assert_reacts(MatterId::METAL, MatterId::SPARK, MatterId::CHARGED_METAL, 2);
+    }
+
+    #[test]
+    fn test_acid_eats_rust() {
+        assert_reacts(MatterId::RUST, MatterId::ACID, MatterId::EMPTY, 30);
+    }
+
+    #[test]
+    fn test_spark_detonates_gunpowder() {
+        assert_reacts(MatterId::GUNPOWDER, MatterId::SPARK, MatterId::EXPLOSION, 2);
+    }
+
+    #[test]
+    fn test_fire_detonates_gunpowder() {
+        assert_reacts(MatterId::GUNPOWDER, MatterId::FIRE, MatterId::EXPLOSION, 2);
+    }
+
+    #[test]
+    fn test_brush_modes() {
+        let (_ctx, mut simulator) = test_setup();
+        let pos = IVec2::new(10, 10);
+        let empty_pos = pos + IVec2::new(3, 0);
+        simulator.draw_matter(pos.as_vec2(), pos.as_vec2(), 0.5, MatterId::ROCK, Brush::default());
+        // Only empty cells are replaced
+        let brush = Brush {
+            mode: BrushMode::ReplaceEmpty,
+            shape: BrushShape::Square,
+            ..Brush::default()
+        };
+        simulator.draw_matter(pos.as_vec2(), pos.as_vec2(), 4.0, MatterId::WOOD, brush);
+        assert_eq!(simulator.query_matter(pos), Some(MatterId::ROCK));
+        assert_eq!(simulator.query_matter(empty_pos), Some(MatterId::WOOD));
+        // Only rock is erased
+        let brush = Brush {
+            mode: BrushMode::EraseMatter(MatterId::ROCK),
+            ..Brush::default()
+        };
+        simulator.draw_matter(pos.as_vec2(), pos.as_vec2(), 4.0, MatterId::SAND, brush);
+        assert_eq!(simulator.query_matter(pos), Some(MatterId::EMPTY));
+        assert_eq!(simulator.query_matter(empty_pos), Some(MatterId::WOOD));
+    }
+
+    #[test]
+    fn test_eraser_brush() {
+        let (_ctx, mut simulator) = test_setup();
+        let pos = IVec2::new(10, 10);
+        let other_pos = pos + IVec2::new(3, 0);
+        let square = Brush {
+            shape: BrushShape::Square,
+            ..Brush::default()
+        };
+        simulator.draw_matter(pos.as_vec2(), pos.as_vec2(), 4.0, MatterId::ROCK, square);
+        simulator.draw_matter(pos.as_vec2(), pos.as_vec2(), 0.5, MatterId::WOOD, square);
+        // Erasing with a brush drawing only into empty cells still erases
+        let brush = Brush {
+            mode: BrushMode::ReplaceEmpty,
+            ..square
+        };
+        let eraser = brush.eraser();
+        simulator.draw_matter(pos.as_vec2(), pos.as_vec2(), 0.5, MatterId::EMPTY, eraser);
+        assert_eq!(simulator.query_matter(pos), Some(MatterId::EMPTY));
+        // Erasing with a brush restricted to rock erases only rock
+        simulator.draw_matter(pos.as_vec2(), pos.as_vec2(), 0.5, MatterId::WOOD, square);
+        let brush = Brush {
+            mode: BrushMode::ReplaceMatter(MatterId::ROCK),
+            ..square
+        };
+        let eraser = brush.eraser();
+        simulator.draw_matter(pos.as_vec2(), pos.as_vec2(), 4.0, MatterId::EMPTY, eraser);
+        assert_eq!(simulator.query_matter(pos), Some(MatterId::WOOD));
+        assert_eq!(simulator.query_matter(other_pos), Some(MatterId::EMPTY));
+    }
+
+    #[test]
+    fn test_batched_draws() {
+        let (_ctx, mut simulator) = test_setup();
+        let pos = IVec2::new(10, 10);
+        let far_pos = IVec2::new(200, 100);
+        // Queued draws are drawn in order once the grid is read
+        let sand = Brush::default();
+        simulator.draw_matter(pos.as_vec2(), pos.as_vec2(), 2.0, MatterId::SAND, sand);
+        let brush = Brush {
+            mode: BrushMode::ReplaceMatter(MatterId::SAND),
+            ..Brush::default()
+        };
+        simulator.draw_matter(pos.as_vec2(), pos.as_vec2(), 1.0, MatterId::WATER, brush);
+        let rock = Brush::default();
+        let far_pos_f = far_pos.as_vec2();
+        simulator.draw_matter(far_pos_f, far_pos_f, 0.5, MatterId::ROCK, rock);
+        let off_canvas = Vec2::new(-50.0, -50.0);
+        simulator.draw_matter(off_canvas, off_canvas, 4.0, MatterId::ROCK, rock);
+        assert_eq!(simulator.query_matter(pos), Some(MatterId::WATER));
+        assert_eq!(
+            simulator.query_matter(pos + IVec2::new(2, 0)),
+            Some(MatterId::SAND)
+        );
+        assert_eq!(simulator.query_matter(far_pos), Some(MatterId::ROCK));
+    }
+
+    #[test]
+    fn test_population() {
+        let (_ctx, mut simulator) = test_setup();
+        let rock = simulator
+            .registry()
+            .definition(MatterId::ROCK)
+            .to_matter_with_color();
+        simulator.write_region(IVec2::new(10, 10), UVec2::new(4, 3), &[rock; 12]);
+        let canvas = UVec2::new(CANVAS_SIZE_X, CANVAS_SIZE_Y);
+        let population = simulator.count_population(IVec2::ZERO, canvas);
+        assert_eq!(population.count(MatterId::ROCK), 12);
+        assert_eq!(
+            population.count(MatterId::EMPTY),
+            CANVAS_SIZE_X * CANVAS_SIZE_Y - 12
+        );
+        // Only the region is counted
+        let population = simulator.count_population(IVec2::new(12, 0), UVec2::new(10, 20));
+        assert_eq!(population.count(MatterId::ROCK), 6);
+        // Counted with the next step & read back afterwards
+        assert_eq!(simulator.take_population(), None);
+        simulator.request_population(IVec2::ZERO, canvas);
+        simulator.step(1, true);
+        let population = simulator.take_population().unwrap();
+        assert_eq!(population.sim_step, simulator.sim_step);
+        assert_eq!(population.count(MatterId::ROCK), 12);
+        assert_eq!(simulator.take_population(), None);
+    }
+
+    #[test]
+    fn test_movement_conserves_matter() {
+        let (_ctx, mut simulator) = test_setup();
+        simulator.check_conservation = true;
+        simulator.draw_matter(
+            Vec2::new(20.0, 60.0),
+            Vec2::new(80.0, 60.0),
+            6.0,
+            MatterId::SAND,
+            Brush::default(),
+        );
+        simulator.draw_matter(
+            Vec2::new(20.0, 80.0),
+            Vec2::new(80.0, 80.0),
+            6.0,
+            MatterId::WATER,
+            Brush::default(),
+        );
+        simulator.draw_matter(
+            Vec2::new(20.0, 20.0),
+            Vec2::new(80.0, 20.0),
+            4.0,
+            MatterId::STEAM,
+            Brush::default(),
+        );
+        for _ in 0..10 {
+            simulator.step(2, false);
+        }
+        assert_eq!(simulator.conservation_violations, vec![]);
+    }
+
+    #[test]
+    fn test_paste_region() {
+        let (_ctx, mut simulator) = test_setup();
+        let registry = simulator.registry();
+        let rock = registry.definition(MatterId::ROCK).to_matter_with_color();
+        let sand = registry.definition(MatterId::SAND).to_matter_with_color();
+        simulator.write_region(IVec2::new(11, 10), UVec2::new(1, 1), &[rock]);
+        let region = CellRegion {
+            width: 2,
+            height: 1,
+            cells: vec![sand, sand],
+        };
+        // Only the empty cell is replaced
+        simulator.paste_region(IVec2::new(10, 10), &region, PasteMode::IntoEmpty);
+        let pasted = simulator.read_region(IVec2::new(10, 10), UVec2::new(2, 1));
+        assert_eq!(pasted, vec![sand, rock]);
+        simulator.paste_region(IVec2::new(10, 10), &region, PasteMode::Over);
+        let pasted = simulator.read_region(IVec2::new(10, 10), UVec2::new(2, 1));
+        assert_eq!(pasted, vec![sand, sand]);
+        // Cells outside the canvas are dropped
+        simulator.paste_region(IVec2::new(-1, 0), &region, PasteMode::Over);
+        assert_eq!(simulator.query_matter(IVec2::ZERO), Some(MatterId::SAND));
+        let pos = IVec2::new(10, 10);
+        simulator.clear_region(pos, UVec2::new(2, 1));
+        assert_eq!(simulator.query_matter(pos), Some(MatterId::EMPTY));
+    }
+
+    #[test]
+    fn test_set_registry_empties_removed_matters() {
+        let (_ctx, mut simulator) = test_setup();
+        let (sand, water) = (IVec2::new(10, 10), IVec2::new(30, 10));
+        for (pos, matter) in [(sand, MatterId::SAND), (water, MatterId::WATER)] {
+            let pos = pos.as_vec2();
+            simulator.draw_matter(pos, pos, 1.0, matter, Brush::default());
+        }
+        let mut pack = MatterPack::builtin();
+        pack.matters.retain(|matter| matter.name != "Water");
+        for matter in &mut pack.matters {
+            matter
+                .reactions
+                .retain(|reaction| reaction.becomes != "Water");
+        }
+        let registry = pack.resolve(Some(simulator.registry())).unwrap();
+        simulator.set_registry(registry);
+        assert_eq!(simulator.query_matter(sand), Some(MatterId::SAND));
+        assert_eq!(simulator.query_matter(water), Some(MatterId::EMPTY));
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let (_ctx, mut simulator) = test_setup();
+        simulator.draw_matter(
+            Vec2::new(10.0, 40.0),
+            Vec2::new(60.0, 40.0),
+            4.0,
+            MatterId::SAND,
+            Brush::default(),
+        );
+        let snapshot = simulator.snapshot(None);
+        for _ in 0..10 {
+            simulator.step(1, false);
+        }
+        let stepped = simulator.read_grid();
+        // Resuming from the snapshot reproduces the same world
+        simulator.restore(&snapshot);
+        assert_eq!(simulator.sim_step, snapshot.sim_step);
+        for _ in 0..10 {
+            simulator.step(1, false);
+        }
+        assert_eq!(simulator.read_grid(), stepped);
+        // Steps counted while paused on a rewound snapshot are dropped when resuming
+        let mut history = History::new(1, 4);
+        history.record(&simulator);
+        history.rewind(&mut simulator, 0);
+        simulator.step(1, true);
+        history.resume(&mut simulator);
+        assert_eq!(Some(simulator.sim_step), history.sim_step(0));
+        assert_eq!(history.inspecting, None);
     }
 }
\ No newline at end of file
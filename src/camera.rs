@@ -3,6 +3,8 @@ use bevy::{
     prelude::Transform,
 };
 
+use crate::{CANVAS_SIZE_X, CANVAS_SIZE_Y};
+
 // c1r1: y flipped for vulkan
 #[rustfmt::skip]
 pub const OPENGL_TO_VULKAN_MATRIX: Mat4 = Mat4::from_cols_array(&[
@@ -14,6 +16,17 @@ pub const OPENGL_TO_VULKAN_MATRIX: Mat4 = Mat4::from_cols_array(&[
 
 const Z_POS: f32 = -10.0;
 
+/// Closest zoom, in world units per screen pixel
+pub const MIN_CAMERA_SCALE: f32 = 0.05;
+/// Furthest zoom, in world units per screen pixel
+pub const MAX_CAMERA_SCALE: f32 = 4.0;
+/// Zoom factor of a mouse wheel step
+pub const CAMERA_ZOOM_STEP: f32 = 1.1;
+/// How quickly the camera catches up with its target, higher is snappier
+pub const CAMERA_SMOOTHING: f32 = 15.0;
+/// Screen pixels the cursor must move with the middle button held before it counts as a drag
+pub const CAMERA_DRAG_THRESHOLD: f32 = 4.0;
+
 /// A simple orthographic camera
 #[derive(Debug, Copy, Clone)]
 pub struct OrthographicCamera {
@@ -38,6 +51,14 @@ impl OrthographicCamera {
         self.bottom = -half_height;
     }
 
+    /// World space min & max corners of the visible area
+    pub fn visible_world(&self) -> (Vec2, Vec2) {
+        (
+            Vec2::new(self.left, self.bottom) * self.scale - self.pos,
+            Vec2::new(self.right, self.top) * self.scale - self.pos,
+        )
+    }
+
     /// Get world to screen matrix to be passed to our rendering
     pub fn world_to_screen(&self) -> Mat4 {
         (OPENGL_TO_VULKAN_MATRIX
@@ -76,4 +97,159 @@ impl Default for OrthographicCamera {
             scale: 1.0,
         }
     }
-}
\ No newline at end of file
+}
+
+/// Where the camera is heading. The camera eases towards its target each frame, except while dragged
+/// which moves it directly.
+#[derive(Debug, Copy, Clone)]
+pub struct CameraController {
+    pub target_pos: Vec2,
+    pub target_scale: f32,
+    /// Keep the view centered over the canvas so that it can't be scrolled fully off screen
+    pub clamp_to_canvas: bool,
+    /// Screen offset from window center that stays over the same world position while zooming
+    zoom_anchor: Option<Vec2>,
+    /// Cursor position where the drag started & on the last frame
+    drag: Option<(Vec2, Vec2)>,
+    /// Whether the last middle button press moved beyond the drag threshold
+    dragged: bool,
+}
+
+impl CameraController {
+    pub fn new(camera: &OrthographicCamera) -> CameraController {
+        CameraController {
+            target_pos: camera.pos,
+            target_scale: camera.scale,
+            clamp_to_canvas: false,
+            zoom_anchor: None,
+            drag: None,
+            dragged: false,
+        }
+    }
+
+    /// Move the target by a world space delta
+    pub fn move_by(&mut self, delta: Vec2) {
+        self.target_pos += delta;
+        self.zoom_anchor = None;
+    }
+
+    /// Target the view centered over a world position
+    pub fn look_at(&mut self, world: Vec2) {
+        self.target_pos = -world;
+        self.zoom_anchor = None;
+    }
+
+    /// Zoom the target by factor so that the world position at screen offset (from window center)
+    /// stays in place. Zoom is limited to `MIN_CAMERA_SCALE`..`MAX_CAMERA_SCALE`.
+    pub fn zoom_at(&mut self, factor: f32, screen_offset: Vec2) {
+        let world = screen_offset * self.target_scale - self.target_pos;
+        self.target_scale = (self.target_scale * factor).clamp(MIN_CAMERA_SCALE, MAX_CAMERA_SCALE);
+        self.target_pos = screen_offset * self.target_scale - world;
+        self.zoom_anchor = Some(screen_offset);
+    }
+
+    /// Target the whole canvas height fitting the window, centered
+    pub fn fit_canvas(&mut self, camera: &OrthographicCamera, window_height: f32) {
+        let mut fitted = *camera;
+        fitted.zoom_to_fit_vertical_pixels(CANVAS_SIZE_Y, window_height as u32);
+        self.target_pos = Vec2::ZERO;
+        self.target_scale = fitted.scale.clamp(MIN_CAMERA_SCALE, MAX_CAMERA_SCALE);
+        self.zoom_anchor = None;
+    }
+
+    pub fn start_drag(&mut self, cursor: Vec2) {
+        self.drag = Some((cursor, cursor));
+        self.dragged = false;
+    }
+
+    /// Pan so that the world under the cursor follows it
+    pub fn drag_to(&mut self, camera: &mut OrthographicCamera, cursor: Vec2) {
+        if let Some((start, last)) = &mut self.drag {
+            let delta = cursor - *last;
+            *last = cursor;
+            self.dragged |= cursor.distance(*start) > CAMERA_DRAG_THRESHOLD;
+            self.target_pos += delta * self.target_scale;
+            camera.pos += delta * camera.scale;
+            self.zoom_anchor = None;
+        }
+    }
+
+    pub fn end_drag(&mut self) {
+        self.drag = None;
+    }
+
+    /// Did the last middle button press pan the camera rather than click
+    pub fn dragged(&self) -> bool {
+        self.dragged
+    }
+
+    /// Ease the camera towards the target
+    pub fn update(&mut self, camera: &mut OrthographicCamera, delta_seconds: f32) {
+        if self.clamp_to_canvas {
+            self.target_pos = clamp_target(self.target_pos);
+        }
+        let t = 1.0 - (-CAMERA_SMOOTHING * delta_seconds).exp();
+        // Zoom at an even pace no matter the scale
+        camera.scale = (camera.scale.ln() + (self.target_scale.ln() - camera.scale.ln()) * t).exp();
+        camera.pos = match self.zoom_anchor {
+            Some(anchor) => {
+                // Keep the anchor over the world position it is over at the target
+                let world = anchor * self.target_scale - self.target_pos;
+                anchor * camera.scale - world
+            }
+            None => camera.pos.lerp(self.target_pos, t),
+        };
+        if self.clamp_to_canvas {
+            camera.pos = clamp_target(camera.pos);
+        }
+    }
+}
+
+/// Camera position with the view center within the canvas
+fn clamp_target(pos: Vec2) -> Vec2 {
+    let half_canvas = Vec2::new(CANVAS_SIZE_X as f32 / 2.0, CANVAS_SIZE_Y as f32 / 2.0);
+    pos.clamp(-half_canvas, half_canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+
+    use crate::{
+        camera::{CameraController, OrthographicCamera, MAX_CAMERA_SCALE},
+        CANVAS_SIZE_X,
+    };
+
+    #[test]
+    fn test_camera_controller() {
+        let mut camera = OrthographicCamera::default();
+        let mut controller = CameraController::new(&camera);
+        // Zooming keeps the world under the cursor in place
+        let cursor = Vec2::new(100.0, -50.0);
+        let world = cursor * camera.scale - camera.pos;
+        controller.zoom_at(0.5, cursor);
+        for _ in 0..100 {
+            controller.update(&mut camera, 1.0 / 60.0);
+            assert!((cursor * camera.scale - camera.pos - world).length() < 1e-3);
+        }
+        assert!((camera.scale - 0.5).abs() < 1e-3);
+        // Zoom is limited
+        controller.zoom_at(1000.0, cursor);
+        assert_eq!(controller.target_scale, MAX_CAMERA_SCALE);
+        // Small moves are still a click
+        controller.start_drag(Vec2::ZERO);
+        controller.drag_to(&mut camera, Vec2::new(1.0, 1.0));
+        assert!(!controller.dragged());
+        let pos = camera.pos;
+        controller.drag_to(&mut camera, Vec2::new(20.0, 1.0));
+        controller.end_drag();
+        assert!(controller.dragged());
+        assert_eq!(camera.pos, pos + Vec2::new(19.0, 0.0) * camera.scale);
+        // The view center is kept over the canvas
+        controller.clamp_to_canvas = true;
+        controller.look_at(Vec2::new(CANVAS_SIZE_X as f32, 0.0));
+        controller.update(&mut camera, 1.0 / 60.0);
+        assert_eq!(controller.target_pos.x, -(CANVAS_SIZE_X as f32) / 2.0);
+        assert!(-camera.pos.x <= CANVAS_SIZE_X as f32 / 2.0);
+    }
+}
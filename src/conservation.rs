@@ -0,0 +1,204 @@
+use bevy::prelude::*;
+use bevy_vulkano::{egui_winit_vulkano::egui, BevyVulkanoWindows};
+
+use crate::{
+    ca_simulator::CASimulator,
+    matter::{MatterDefinition, MatterId},
+};
+
+/// Side of the tiles violations are located in
+pub const CONSERVATION_TILE_SIZE: u32 = 32;
+/// Violations kept, older ones are dropped
+pub const MAX_CONSERVATION_VIOLATIONS: usize = 100;
+
+/// Kernels that only move matter, so they must neither create nor destroy it
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MovementPass {
+    Fall,
+    Rise,
+    Slide,
+}
+
+impl MovementPass {
+    /// Shader of the pass
+    pub fn name(&self) -> &'static str {
+        match self {
+            MovementPass::Fall => "fall_empty",
+            MovementPass::Rise => "rise_empty",
+            MovementPass::Slide => "slide_down_empty",
+        }
+    }
+}
+
+/// A movement pass that changed how many cells there are of some matters
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConservationViolation {
+    /// Step during which the pass ran
+    pub sim_step: u32,
+    pub pass: MovementPass,
+    /// Matters whose count changed & by how much
+    pub changes: Vec<(MatterId, i64)>,
+    /// Tiles (min & size) where the change can't be explained by moving cells, may be empty if the
+    /// cells appeared or vanished next to other cells of the same matter
+    pub regions: Vec<(IVec2, UVec2)>,
+}
+
+/// Change in the count of each matter between two populations (cell counts indexed by matter id)
+pub fn population_changes(before: &[u32], after: &[u32]) -> Vec<(MatterId, i64)> {
+    before
+        .iter()
+        .zip(after)
+        .enumerate()
+        .filter(|(_, (before, after))| before != after)
+        .map(|(id, (&before, &after))| (MatterId(id as u8), after as i64 - before as i64))
+        .collect()
+}
+
+/// Summed area table of the cells of matter, `(width + 1) * (height + 1)` entries
+fn summed_area(grid: &[u32], width: u32, height: u32, matter: MatterId) -> Vec<u32> {
+    let stride = (width + 1) as usize;
+    let mut table = vec![0; stride * (height + 1) as usize];
+    for y in 0..height as usize {
+        let mut row = 0;
+        for x in 0..width as usize {
+            row +=
+                (MatterDefinition::get_id_from_u32(grid[y * width as usize + x]) == matter) as u32;
+            table[(y + 1) * stride + x + 1] = table[y * stride + x + 1] + row;
+        }
+    }
+    table
+}
+
+/// Cells counted in a summed area table within min (inclusive) & max (exclusive)
+fn count_in(table: &[u32], width: u32, min: IVec2, max: IVec2) -> u32 {
+    let stride = (width + 1) as usize;
+    let at = |x: i32, y: i32| table[y as usize * stride + x as usize];
+    at(max.x, max.y) + at(min.x, min.y) - at(min.x, max.y) - at(max.x, min.y)
+}
+
+/// Tiles where the change of matters between two grids can't come from moving cells by at most one
+/// cell: a tile has more cells of a matter than there were within one cell of it, or the other way
+/// around.
+pub fn violating_tiles(
+    before: &[u32],
+    after: &[u32],
+    width: u32,
+    height: u32,
+    matters: &[MatterId],
+) -> Vec<(IVec2, UVec2)> {
+    let size = IVec2::new(width as i32, height as i32);
+    let mut tiles = vec![];
+    for &matter in matters {
+        let before = summed_area(before, width, height, matter);
+        let after = summed_area(after, width, height, matter);
+        for y in (0..height).step_by(CONSERVATION_TILE_SIZE as usize) {
+            for x in (0..width).step_by(CONSERVATION_TILE_SIZE as usize) {
+                let min = IVec2::new(x as i32, y as i32);
+                let max = (min + CONSERVATION_TILE_SIZE as i32).min(size);
+                let (grown_min, grown_max) = ((min - 1).max(IVec2::ZERO), (max + 1).min(size));
+                let created = count_in(&after, width, min, max)
+                    > count_in(&before, width, grown_min, grown_max);
+                let destroyed = count_in(&before, width, min, max)
+                    > count_in(&after, width, grown_min, grown_max);
+                let tile = (min, (max - min).as_uvec2());
+                if (created || destroyed) && !tiles.contains(&tile) {
+                    tiles.push(tile);
+                }
+            }
+        }
+    }
+    tiles
+}
+
+/// Egui window listing violations while conservation is checked
+pub fn conservation_interface(
+    vulkano_windows: NonSend<BevyVulkanoWindows>,
+    mut simulator: ResMut<CASimulator>,
+) {
+    if !simulator.check_conservation {
+        return;
+    }
+    let (_, gui) = vulkano_windows.get_primary_window_renderer().unwrap();
+    let ctx = gui.context();
+    let mut open = true;
+    egui::Window::new("Mass Conservation")
+        .open(&mut open)
+        .vscroll(true)
+        .show(&ctx, |ui| {
+            ui.label("Movement passes are stepped one by one & checked for created or lost cells");
+            if ui.button("Clear").clicked() {
+                simulator.conservation_violations.clear();
+            }
+            if simulator.conservation_violations.is_empty() {
+                ui.label("No violations");
+            }
+            let registry = simulator.registry();
+            for violation in simulator.conservation_violations.iter().rev() {
+                ui.separator();
+                ui.colored_label(
+                    egui::Color32::RED,
+                    format!("Step {}, {}", violation.sim_step, violation.pass.name()),
+                );
+                let changes = violation
+                    .changes
+                    .iter()
+                    .map(|(id, change)| format!("{} {:+}", registry.name(*id), change))
+                    .collect::<Vec<_>>();
+                ui.label(changes.join(", "));
+                if violation.regions.is_empty() {
+                    ui.label("Region unknown");
+                }
+                for (min, size) in &violation.regions {
+                    ui.label(format!(
+                        "Region [{}, {}] size [{}, {}]",
+                        min.x, min.y, size.x, size.y
+                    ));
+                }
+            }
+        });
+    simulator.check_conservation = open;
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{IVec2, UVec2};
+
+    use crate::{
+        conservation::{population_changes, violating_tiles, CONSERVATION_TILE_SIZE},
+        matter::MatterId,
+    };
+
+    #[test]
+    fn test_violating_tiles() {
+        assert_eq!(
+            population_changes(&[10, 2, 3], &[9, 3, 3]),
+            vec![(MatterId(0), -1), (MatterId(1), 1)]
+        );
+        let (width, height) = (2 * CONSERVATION_TILE_SIZE, CONSERVATION_TILE_SIZE);
+        let sand = MatterId::SAND.0 as u32;
+        let mut before = vec![0; (width * height) as usize];
+        before[(10 * width + 31) as usize] = sand;
+        // Moving across a tile border is fine
+        let mut moved = vec![0; (width * height) as usize];
+        moved[(10 * width + 32) as usize] = sand;
+        assert!(violating_tiles(&before, &moved, width, height, &[MatterId::SAND]).is_empty());
+        // Duplicating sand far from other sand is located
+        let mut single = vec![0; (width * height) as usize];
+        single[(10 * width + 5) as usize] = sand;
+        let mut duplicated = single.clone();
+        duplicated[(20 * width + 50) as usize] = sand;
+        assert_eq!(
+            violating_tiles(&single, &duplicated, width, height, &[MatterId::SAND]),
+            vec![(
+                IVec2::new(CONSERVATION_TILE_SIZE as i32, 0),
+                UVec2::splat(CONSERVATION_TILE_SIZE)
+            )]
+        );
+        // As is deleting it
+        let deleted = vec![0; (width * height) as usize];
+        assert_eq!(
+            violating_tiles(&single, &deleted, width, height, &[MatterId::SAND]),
+            vec![(IVec2::ZERO, UVec2::splat(CONSERVATION_TILE_SIZE))]
+        );
+    }
+}
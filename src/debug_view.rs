@@ -0,0 +1,36 @@
+use strum_macros::EnumIter;
+
+/// What the color kernel shows for each cell
+#[repr(u32)]
+#[derive(EnumIter, Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum DebugView {
+    /// Matter colors, the normal view
+    #[default]
+    Matter = 0,
+    /// False color by `MatterState`
+    State = 1,
+    /// False color by matter id
+    MatterId = 2,
+    /// Cells that changed this step in white
+    Changed = 3,
+    /// Matter colors with work group sized chunks tinted by whether any of their cells changed this
+    /// step
+    ChunkActivity = 4,
+}
+
+impl DebugView {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DebugView::Matter => "Matter",
+            DebugView::State => "State",
+            DebugView::MatterId => "Matter Id",
+            DebugView::Changed => "Changed This Step",
+            DebugView::ChunkActivity => "Chunk Activity",
+        }
+    }
+
+    /// Does the view compare cells to the grid at the start of the step
+    pub fn needs_previous_grid(&self) -> bool {
+        matches!(self, DebugView::Changed | DebugView::ChunkActivity)
+    }
+}
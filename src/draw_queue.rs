@@ -0,0 +1,121 @@
+use bevy::math::{IVec2, UVec2, Vec2};
+
+use crate::{
+    brush::Brush,
+    utils::{clamp_to_canvas, line_bounds},
+};
+
+/// Words before the primitives in the draw buffer: count, padding & inclusive min & max of the area
+pub const DRAW_HEADER_GPU_WORDS: usize = 6;
+/// Must match `DrawPrimitive` in `includes.glsl`
+pub const DRAW_PRIMITIVE_GPU_WORDS: usize = 14;
+
+/// A brush line waiting to be drawn. Primitives are queued & drawn together the next time the grid is
+/// stepped, read or written.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DrawPrimitive {
+    pub start: Vec2,
+    pub end: Vec2,
+    pub radius: f32,
+    /// Packed matter & color
+    pub matter: u32,
+    pub brush: Brush,
+}
+
+impl DrawPrimitive {
+    /// Inclusive bounds of the cells the primitive may change within the canvas
+    fn bounds(&self) -> Option<(IVec2, IVec2)> {
+        let (min, max) = line_bounds(self.start, self.end, self.radius);
+        let (min, size) = clamp_to_canvas(min, max)?;
+        Some((min, min + size.as_ivec2() - IVec2::ONE))
+    }
+
+    fn to_gpu_words(&self, min: IVec2, max: IVec2) -> [u32; DRAW_PRIMITIVE_GPU_WORDS] {
+        let (mode, mode_matter) = self.brush.mode.to_gpu();
+        [
+            self.start.x.to_bits(),
+            self.start.y.to_bits(),
+            self.end.x.to_bits(),
+            self.end.y.to_bits(),
+            self.radius.to_bits(),
+            self.matter,
+            self.brush.shape as u32,
+            mode,
+            mode_matter,
+            self.brush.spray_density.to_bits(),
+            min.x as u32,
+            min.y as u32,
+            max.x as u32,
+            max.y as u32,
+        ]
+    }
+}
+
+/// Draw buffer contents for primitives drawn in order, with the min & size of the area covering them
+/// all. None if no primitive touches the canvas.
+pub fn draw_buffer(primitives: &[DrawPrimitive]) -> Option<(Vec<u32>, IVec2, UVec2)> {
+    let bounded = primitives
+        .iter()
+        .filter_map(|primitive| primitive.bounds().map(|bounds| (primitive, bounds)))
+        .collect::<Vec<_>>();
+    let area_min = bounded
+        .iter()
+        .map(|(_, (min, _))| *min)
+        .reduce(IVec2::min)?;
+    let area_max = bounded
+        .iter()
+        .map(|(_, (_, max))| *max)
+        .reduce(IVec2::max)?;
+    let mut words = vec![
+        bounded.len() as u32,
+        0,
+        area_min.x as u32,
+        area_min.y as u32,
+        area_max.x as u32,
+        area_max.y as u32,
+    ];
+    for (primitive, (min, max)) in bounded {
+        words.extend(primitive.to_gpu_words(min, max));
+    }
+    let area_size = (area_max - area_min + IVec2::ONE).as_uvec2();
+    Some((words, area_min, area_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{IVec2, UVec2, Vec2};
+
+    use crate::{
+        brush::Brush,
+        draw_queue::{draw_buffer, DrawPrimitive, DRAW_HEADER_GPU_WORDS, DRAW_PRIMITIVE_GPU_WORDS},
+    };
+
+    #[test]
+    fn test_draw_buffer() {
+        let primitive = |start: Vec2, end: Vec2| DrawPrimitive {
+            start,
+            end,
+            radius: 2.0,
+            matter: 1,
+            brush: Brush::default(),
+        };
+        assert_eq!(draw_buffer(&[]), None);
+        let off_canvas = primitive(Vec2::new(-100.0, -100.0), Vec2::new(-50.0, -100.0));
+        assert_eq!(draw_buffer(&[off_canvas]), None);
+        let (words, min, size) = draw_buffer(&[
+            primitive(Vec2::new(10.0, 10.0), Vec2::new(20.0, 10.0)),
+            off_canvas,
+            primitive(Vec2::new(30.0, 40.0), Vec2::new(30.0, 40.0)),
+        ])
+        .unwrap();
+        // Primitives outside the canvas are dropped
+        assert_eq!(words[0], 2);
+        assert_eq!(
+            words.len(),
+            DRAW_HEADER_GPU_WORDS + 2 * DRAW_PRIMITIVE_GPU_WORDS
+        );
+        // The area covers both lines & their radius
+        assert_eq!(min, IVec2::new(7, 7));
+        assert_eq!(size, UVec2::new(27, 37));
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::VecDeque;
+
+use bevy::math::IVec2;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    matter::MatterDefinition,
+    undo::RegionSnapshot,
+    utils::{u32_rgba_to_u8_rgba, u8_rgba_to_u32_rgba},
+};
+
+/// How a flood fill spreads
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FloodFill {
+    /// Also spread to diagonal neighbors (8-connected instead of 4-connected)
+    pub diagonal: bool,
+    /// Stop after filling this many cells, 0 for no limit
+    pub max_area: u32,
+}
+
+impl Default for FloodFill {
+    fn default() -> Self {
+        FloodFill {
+            diagonal: false,
+            max_area: 0,
+        }
+    }
+}
+
+const NEIGHBORS_4: [IVec2; 4] = [
+    IVec2::new(0, 1),
+    IVec2::new(1, 0),
+    IVec2::new(0, -1),
+    IVec2::new(-1, 0),
+];
+const NEIGHBORS_8: [IVec2; 8] = [
+    IVec2::new(-1, 1),
+    IVec2::new(0, 1),
+    IVec2::new(1, 1),
+    IVec2::new(1, 0),
+    IVec2::new(1, -1),
+    IVec2::new(0, -1),
+    IVec2::new(-1, -1),
+    IVec2::new(-1, 0),
+];
+
+/// The bounding box of a flood fill's cells, computed from a single read of the grid
+pub struct FilledRegion {
+    /// Cells as they were before the fill
+    pub before: RegionSnapshot,
+    /// Cells after the fill
+    pub after: Vec<u32>,
+}
+
+/// Indices of the cells connected to start that have the same matter as start, in fill order.
+/// `grid` is row by row with given width.
+pub fn flood_fill_cells(grid: &[u32], width: u32, start: IVec2, fill: &FloodFill) -> Vec<usize> {
+    let height = grid.len() as u32 / width;
+    let is_inside = |pos: IVec2| {
+        pos.x >= 0 && pos.y >= 0 && pos.x < width as i32 && pos.y < height as i32
+    };
+    if !is_inside(start) {
+        return vec![];
+    }
+    let index = |pos: IVec2| (pos.y as u32 * width + pos.x as u32) as usize;
+    let matter = MatterDefinition::get_id_from_u32(grid[index(start)]);
+    let neighbors: &[IVec2] = if fill.diagonal {
+        &NEIGHBORS_8
+    } else {
+        &NEIGHBORS_4
+    };
+    let max_area = if fill.max_area == 0 {
+        usize::MAX
+    } else {
+        fill.max_area as usize
+    };
+    let mut visited = vec![false; grid.len()];
+    let mut cells = vec![];
+    let mut queue = VecDeque::from([start]);
+    visited[index(start)] = true;
+    while let Some(pos) = queue.pop_front() {
+        cells.push(index(pos));
+        if cells.len() >= max_area {
+            break;
+        }
+        for offset in neighbors {
+            let next = pos + *offset;
+            if is_inside(next)
+                && !visited[index(next)]
+                && MatterDefinition::get_id_from_u32(grid[index(next)]) == matter
+            {
+                visited[index(next)] = true;
+                queue.push_back(next);
+            }
+        }
+    }
+    cells
+}
+
+/// Inclusive bounds of cell indices in a grid of given width
+pub fn cell_bounds(cells: &[usize], width: u32) -> Option<(IVec2, IVec2)> {
+    let positions = cells
+        .iter()
+        .map(|&i| IVec2::new((i as u32 % width) as i32, (i as u32 / width) as i32));
+    positions.fold(None, |bounds, pos| match bounds {
+        None => Some((pos, pos)),
+        Some((min, max)) => Some((min.min(pos), max.max(pos))),
+    })
+}
+
+/// Vary the color of a cell by its position like `variate_color` in `includes.glsl` does, so that
+/// filled matter looks like drawn matter. The shader's float math is followed step by step.
+pub fn variate_color(pos: IVec2, matter_with_color: u32) -> u32 {
+    // Golden ratio as a glsl float
+    const PHI: f32 = 1.618_034;
+    // rand(pos, 0.1)
+    let xy = pos.as_vec2();
+    let noise = (xy * PHI).distance(xy) * 0.1;
+    let noise = noise.tan() * xy.x;
+    // Glsl's fract, which unlike f32::fract is positive for negative numbers
+    let p = noise - noise.floor();
+    let variation = -0.1 + 0.2 * p;
+    // Channels above 255 wrap around like the shader's conversion to uint
+    let vary = |c: u8| (((c as f32 / 255.0 + variation) * 255.0) as u32 & 255) as u8;
+    let [r, g, b, id] = u32_rgba_to_u8_rgba(matter_with_color);
+    u8_rgba_to_u32_rgba(vary(r), vary(g), vary(b), id)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::IVec2;
+
+    use crate::flood_fill::{cell_bounds, flood_fill_cells, FloodFill};
+
+    #[test]
+    fn test_flood_fill_connectivity() {
+        // 4x3 grid, matter 1 forms a diagonal that 4-connected fill does not cross
+        #[rustfmt::skip]
+        let grid = [
+            1, 0, 0, 0,
+            0, 1, 0, 0,
+            0, 0, 1, 1,
+        ];
+        let four = flood_fill_cells(&grid, 4, IVec2::new(0, 0), &FloodFill::default());
+        assert_eq!(four, vec![0]);
+        let eight = FloodFill {
+            diagonal: true,
+            ..FloodFill::default()
+        };
+        let filled = flood_fill_cells(&grid, 4, IVec2::new(0, 0), &eight);
+        assert_eq!(filled.len(), 4);
+        assert_eq!(cell_bounds(&filled, 4), Some((IVec2::new(0, 0), IVec2::new(3, 2))));
+        // Empty region, limited area
+        let limited = FloodFill {
+            diagonal: false,
+            max_area: 3,
+        };
+        assert_eq!(flood_fill_cells(&grid, 4, IVec2::new(3, 0), &limited).len(), 3);
+        assert!(flood_fill_cells(&grid, 4, IVec2::new(4, 0), &limited).is_empty());
+    }
+}
@@ -6,22 +6,110 @@ use bevy_vulkano::{
     egui_winit_vulkano::{egui, egui::Ui},
     BevyVulkanoWindows,
 };
+
 use strum::IntoEnumIterator;
 
 use crate::{
+    bloom::MAX_BLOOM_RADIUS,
+    brush::{Brush, BrushMode, BrushShape},
     ca_simulator::CASimulator,
     camera::OrthographicCamera,
     cursor_to_world,
-    matter::MatterId,
+    debug_view::DebugView,
+    matter::{ActiveMatterPack, MatterId, MatterRegistry},
+    matter_editor::MatterEditor,
+    pack_watcher::MatterPackWatcher,
+    replay::{ReplayState, SimCommand},
+    rewind::History,
+    selection::{PasteMode, Selection},
+    shapes::ShapeDraft,
+    stamps::StampLibrary,
     timer::{RenderTimer, SimTimer},
-    DynamicSettings, MousePos, CANVAS_SIZE_X, CANVAS_SIZE_Y,
+    undo::UndoStack,
+    utils::canvas_to_window,
+    CurrentMousePos, DynamicSettings, MousePos, Tool, CANVAS_SIZE_X, CANVAS_SIZE_Y,
 };
 
+/// Input bindings listed in the help overlay
+const INPUT_BINDINGS: [(&str, &str); 18] = [
+    ("Left mouse", "Use the selected tool"),
+    ("Right mouse", "Erase with the current brush"),
+    (
+        "Middle click, Alt + Left mouse",
+        "Pick the matter under the cursor",
+    ),
+    ("Middle mouse drag", "Pan the camera"),
+    ("W A S D, Arrows", "Move the camera"),
+    ("Mouse wheel", "Zoom toward the cursor"),
+    ("Home", "Fit the canvas in the window"),
+    ("Space", "Pause & resume"),
+    ("Period", "Step once"),
+    ("Ctrl+Z", "Undo"),
+    ("Ctrl+Y, Ctrl+Shift+Z", "Redo"),
+    ("Ctrl+C, Ctrl+X, Ctrl+V", "Copy, cut & paste the selection"),
+    ("Delete", "Clear the selection"),
+    ("R", "Rotate the pasted cells or stamp"),
+    ("F, Shift+F", "Flip the pasted cells or stamp"),
+    (
+        "Enter, Backspace",
+        "Draw the polygon, remove its last vertex",
+    ),
+    ("F1", "Toggle this help"),
+    ("Esc", "Quit"),
+];
+
 /// Give our text a custom size
 fn sized_text(ui: &mut Ui, text: impl Into<String>, size: f32) {
     ui.label(egui::RichText::new(text).size(size));
 }
 
+/// Brush shape & mode selection. Modes restricted to a matter start with `draw_matter`.
+fn brush_edit(ui: &mut Ui, brush: &mut Brush, registry: &MatterRegistry, draw_matter: MatterId) {
+    egui::ComboBox::from_label("Brush Shape")
+        .selected_text(format!("{:?}", brush.shape))
+        .show_ui(ui, |ui| {
+            for shape in BrushShape::iter() {
+                ui.selectable_value(&mut brush.shape, shape, format!("{:?}", shape));
+            }
+        });
+    if brush.shape == BrushShape::Spray {
+        ui.add(egui::Slider::new(&mut brush.spray_density, 0.01..=1.0).text("Spray Density"));
+    }
+    brush_mode_edit(ui, &mut brush.mode, registry, draw_matter);
+}
+
+/// Brush mode selection, also used by the shape tools
+fn brush_mode_edit(
+    ui: &mut Ui,
+    mode: &mut BrushMode,
+    registry: &MatterRegistry,
+    draw_matter: MatterId,
+) {
+    let target = mode.target().unwrap_or(draw_matter);
+    egui::ComboBox::from_label("Brush Mode")
+        .selected_text(mode.name())
+        .show_ui(ui, |ui| {
+            for option in BrushMode::all(target) {
+                ui.selectable_value(&mut *mode, option, option.name());
+            }
+        });
+    if let Some(target) = mode.target() {
+        let mut selected = target;
+        egui::ComboBox::from_label("Brush Mode Matter")
+            .selected_text(registry.name(target))
+            .show_ui(ui, |ui| {
+                for (matter, name) in registry.iter() {
+                    ui.selectable_value(&mut selected, matter, name);
+                }
+            });
+        *mode = match *mode {
+            BrushMode::ReplaceMatter(_) => BrushMode::ReplaceMatter(selected),
+            BrushMode::EraseMatter(_) => BrushMode::EraseMatter(selected),
+            other => other,
+        };
+    }
+}
+
 /// System to generate user interface with egui
 pub fn user_interface(
     vulkano_windows: NonSend<BevyVulkanoWindows>,
@@ -32,6 +120,13 @@ pub fn user_interface(
     sim_timer: Res<SimTimer>,
     render_timer: Res<RenderTimer>,
     mut simulator: ResMut<CASimulator>,
+    mut replay: ResMut<ReplayState>,
+    mut undo: ResMut<UndoStack>,
+    matter_pack: Res<ActiveMatterPack>,
+    pack_watcher: Res<MatterPackWatcher>,
+    mut matter_editor: ResMut<MatterEditor>,
+    mut selection: ResMut<Selection>,
+    mut library: ResMut<StampLibrary>,
 ) {
     let (_, gui) = vulkano_windows.get_primary_window_renderer().unwrap();
     let ctx = gui.context();
@@ -69,20 +164,187 @@ pub fn user_interface(
                 size,
             );
             ui.heading("Settings");
-            ui.add(egui::Slider::new(&mut settings.brush_radius, 0.5..=200.0).text("Brush Size"));
-            ui.add(egui::Slider::new(&mut settings.move_steps, 1..=5).text("Move Steps"));
+            ui.horizontal(|ui| {
+                for tool in Tool::iter() {
+                    ui.selectable_value(&mut settings.tool, tool, format!("{:?}", tool));
+                }
+            });
+            // Move steps affect the world, so changes go through replay commands
+            let mut move_steps = settings.move_steps;
+            if ui
+                .add(egui::Slider::new(&mut move_steps, 1..=5).text("Move Steps"))
+                .changed()
+            {
+                replay.execute(
+                    SimCommand::SetMoveSteps(move_steps),
+                    &mut simulator,
+                    &mut settings,
+                );
+            }
             // Selectable matter
+            let registry = simulator.registry();
             egui::ComboBox::from_label("Matter")
-                .selected_text(format!("{:?}", settings.draw_matter))
+                .selected_text(registry.name(settings.draw_matter))
                 .show_ui(ui, |ui| {
-                    for matter in MatterId::iter() {
-                        ui.selectable_value(
-                            &mut settings.draw_matter,
-                            matter,
-                            format!("{:?}", matter),
-                        );
+                    for (matter, name) in registry.iter() {
+                        ui.selectable_value(&mut settings.draw_matter, matter, name);
                     }
                 });
+            match settings.tool {
+                Tool::Brush => {
+                    ui.add(
+                        egui::Slider::new(&mut settings.brush_radius, 0.5..=200.0)
+                            .text("Brush Size"),
+                    );
+                    let draw_matter = settings.draw_matter;
+                    brush_edit(ui, &mut settings.brush, registry, draw_matter);
+                }
+                Tool::Fill => {
+                    ui.checkbox(&mut settings.flood_fill.diagonal, "Fill diagonally (8-connected)");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.flood_fill.max_area)
+                            .prefix("Max fill area (0 = unlimited): "),
+                    );
+                }
+                Tool::Line | Tool::Rectangle | Tool::Ellipse | Tool::Polygon => {
+                    ui.add(
+                        egui::Slider::new(&mut settings.shape_style.thickness, 1.0..=50.0)
+                            .text("Thickness"),
+                    );
+                    if settings.tool != Tool::Line {
+                        ui.checkbox(&mut settings.shape_style.filled, "Filled");
+                    }
+                    let draw_matter = settings.draw_matter;
+                    brush_mode_edit(ui, &mut settings.brush.mode, registry, draw_matter);
+                    if settings.tool == Tool::Polygon {
+                        sized_text(ui, "Enter: draw polygon, Backspace: remove vertex", size);
+                    }
+                }
+                Tool::Select => {
+                    egui::ComboBox::from_label("Paste Mode")
+                        .selected_text(selection.paste_mode.name())
+                        .show_ui(ui, |ui| {
+                            for mode in PasteMode::iter() {
+                                ui.selectable_value(&mut selection.paste_mode, mode, mode.name());
+                            }
+                        });
+                    ui.horizontal(|ui| {
+                        if ui.button("Copy").clicked() {
+                            selection.copy(&simulator);
+                        }
+                        if ui.button("Cut").clicked() {
+                            selection.cut(&mut simulator, &mut settings, &mut replay, &mut undo);
+                        }
+                        if ui.button("Paste").clicked() {
+                            selection.start_paste();
+                        }
+                        if ui.button("Delete").clicked() {
+                            selection.clear(&mut simulator, &mut settings, &mut replay, &mut undo);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.button("Rotate").clicked() {
+                            selection.rotate_clipboard();
+                        }
+                        if ui.button("Flip Horizontal").clicked() {
+                            selection.flip_clipboard(false);
+                        }
+                        if ui.button("Flip Vertical").clicked() {
+                            selection.flip_clipboard(true);
+                        }
+                    });
+                    sized_text(
+                        ui,
+                        "Ctrl+C/X/V: copy/cut/paste, Delete: clear, R: rotate, F/Shift+F: flip",
+                        size,
+                    );
+                }
+                Tool::Stamp => {
+                    let selected = library.selected.and_then(|i| library.entries.get(i));
+                    match selected {
+                        Some(entry) => sized_text(ui, format!("Stamp: {}", entry.stamp.name), size),
+                        None => sized_text(ui, "Pick a stamp in the stamp library", size),
+                    }
+                }
+            }
+            sized_text(
+                ui,
+                match &matter_pack.path {
+                    Some(path) => format!("Matter pack: {} ({:?})", matter_pack.pack.name, path),
+                    None => format!("Matter pack: {}", matter_pack.pack.name),
+                },
+                size,
+            );
+            if let Some(error) = &pack_watcher.error {
+                ui.colored_label(egui::Color32::RED, format!("Matter pack error: {}", error));
+            }
+            ui.checkbox(&mut matter_editor.open, "Matter Editor");
+            ui.checkbox(&mut library.open, "Stamp Library");
+            ui.checkbox(&mut settings.show_population, "Population");
+            ui.checkbox(&mut settings.show_minimap, "Minimap");
+            ui.checkbox(&mut settings.show_rewind, "Rewind");
+            ui.checkbox(&mut simulator.check_conservation, "Check Mass Conservation");
+            egui::ComboBox::from_label("Debug View")
+                .selected_text(simulator.debug_view.name())
+                .show_ui(ui, |ui| {
+                    for view in DebugView::iter() {
+                        ui.selectable_value(&mut simulator.debug_view, view, view.name());
+                    }
+                });
+            ui.checkbox(&mut settings.bloom.enabled, "Bloom");
+            if settings.bloom.enabled {
+                ui.add(
+                    egui::Slider::new(&mut settings.bloom.intensity, 0.0..=4.0)
+                        .text("Bloom Intensity"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut settings.bloom.radius, 1..=MAX_BLOOM_RADIUS)
+                        .text("Bloom Radius"),
+                );
+            }
+            ui.checkbox(&mut settings.show_help, "Help (F1)");
+            ui.heading("Undo");
+            ui.horizontal(|ui| {
+                if ui.button(format!("Undo ({})", undo.num_undo())).clicked() {
+                    if let Some(command) = undo.undo(&simulator) {
+                        replay.execute(command, &mut simulator, &mut settings);
+                    }
+                }
+                if ui.button(format!("Redo ({})", undo.num_redo())).clicked() {
+                    if let Some(command) = undo.redo(&simulator) {
+                        replay.execute(command, &mut simulator, &mut settings);
+                    }
+                }
+            });
+            let mut budget_mb = undo.budget_bytes / (1024 * 1024);
+            if ui
+                .add(egui::Slider::new(&mut budget_mb, 1..=1024).text("Undo Memory (MB)"))
+                .changed()
+            {
+                undo.budget_bytes = budget_mb * 1024 * 1024;
+                undo.enforce_budget();
+            }
+            sized_text(
+                ui,
+                format!(
+                    "Undo memory used: {:.2} MB",
+                    undo.memory_usage_bytes() as f64 / (1024.0 * 1024.0)
+                ),
+                size,
+            );
+            ui.heading("Replay");
+            if replay.is_playing() {
+                sized_text(ui, "Playing replay...", size);
+            } else if replay.is_recording() {
+                if ui.button("Stop & save recording").clicked() {
+                    match replay.stop_recording(&simulator) {
+                        Ok(()) => info!("Saved replay to {:?}", replay.path),
+                        Err(e) => error!("Failed to save replay {:?}: {}", replay.path, e),
+                    }
+                }
+            } else if ui.button("Start recording").clicked() {
+                replay.start_recording(&simulator, &settings);
+            }
         });
     let primary = windows.get_primary().unwrap();
     if primary.cursor_position().is_some() {
@@ -92,8 +354,213 @@ pub fn user_interface(
             ui.label(format!("World: [{:.2}, {:.2}]", world_pos.x, world_pos.y));
             ui.label(format!("Sim: [{:.2}, {:.2}]", sim_pos.x, sim_pos.y));
             if let Some(matter) = simulator.query_matter(sim_pos.as_ivec2()) {
-                ui.label(format!("Matter: {:?}", matter));
+                ui.label(format!("Matter: {}", simulator.registry().name(matter)));
             }
         });
     }
-}
\ No newline at end of file
+}
+
+/// Painter for overlays drawn on top of the canvas
+fn canvas_overlay_painter(ctx: &egui::Context) -> egui::Painter {
+    ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Foreground,
+        egui::Id::new("Canvas overlay"),
+    ))
+}
+
+/// Live outline of the shape being drawn with the shape tools
+pub fn shape_preview(
+    vulkano_windows: NonSend<BevyVulkanoWindows>,
+    windows: Res<Windows>,
+    camera: Res<OrthographicCamera>,
+    settings: Res<DynamicSettings>,
+    draft: Res<ShapeDraft>,
+    current: Res<CurrentMousePos>,
+) {
+    let end = match current.0 {
+        Some(current) => current.canvas_pos().floor().as_ivec2(),
+        None => return,
+    };
+    let shape = match draft.shape(settings.tool, end) {
+        Some(shape) => shape,
+        None => return,
+    };
+    let (_, gui) = vulkano_windows.get_primary_window_renderer().unwrap();
+    let ctx = gui.context();
+    let primary = windows.get_primary().unwrap();
+    let to_screen = |canvas_pos: Vec2| {
+        let pos = canvas_to_window(primary, camera.pos, camera.scale, canvas_pos);
+        egui::pos2(pos.x, pos.y)
+    };
+    let painter = canvas_overlay_painter(&ctx);
+    let stroke = egui::Stroke::new(1.5, egui::Color32::WHITE);
+    let outline = shape
+        .outline()
+        .into_iter()
+        .map(&to_screen)
+        .collect::<Vec<_>>();
+    if shape.is_closed() {
+        painter.add(egui::Shape::closed_line(outline, stroke));
+    } else {
+        painter.add(egui::Shape::line(outline, stroke));
+    }
+    // Mark placed polygon vertices
+    for point in &draft.points {
+        painter.circle_filled(to_screen(point.as_vec2() + 0.5), 3.0, egui::Color32::WHITE);
+    }
+}
+
+/// Outline of the selection and of where moved, pasted or stamped cells would land
+pub fn selection_preview(
+    vulkano_windows: NonSend<BevyVulkanoWindows>,
+    windows: Res<Windows>,
+    camera: Res<OrthographicCamera>,
+    settings: Res<DynamicSettings>,
+    selection: Res<Selection>,
+    library: Res<StampLibrary>,
+    current: Res<CurrentMousePos>,
+) {
+    if settings.tool != Tool::Select && settings.tool != Tool::Stamp {
+        return;
+    }
+    let (_, gui) = vulkano_windows.get_primary_window_renderer().unwrap();
+    let ctx = gui.context();
+    let primary = windows.get_primary().unwrap();
+    // Inclusive cell bounds to screen rectangle
+    let to_screen = |min: IVec2, max: IVec2| {
+        let a = canvas_to_window(primary, camera.pos, camera.scale, min.as_vec2());
+        let b = canvas_to_window(primary, camera.pos, camera.scale, (max + 1).as_vec2());
+        egui::Rect::from_two_pos(egui::pos2(a.x, a.y), egui::pos2(b.x, b.y))
+    };
+    let painter = canvas_overlay_painter(&ctx);
+    if let (Tool::Select, Some((min, max))) = (settings.tool, selection.bounds) {
+        painter.rect_stroke(
+            to_screen(min, max),
+            0.0,
+            egui::Stroke::new(1.5, egui::Color32::YELLOW),
+        );
+    }
+    let cursor = match current.0 {
+        Some(current) => current.canvas_pos().floor().as_ivec2(),
+        None => return,
+    };
+    let landing = match (
+        selection.move_offset,
+        selection.bounds,
+        &selection.clipboard,
+    ) {
+        _ if settings.tool == Tool::Stamp => library.placing.as_ref().and_then(|placing| {
+            let pos = library.place_pos(cursor)?;
+            Some((pos, placing.size().as_ivec2() - 1))
+        }),
+        (Some(offset), Some((min, max)), _) => Some((cursor - offset, max - min)),
+        (None, _, Some(clipboard)) if selection.pasting => selection
+            .paste_pos(cursor)
+            .map(|pos| (pos, clipboard.size().as_ivec2() - 1)),
+        _ => None,
+    };
+    if let Some((min, extent)) = landing {
+        painter.rect_stroke(
+            to_screen(min, min + extent),
+            0.0,
+            egui::Stroke::new(1.5, egui::Color32::WHITE),
+        );
+    }
+}
+
+/// Overlay listing the input bindings
+pub fn help_overlay(
+    vulkano_windows: NonSend<BevyVulkanoWindows>,
+    mut settings: ResMut<DynamicSettings>,
+) {
+    if !settings.show_help {
+        return;
+    }
+    let (_, gui) = vulkano_windows.get_primary_window_renderer().unwrap();
+    let ctx = gui.context();
+    egui::Window::new("Help")
+        .open(&mut settings.show_help)
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-10.0, 10.0))
+        .show(&ctx, |ui| {
+            egui::Grid::new("Input bindings")
+                .striped(true)
+                .show(ui, |ui| {
+                    for (input, action) in INPUT_BINDINGS {
+                        ui.label(input);
+                        ui.label(action);
+                        ui.end_row();
+                    }
+                });
+        });
+}
+
+/// Timeline to scrub back through recent simulation history
+pub fn rewind_interface(
+    vulkano_windows: NonSend<BevyVulkanoWindows>,
+    mut settings: ResMut<DynamicSettings>,
+    mut simulator: ResMut<CASimulator>,
+    mut replay: ResMut<ReplayState>,
+    mut history: ResMut<History>,
+    mut undo: ResMut<UndoStack>,
+) {
+    if !settings.show_rewind {
+        return;
+    }
+    let (_, gui) = vulkano_windows.get_primary_window_renderer().unwrap();
+    let ctx = gui.context();
+    let mut open = settings.show_rewind;
+    egui::Window::new("Rewind")
+        .open(&mut open)
+        .default_pos(egui::pos2(10.0, 600.0))
+        .show(&ctx, |ui| {
+            let size = 15.0;
+            if replay.is_playing() {
+                sized_text(ui, "Not available while a replay plays", size);
+                return;
+            }
+            if history.is_empty() {
+                sized_text(ui, "No history yet", size);
+            } else {
+                let last = history.len() - 1;
+                let mut index = history.inspecting.unwrap_or(last);
+                let step = history.sim_step(index).unwrap_or_default();
+                if ui
+                    .add(egui::Slider::new(&mut index, 0..=last).text(format!("Step {}", step)))
+                    .changed()
+                {
+                    // Rewinding can't be replayed, so it ends the recording
+                    if replay.is_recording() {
+                        warn!("Rewinding ends the recording");
+                        if let Err(e) = replay.stop_recording(&simulator) {
+                            error!("Failed to save replay {:?}: {}", replay.path, e);
+                        }
+                    }
+                    replay.execute(SimCommand::SetPaused(true), &mut simulator, &mut settings);
+                    history.rewind(&mut simulator, index);
+                    // Edits refer to cells of the grid we left
+                    undo.clear();
+                }
+                if history.inspecting.is_some() && ui.button("Resume from here").clicked() {
+                    replay.execute(SimCommand::SetPaused(false), &mut simulator, &mut settings);
+                }
+            }
+            ui.add(egui::Slider::new(&mut history.interval, 1..=300).text("Snapshot Interval"));
+            if ui
+                .add(egui::Slider::new(&mut history.capacity, 1..=600).text("Snapshots"))
+                .changed()
+            {
+                history.shrink_to_capacity();
+            }
+            sized_text(
+                ui,
+                format!(
+                    "History: {} snapshots, {:.1} MB gpu memory",
+                    history.len(),
+                    (history.len() * (CANVAS_SIZE_X * CANVAS_SIZE_Y) as usize * 4) as f64
+                        / (1024.0 * 1024.0)
+                ),
+                size,
+            );
+        });
+    settings.show_rewind = open;
+}
@@ -1,13 +1,32 @@
+mod bloom;
+mod brush;
 mod ca_simulator;
 mod camera;
+mod conservation;
+mod debug_view;
+mod draw_queue;
+mod flood_fill;
 mod gui;
 mod matter;
+mod matter_editor;
+mod minimap;
+mod pack_watcher;
+mod population;
 mod quad_pipeline;
 mod render;
+mod replay;
+mod rewind;
+mod screenshot;
+mod selection;
+mod shapes;
+mod stamps;
 mod timer;
+mod undo;
 mod utils;
 mod vertex;
 
+use std::path::PathBuf;
+
 use bevy::{
     input::mouse::MouseWheel,
     prelude::*,
@@ -17,15 +36,34 @@ use bevy::{
 use bevy_vulkano::{
     egui_winit_vulkano::egui::Visuals, BevyVulkanoWindows, VulkanoWinitConfig, VulkanoWinitPlugin,
 };
+use strum_macros::EnumIter;
+use vulkano_util::context::VulkanoContext;
 
 use crate::{
+    bloom::BloomSettings,
+    brush::Brush,
     ca_simulator::CASimulator,
-    camera::OrthographicCamera,
-    gui::user_interface,
-    matter::MatterId,
+    camera::{CameraController, OrthographicCamera, CAMERA_ZOOM_STEP},
+    conservation::conservation_interface,
+    flood_fill::FloodFill,
+    gui::{help_overlay, rewind_interface, selection_preview, shape_preview, user_interface},
+    matter::{
+        ActiveMatterPack, MatterId, MatterPack, MatterPackError, MatterRegistry, ReactionGraph,
+    },
+    matter_editor::{matter_editor_interface, MatterEditor},
+    minimap::{minimap_interface, Minimap},
+    pack_watcher::{watch_matter_pack, MatterPackWatcher},
+    population::{population_interface, population_stats, PopulationStats},
     render::FillScreenRenderPass,
+    replay::{grid_checksum, play_headless, Replay, ReplayState, SimCommand},
+    rewind::History,
+    screenshot::{canvas_camera, check_golden, GoldenResult, OffscreenRenderer},
+    selection::{paste_edit, Selection},
+    shapes::{Shape, ShapeDraft, ShapeStyle},
+    stamps::{stamp_library_interface, StampLibrary},
     timer::{PerformanceTimer, RenderTimer, SimTimer},
-    utils::{cursor_to_world, MousePos},
+    undo::UndoStack,
+    utils::{cursor_to_world, line_bounds, MousePos},
 };
 
 pub const WIDTH: f32 = 1920.0;
@@ -43,21 +81,152 @@ pub const CLEAR_COLOR: [f32; 4] = if GREY_SCALE { [0.8; 4] } else { [0.0; 4] };
 pub const EMPTY_COLOR: u32 = if GREY_SCALE { 0xffffffff } else { 0x0 };
 pub const CAMERA_MOVE_SPEED: f32 = 200.0;
 
+/// What the left mouse button does on the canvas
+#[derive(EnumIter, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Tool {
+    Brush,
+    /// Flood fill the region under the cursor
+    Fill,
+    /// Drag from start to end
+    Line,
+    /// Drag between opposite corners
+    Rectangle,
+    /// Drag between opposite corners of the bounding rectangle
+    Ellipse,
+    /// Click to place vertices, Enter draws the polygon & Backspace removes the last vertex
+    Polygon,
+    /// Drag to select a region, drag the selection to move it
+    Select,
+    /// Place the stamp selected in the stamp library
+    Stamp,
+}
+
 pub struct DynamicSettings {
+    pub tool: Tool,
     pub brush_radius: f32,
+    pub brush: Brush,
+    pub flood_fill: FloodFill,
+    pub shape_style: ShapeStyle,
     pub move_steps: u32,
     pub draw_matter: MatterId,
     pub is_paused: bool,
+    /// Step once on next simulation tick even if paused
+    pub step_once: bool,
+    /// Show the overlay listing input bindings
+    pub show_help: bool,
+    /// Count matters & show their population
+    pub show_population: bool,
+    /// Show the minimap window
+    pub show_minimap: bool,
+    /// Show the rewind timeline window
+    pub show_rewind: bool,
+    pub bloom: BloomSettings,
 }
 
 impl Default for DynamicSettings {
     fn default() -> Self {
         Self {
+            tool: Tool::Brush,
             brush_radius: 4.0,
+            brush: Brush::default(),
+            flood_fill: FloodFill::default(),
+            shape_style: ShapeStyle::default(),
             move_steps: 1,
-            draw_matter: MatterId::Sand,
+            draw_matter: MatterId::SAND,
             is_paused: false,
+            step_once: false,
+            show_help: false,
+            show_population: false,
+            show_minimap: false,
+            show_rewind: false,
+            bloom: BloomSettings::default(),
+        }
+    }
+}
+
+/// Command line options
+#[derive(Debug, Clone)]
+pub struct LaunchOptions {
+    /// Replay file to play back on start
+    pub replay: Option<PathBuf>,
+    /// Where recordings are saved
+    pub record: PathBuf,
+    /// Play the replay without a window and print the checksum of the resulting world
+    pub headless: bool,
+    /// Matter pack to load instead of the built-in matters
+    pub matters: Option<PathBuf>,
+    /// Print an analysis of the matter pack's reactions instead of running
+    pub analyze: bool,
+    /// Where the analysis writes the reaction graph as Graphviz DOT
+    pub dot: Option<PathBuf>,
+    /// Folder of the stamp library
+    pub stamps: PathBuf,
+    /// Check that movement passes conserve matter while playing headless
+    pub check_conservation: bool,
+    /// Where headless play saves a screenshot of the resulting world
+    pub screenshot: Option<PathBuf>,
+    /// Golden image headless play compares its screenshot to, created if missing
+    pub golden: Option<PathBuf>,
+    /// Width & height of headless screenshots
+    pub screenshot_size: [u32; 2],
+}
+
+impl LaunchOptions {
+    /// Parses `--replay <file>`, `--record <file>`, `--headless`, `--matters <file>`, `--analyze`,
+    /// `--dot <file>`, `--stamps <folder>`, `--check-conservation`, `--screenshot <file>`,
+    /// `--golden <file>` and `--screenshot-size <width>x<height>`
+    pub fn from_args(args: impl Iterator<Item = String>) -> LaunchOptions {
+        let mut options = LaunchOptions {
+            replay: None,
+            record: PathBuf::from("replay.ron"),
+            headless: false,
+            matters: None,
+            analyze: false,
+            dot: None,
+            stamps: PathBuf::from("stamps"),
+            check_conservation: false,
+            screenshot: None,
+            golden: None,
+            screenshot_size: [WIDTH as u32, HEIGHT as u32],
+        };
+        let mut args = args.skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--replay" => options.replay = args.next().map(PathBuf::from),
+                "--record" => {
+                    if let Some(path) = args.next() {
+                        options.record = PathBuf::from(path);
+                    }
+                }
+                "--headless" => options.headless = true,
+                "--matters" => options.matters = args.next().map(PathBuf::from),
+                "--analyze" => options.analyze = true,
+                "--dot" => {
+                    options.analyze = true;
+                    options.dot = args.next().map(PathBuf::from);
+                }
+                "--stamps" => {
+                    if let Some(path) = args.next() {
+                        options.stamps = PathBuf::from(path);
+                    }
+                }
+                "--check-conservation" => options.check_conservation = true,
+                "--screenshot" => options.screenshot = args.next().map(PathBuf::from),
+                "--golden" => options.golden = args.next().map(PathBuf::from),
+                "--screenshot-size" => {
+                    let size = args.next().and_then(|size| {
+                        let (width, height) = size.split_once('x')?;
+                        Some([width.parse().ok()?, height.parse().ok()?])
+                    });
+                    match size {
+                        Some(size) => options.screenshot_size = size,
+                        None => eprintln!("--screenshot-size expects <width>x<height>"),
+                    }
+                }
+                _ => eprintln!("Unknown argument: {}", arg),
+            }
         }
+        options
     }
 }
 
@@ -68,7 +237,17 @@ pub struct PreviousMousePos(pub Option<MousePos>);
 pub struct CurrentMousePos(pub Option<MousePos>);
 
 fn main() {
+    let options = LaunchOptions::from_args(std::env::args());
+    if options.analyze {
+        analyze_matter_pack(&options);
+        return;
+    }
+    if options.headless {
+        run_headless(&options);
+        return;
+    }
     App::new()
+        .insert_resource(options)
         .insert_non_send_resource(VulkanoWinitConfig::default())
         .insert_resource(WindowDescriptor {
             width: WIDTH,
@@ -94,6 +273,10 @@ fn main() {
         .add_system(update_camera)
         .add_system(update_mouse)
         .add_system(draw_matter)
+        .add_system(undo_redo)
+        .add_system(selection_actions)
+        .add_system(watch_matter_pack)
+        .add_system(population_stats)
         // Simulate only SIM_FPS times per second
         .add_system_set_to_stage(
             CoreStage::Update,
@@ -103,13 +286,156 @@ fn main() {
         )
         // Gui
         .add_system(user_interface.after(simulate))
+        .add_system(rewind_interface.after(simulate))
+        .add_system(matter_editor_interface.after(simulate))
+        .add_system(stamp_library_interface.after(simulate))
+        .add_system(help_overlay.after(simulate))
+        .add_system(population_interface.after(simulate))
+        .add_system(conservation_interface.after(simulate))
+        .add_system(minimap_interface.after(simulate))
+        .add_system(shape_preview.after(simulate))
+        .add_system(selection_preview.after(simulate))
         // Render after update
         .add_system_to_stage(CoreStage::PostUpdate, render)
         .run();
 }
 
+/// Play a replay without a window and print the checksum of the resulting world
+fn run_headless(options: &LaunchOptions) {
+    let path = match &options.replay {
+        Some(path) => path,
+        None => {
+            eprintln!("--headless requires --replay <file>");
+            return;
+        }
+    };
+    let replay = match Replay::load(path) {
+        Ok(replay) => replay,
+        Err(e) => {
+            eprintln!("Failed to load replay {:?}: {}", path, e);
+            return;
+        }
+    };
+    let vulkano_context = VulkanoContext::default();
+    let mut simulator = CASimulator::new(vulkano_context.compute_queue());
+    match load_matter_pack(options) {
+        Ok((_, registry)) => simulator.set_registry(registry),
+        Err(e) => {
+            eprintln!("Failed to load matter pack {:?}: {}", options.matters, e);
+            return;
+        }
+    }
+    simulator.check_conservation = options.check_conservation;
+    play_headless(&mut simulator, &replay);
+    println!(
+        "Replayed {} commands over {} steps, world checksum: {:016x}",
+        replay.commands.len(),
+        simulator.sim_step,
+        grid_checksum(&simulator.read_grid())
+    );
+    if options.check_conservation {
+        println!(
+            "Mass conservation violations: {}",
+            simulator.conservation_violations.len()
+        );
+        for violation in &simulator.conservation_violations {
+            println!(
+                "  step {}, {}: {:?} in {:?}",
+                violation.sim_step,
+                violation.pass.name(),
+                violation.changes,
+                violation.regions
+            );
+        }
+    }
+    if options.screenshot.is_some() || options.golden.is_some() {
+        save_screenshot(options, &vulkano_context, &mut simulator);
+    }
+}
+
+/// Render the world framed like the window on start & save it or compare it to the golden image
+fn save_screenshot(
+    options: &LaunchOptions,
+    vulkano_context: &VulkanoContext,
+    simulator: &mut CASimulator,
+) {
+    simulator.color();
+    let mut renderer = OffscreenRenderer::new(vulkano_context.graphics_queue());
+    let size = options.screenshot_size;
+    let screenshot = renderer.render(
+        simulator,
+        canvas_camera(size),
+        &BloomSettings::default(),
+        size,
+    );
+    if let Some(path) = &options.screenshot {
+        match screenshot.save(path) {
+            Ok(()) => println!("Saved screenshot to {:?}", path),
+            Err(e) => eprintln!("Failed to save screenshot {:?}: {}", path, e),
+        }
+    }
+    if let Some(path) = &options.golden {
+        match check_golden(&screenshot, path) {
+            Ok(GoldenResult::Created) => println!("Created golden image {:?}", path),
+            Ok(GoldenResult::Matches) => println!("Screenshot matches golden image {:?}", path),
+            Ok(GoldenResult::Differs(difference)) => {
+                eprintln!(
+                    "Screenshot differs from golden image {:?}, largest difference: {:?}",
+                    path, difference
+                );
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("Failed to check golden image {:?}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Print the reaction graph analysis of the matter pack and optionally write it as DOT
+fn analyze_matter_pack(options: &LaunchOptions) {
+    let (matter_pack, registry) = match load_matter_pack(options) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Failed to load matter pack {:?}: {}", options.matters, e);
+            return;
+        }
+    };
+    let graph = ReactionGraph::new(&registry);
+    print!("{}", graph.report());
+    if let Some(path) = &options.dot {
+        match std::fs::write(path, graph.to_dot(&matter_pack.pack.name)) {
+            Ok(()) => println!("Wrote reaction graph to {:?}", path),
+            Err(e) => eprintln!("Failed to write {:?}: {}", path, e),
+        }
+    }
+}
+
+/// Load & resolve the matter pack given in options, or the built-in matters if none was given
+fn load_matter_pack(
+    options: &LaunchOptions,
+) -> Result<(ActiveMatterPack, MatterRegistry), MatterPackError> {
+    let matter_pack = match &options.matters {
+        Some(path) => ActiveMatterPack {
+            pack: MatterPack::load(path)?,
+            path: Some(path.clone()),
+        },
+        None => ActiveMatterPack {
+            pack: MatterPack::builtin(),
+            path: None,
+        },
+    };
+    let registry = matter_pack.pack.resolve(None)?;
+    Ok((matter_pack, registry))
+}
+
 /// Creates our simulation & render pipelines
-fn setup(mut commands: Commands, vulkano_windows: NonSend<BevyVulkanoWindows>) {
+fn setup(
+    mut commands: Commands,
+    vulkano_windows: NonSend<BevyVulkanoWindows>,
+    options: Res<LaunchOptions>,
+) {
     let (primary_window_renderer, gui) = vulkano_windows.get_primary_window_renderer().unwrap();
     // Create our render pass
     let fill_screen = FillScreenRenderPass::new(
@@ -119,24 +445,79 @@ fn setup(mut commands: Commands, vulkano_windows: NonSend<BevyVulkanoWindows>) {
 
     // Use same queue for compute
     let mut sim_pipeline = CASimulator::new(primary_window_renderer.compute_queue());
+    // Load matters, built-in matters are used if the pack can't be loaded
+    let (mut matter_pack, registry) = match load_matter_pack(&options) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            bevy::log::error!("Failed to load matter pack {:?}: {}", options.matters, e);
+            (
+                ActiveMatterPack {
+                    pack: MatterPack::builtin(),
+                    path: None,
+                },
+                MatterRegistry::builtin(),
+            )
+        }
+    };
+    sim_pipeline.set_registry(registry);
     // Ensure bg is white for empty when grey scale...
     if GREY_SCALE {
         let start = Vec2::new(CANVAS_SIZE_X as f32, CANVAS_SIZE_Y as f32) / 2.0;
         let end = start;
-        sim_pipeline.draw_matter(start, end, CANVAS_SIZE_X as f32, MatterId::Empty);
+        sim_pipeline.draw_matter(
+            start,
+            end,
+            CANVAS_SIZE_X as f32,
+            MatterId::EMPTY,
+            Brush::default(),
+        );
     }
     // Create simple orthographic camera
     let mut camera = OrthographicCamera::default();
     // Zoom camera to fit vertical pixels
     camera.zoom_to_fit_vertical_pixels(CANVAS_SIZE_Y, HEIGHT as u32);
+    // Play back a replay if one was given
+    let mut settings = DynamicSettings::default();
+    let replay_state = match &options.replay {
+        Some(path) => match Replay::load(path) {
+            Ok(replay) => {
+                // The replay's matters rather than the pack file's
+                if let Some(start) = &replay.start {
+                    matter_pack = ActiveMatterPack {
+                        pack: start.matters.pack.clone(),
+                        path: None,
+                    };
+                }
+                let record = options.record.clone();
+                ReplayState::playing(replay, record, &mut sim_pipeline, &mut settings)
+            }
+            Err(e) => {
+                bevy::log::error!("Failed to load replay {:?}: {}", path, e);
+                ReplayState::new(options.record.clone())
+            }
+        },
+        None => ReplayState::new(options.record.clone()),
+    };
     // Simulation performance timer
     let perf_timer = PerformanceTimer::new();
     let render_timer = PerformanceTimer::new();
     // Insert resources
     commands.insert_resource(fill_screen);
     commands.insert_resource(sim_pipeline);
+    commands.insert_resource(CameraController::new(&camera));
     commands.insert_resource(camera);
-    commands.insert_resource(DynamicSettings::default());
+    commands.insert_resource(settings);
+    commands.insert_resource(replay_state);
+    commands.insert_resource(MatterPackWatcher::new(&matter_pack));
+    commands.insert_resource(MatterEditor::default());
+    commands.insert_resource(Minimap::default());
+    commands.insert_resource(matter_pack);
+    commands.insert_resource(UndoStack::default());
+    commands.insert_resource(ShapeDraft::default());
+    commands.insert_resource(Selection::default());
+    commands.insert_resource(StampLibrary::new(options.stamps.clone()));
+    commands.insert_resource(History::default());
+    commands.insert_resource(PopulationStats::default());
     commands.insert_resource(PreviousMousePos(None));
     commands.insert_resource(CurrentMousePos(None));
     commands.insert_resource(SimTimer(perf_timer));
@@ -151,35 +532,327 @@ fn setup(mut commands: Commands, vulkano_windows: NonSend<BevyVulkanoWindows>) {
     }
 }
 
-/// Draw matter to our grid
+/// Draw matter to our grid with the selected tool
 fn draw_matter(
     mut simulator: ResMut<CASimulator>,
     prev: Res<PreviousMousePos>,
     current: Res<CurrentMousePos>,
-    settings: Res<DynamicSettings>,
+    mut settings: ResMut<DynamicSettings>,
+    mut replay: ResMut<ReplayState>,
+    mut undo: ResMut<UndoStack>,
+    mut draft: ResMut<ShapeDraft>,
+    mut selection: ResMut<Selection>,
+    library: Res<StampLibrary>,
+    camera_controller: Res<CameraController>,
     mouse_button_input: Res<Input<MouseButton>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    vulkano_windows: NonSend<BevyVulkanoWindows>,
+) {
+    if replay.is_playing() {
+        draft.clear();
+        return;
+    }
+    let typing = gui_wants_keyboard(&vulkano_windows);
+    // Whole stroke is a single edit
+    if mouse_button_input.just_released(MouseButton::Left)
+        || mouse_button_input.just_released(MouseButton::Right)
+    {
+        undo.end_edit();
+    }
+    // Clicks on egui windows, e.g. the minimap, are not meant for the canvas
+    if gui_wants_pointer(&vulkano_windows) {
+        return;
+    }
+    let current = match current.0 {
+        Some(current) => current,
+        None => return,
+    };
+    // Pick the matter under the cursor with middle click (not a camera drag) or Alt + click
+    let alt = keyboard_input.pressed(KeyCode::LAlt) || keyboard_input.pressed(KeyCode::RAlt);
+    if (mouse_button_input.just_released(MouseButton::Middle) && !camera_controller.dragged())
+        || (alt && mouse_button_input.just_pressed(MouseButton::Left))
+    {
+        if let Some(matter) = simulator.query_matter(current.canvas_pos().floor().as_ivec2()) {
+            settings.draw_matter = matter;
+        }
+        return;
+    }
+    if alt {
+        return;
+    }
+    // Erase with the current brush
+    if mouse_button_input.pressed(MouseButton::Right) {
+        let eraser = settings.brush.eraser();
+        brush_stroke(
+            MatterId::EMPTY,
+            eraser,
+            prev.0,
+            current,
+            &mut simulator,
+            &mut settings,
+            &mut replay,
+            &mut undo,
+        );
+        return;
+    }
+    match settings.tool {
+        Tool::Brush if mouse_button_input.pressed(MouseButton::Left) => {
+            let matter = settings.draw_matter;
+            let brush = settings.brush;
+            brush_stroke(
+                matter,
+                brush,
+                prev.0,
+                current,
+                &mut simulator,
+                &mut settings,
+                &mut replay,
+                &mut undo,
+            );
+        }
+        Tool::Fill if mouse_button_input.just_pressed(MouseButton::Left) => {
+            let pos = current.canvas_pos().floor().as_ivec2();
+            // The grid is read once for the fill & its undo
+            let filled =
+                simulator.plan_flood_fill(pos, settings.draw_matter, &settings.flood_fill);
+            if let Some(filled) = filled {
+                let command = SimCommand::FloodFill {
+                    pos: pos.into(),
+                    matter: settings.draw_matter,
+                    fill: settings.flood_fill,
+                };
+                if replay.record(&command, &simulator) {
+                    simulator.apply_flood_fill(&filled);
+                    undo.capture_snapshot(filled.before);
+                }
+            }
+        }
+        Tool::Line | Tool::Rectangle | Tool::Ellipse => {
+            let pos = current.canvas_pos().floor().as_ivec2();
+            if mouse_button_input.just_pressed(MouseButton::Left) {
+                draft.start = Some(pos);
+            } else if mouse_button_input.just_released(MouseButton::Left) {
+                if let Some(shape) = draft.shape(settings.tool, pos) {
+                    draw_shape(shape, &mut simulator, &mut settings, &mut replay, &mut undo);
+                }
+                draft.clear();
+            }
+        }
+        Tool::Polygon => {
+            if mouse_button_input.just_pressed(MouseButton::Left) {
+                draft.points.push(current.canvas_pos().floor().as_ivec2());
+            } else if !typing && keyboard_input.just_pressed(KeyCode::Back) {
+                draft.points.pop();
+            } else if !typing && keyboard_input.just_pressed(KeyCode::Return) {
+                // The last placed vertex closes the polygon instead of the cursor
+                if let Some(last) = draft.points.pop() {
+                    if let Some(shape) = draft.shape(Tool::Polygon, last) {
+                        draw_shape(shape, &mut simulator, &mut settings, &mut replay, &mut undo);
+                    }
+                }
+                draft.clear();
+            }
+        }
+        Tool::Select => {
+            let pos = current.canvas_pos().floor().as_ivec2();
+            if selection.pasting {
+                if mouse_button_input.just_pressed(MouseButton::Left) {
+                    if let Some(paste_pos) = selection.paste_pos(pos) {
+                        selection.paste(
+                            paste_pos,
+                            &mut simulator,
+                            &mut settings,
+                            &mut replay,
+                            &mut undo,
+                        );
+                    }
+                }
+            } else if mouse_button_input.just_pressed(MouseButton::Left) {
+                // Dragging inside the selection moves it, elsewhere starts a new selection
+                if selection.contains(pos) {
+                    selection.move_offset = selection.bounds.map(|(min, _)| pos - min);
+                } else {
+                    selection.deselect();
+                    selection.drag_start = Some(pos);
+                }
+            } else if mouse_button_input.pressed(MouseButton::Left) {
+                if let Some(start) = selection.drag_start {
+                    selection.bounds = Some((start.min(pos), start.max(pos)));
+                }
+            } else if mouse_button_input.just_released(MouseButton::Left) {
+                if let Some(offset) = selection.move_offset.take() {
+                    selection.move_to(
+                        pos - offset,
+                        &mut simulator,
+                        &mut settings,
+                        &mut replay,
+                        &mut undo,
+                    );
+                }
+                if selection.drag_start.take().is_some() {
+                    selection.clamp_to_canvas();
+                }
+            }
+        }
+        Tool::Stamp if mouse_button_input.just_pressed(MouseButton::Left) => {
+            let cursor = current.canvas_pos().floor().as_ivec2();
+            if let (Some(pos), Some(region)) = (library.place_pos(cursor), &library.placing) {
+                paste_edit(
+                    pos,
+                    region.clone(),
+                    library.paste_mode,
+                    &mut simulator,
+                    &mut settings,
+                    &mut replay,
+                    &mut undo,
+                );
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Copy (Ctrl+C), cut (Ctrl+X), paste (Ctrl+V) & delete (Delete) the selection. While pasting or
+/// placing a stamp R rotates and F (Shift+F) flips horizontally (vertically).
+fn selection_actions(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut simulator: ResMut<CASimulator>,
+    mut settings: ResMut<DynamicSettings>,
+    mut replay: ResMut<ReplayState>,
+    mut undo: ResMut<UndoStack>,
+    mut selection: ResMut<Selection>,
+    mut library: ResMut<StampLibrary>,
+    vulkano_windows: NonSend<BevyVulkanoWindows>,
 ) {
-    if let Some(current) = current.0 {
-        if mouse_button_input.pressed(MouseButton::Left) {
-            let end = current.canvas_pos();
-            let start = if let Some(prev) = prev.0 {
-                prev.canvas_pos()
-            } else {
-                end
-            };
-            simulator.draw_matter(start, end, settings.brush_radius, settings.draw_matter);
+    if replay.is_playing() || gui_wants_keyboard(&vulkano_windows) {
+        return;
+    }
+    let ctrl =
+        keyboard_input.pressed(KeyCode::LControl) || keyboard_input.pressed(KeyCode::RControl);
+    let shift = keyboard_input.pressed(KeyCode::LShift) || keyboard_input.pressed(KeyCode::RShift);
+    if settings.tool == Tool::Stamp {
+        if keyboard_input.just_pressed(KeyCode::R) {
+            library.rotate();
+        } else if keyboard_input.just_pressed(KeyCode::F) {
+            library.flip(shift);
         }
+        return;
+    }
+    if settings.tool != Tool::Select {
+        return;
+    }
+    if ctrl && keyboard_input.just_pressed(KeyCode::C) {
+        selection.copy(&simulator);
+    } else if ctrl && keyboard_input.just_pressed(KeyCode::X) {
+        selection.cut(&mut simulator, &mut settings, &mut replay, &mut undo);
+    } else if ctrl && keyboard_input.just_pressed(KeyCode::V) {
+        selection.start_paste();
+    } else if keyboard_input.just_pressed(KeyCode::Delete) {
+        selection.clear(&mut simulator, &mut settings, &mut replay, &mut undo);
+    } else if selection.pasting && keyboard_input.just_pressed(KeyCode::R) {
+        selection.rotate_clipboard();
+    } else if selection.pasting && keyboard_input.just_pressed(KeyCode::F) {
+        selection.flip_clipboard(shift);
+    }
+}
+
+/// Draw matter with a brush from the previous to the current mouse position
+fn brush_stroke(
+    matter: MatterId,
+    brush: Brush,
+    prev: Option<MousePos>,
+    current: MousePos,
+    simulator: &mut CASimulator,
+    settings: &mut DynamicSettings,
+    replay: &mut ReplayState,
+    undo: &mut UndoStack,
+) {
+    let end = current.canvas_pos();
+    let start = if let Some(prev) = prev {
+        prev.canvas_pos()
+    } else {
+        end
+    };
+    let (min, max) = line_bounds(start, end, settings.brush_radius);
+    undo.capture(simulator, min, max);
+    let command = SimCommand::Draw {
+        start: start.into(),
+        end: end.into(),
+        radius: settings.brush_radius,
+        matter,
+        brush,
+    };
+    replay.execute(command, simulator, settings);
+}
+
+/// Draw a finished shape with the selected matter as a single edit
+fn draw_shape(
+    shape: Shape,
+    simulator: &mut CASimulator,
+    settings: &mut DynamicSettings,
+    replay: &mut ReplayState,
+    undo: &mut UndoStack,
+) {
+    let (min, max) = shape.bounds(&settings.shape_style);
+    undo.capture(simulator, min, max);
+    let command = SimCommand::DrawShape {
+        shape,
+        style: settings.shape_style,
+        matter: settings.draw_matter,
+        mode: settings.brush.mode,
+    };
+    replay.execute(command, simulator, settings);
+    undo.end_edit();
+}
+
+/// Undo (Ctrl+Z) & redo (Ctrl+Y or Ctrl+Shift+Z) edits
+fn undo_redo(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut simulator: ResMut<CASimulator>,
+    mut settings: ResMut<DynamicSettings>,
+    mut replay: ResMut<ReplayState>,
+    mut undo: ResMut<UndoStack>,
+    vulkano_windows: NonSend<BevyVulkanoWindows>,
+) {
+    if replay.is_playing() || gui_wants_keyboard(&vulkano_windows) {
+        return;
+    }
+    let ctrl =
+        keyboard_input.pressed(KeyCode::LControl) || keyboard_input.pressed(KeyCode::RControl);
+    let shift = keyboard_input.pressed(KeyCode::LShift) || keyboard_input.pressed(KeyCode::RShift);
+    if !ctrl {
+        return;
+    }
+    let command = if keyboard_input.just_pressed(KeyCode::Y)
+        || (shift && keyboard_input.just_pressed(KeyCode::Z))
+    {
+        undo.redo(&simulator)
+    } else if keyboard_input.just_pressed(KeyCode::Z) {
+        undo.undo(&simulator)
+    } else {
+        None
+    };
+    if let Some(command) = command {
+        replay.execute(command, &mut simulator, &mut settings);
     }
 }
 
 /// Step simulation
 fn simulate(
     mut sim_pipeline: ResMut<CASimulator>,
-    settings: Res<DynamicSettings>,
+    mut settings: ResMut<DynamicSettings>,
+    mut replay: ResMut<ReplayState>,
+    mut history: ResMut<History>,
     mut sim_timer: ResMut<SimTimer>,
 ) {
     sim_timer.0.start();
-    sim_pipeline.step(settings.move_steps, settings.is_paused);
+    if !settings.is_paused || settings.step_once {
+        history.resume(&mut sim_pipeline);
+    }
+    replay.step(&mut sim_pipeline, &mut settings);
+    if !settings.is_paused {
+        history.record(&sim_pipeline);
+    }
     sim_timer.0.time_it();
 }
 
@@ -189,6 +862,7 @@ fn render(
     mut fill_screen: ResMut<FillScreenRenderPass>,
     sim_pipeline: Res<CASimulator>,
     camera: Res<OrthographicCamera>,
+    settings: Res<DynamicSettings>,
     mut render_timer: ResMut<RenderTimer>,
 ) {
     render_timer.0.start();
@@ -211,6 +885,8 @@ fn render(
         before,
         *camera,
         canvas_image,
+        Some(sim_pipeline.emission_image()),
+        &settings.bloom,
         final_image.clone(),
         CLEAR_COLOR,
         false,
@@ -248,19 +924,43 @@ fn update_mouse(
     }
 }
 
+/// Whether an egui widget, e.g. a text field, takes the keyboard input
+fn gui_wants_keyboard(vulkano_windows: &BevyVulkanoWindows) -> bool {
+    let (_, gui) = vulkano_windows.get_primary_window_renderer().unwrap();
+    gui.context().wants_keyboard_input()
+}
+
+/// Whether the pointer is over or dragging an egui widget
+fn gui_wants_pointer(vulkano_windows: &BevyVulkanoWindows) -> bool {
+    let (_, gui) = vulkano_windows.get_primary_window_renderer().unwrap();
+    gui.context().wants_pointer_input()
+}
+
 /// Input actions for camera movement, zoom and pausing
 fn input_actions(
     time: Res<Time>,
+    windows: Res<Windows>,
     mut camera: ResMut<OrthographicCamera>,
+    mut camera_controller: ResMut<CameraController>,
     keyboard_input: Res<Input<KeyCode>>,
+    mouse_button_input: Res<Input<MouseButton>>,
     mut mouse_input_events: EventReader<MouseWheel>,
     mut settings: ResMut<DynamicSettings>,
+    mut simulator: ResMut<CASimulator>,
+    mut replay: ResMut<ReplayState>,
+    vulkano_windows: NonSend<BevyVulkanoWindows>,
 ) {
+    let window = windows.get_primary().unwrap();
+    // Keys typed into a text field are not shortcuts
+    let typing = gui_wants_keyboard(&vulkano_windows);
+    let over_gui = gui_wants_pointer(&vulkano_windows);
+    let pressed = |key| !typing && keyboard_input.pressed(key);
+    let just_pressed = |key| !typing && keyboard_input.just_pressed(key);
     // Move camera with arrows & WASD
-    let up = keyboard_input.pressed(KeyCode::W) || keyboard_input.pressed(KeyCode::Up);
-    let down = keyboard_input.pressed(KeyCode::S) || keyboard_input.pressed(KeyCode::Down);
-    let left = keyboard_input.pressed(KeyCode::A) || keyboard_input.pressed(KeyCode::Left);
-    let right = keyboard_input.pressed(KeyCode::D) || keyboard_input.pressed(KeyCode::Right);
+    let up = pressed(KeyCode::W) || pressed(KeyCode::Up);
+    let down = pressed(KeyCode::S) || pressed(KeyCode::Down);
+    let left = pressed(KeyCode::A) || pressed(KeyCode::Left);
+    let right = pressed(KeyCode::D) || pressed(KeyCode::Right);
 
     let x_axis = -(right as i8) + left as i8;
     let y_axis = -(up as i8) + down as i8;
@@ -268,20 +968,55 @@ fn input_actions(
     let mut move_delta = Vec2::new(x_axis as f32, y_axis as f32);
     if move_delta != Vec2::ZERO {
         move_delta /= move_delta.length();
-        camera.pos += move_delta * time.delta_seconds() * CAMERA_MOVE_SPEED;
+        // Same speed on screen at any zoom
+        let speed = CAMERA_MOVE_SPEED * camera_controller.target_scale;
+        camera_controller.move_by(move_delta * time.delta_seconds() * speed);
     }
 
-    // Zoom camera with mouse scroll
+    // Pan camera by dragging with the middle mouse button
+    let cursor = window.cursor_position();
+    if let Some(cursor) = cursor {
+        if mouse_button_input.just_pressed(MouseButton::Middle) && !over_gui {
+            camera_controller.start_drag(cursor);
+        } else if mouse_button_input.pressed(MouseButton::Middle) {
+            camera_controller.drag_to(&mut camera, cursor);
+        }
+    }
+    if mouse_button_input.just_released(MouseButton::Middle) {
+        camera_controller.end_drag();
+    }
+
+    // Zoom camera toward the cursor with mouse scroll
+    let center = Vec2::new(window.width() / 2.0, window.height() / 2.0);
+    let screen_offset = cursor.map_or(Vec2::ZERO, |cursor| cursor - center);
     for e in mouse_input_events.iter() {
+        // Scrolling an egui window
+        if over_gui {
+            continue;
+        }
         if e.y < 0.0 {
-            camera.scale *= 1.05;
+            camera_controller.zoom_at(CAMERA_ZOOM_STEP, screen_offset);
         } else {
-            camera.scale *= 1.0 / 1.05;
+            camera_controller.zoom_at(1.0 / CAMERA_ZOOM_STEP, screen_offset);
         }
     }
 
+    // Fit the canvas in the window
+    if just_pressed(KeyCode::Home) {
+        camera_controller.fit_canvas(&camera, window.height());
+    }
+    camera_controller.update(&mut camera, time.delta_seconds());
+
     // Pause
-    if keyboard_input.just_pressed(KeyCode::Space) {
-        settings.is_paused = !settings.is_paused;
+    if just_pressed(KeyCode::Space) {
+        let command = SimCommand::SetPaused(!settings.is_paused);
+        replay.execute(command, &mut simulator, &mut settings);
+    }
+    // Step once while paused
+    if just_pressed(KeyCode::Period) {
+        replay.execute(SimCommand::StepOnce, &mut simulator, &mut settings);
+    }
+    if just_pressed(KeyCode::F1) {
+        settings.show_help = !settings.show_help;
     }
 }
\ No newline at end of file
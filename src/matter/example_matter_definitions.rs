@@ -8,7 +8,7 @@ use crate::matter::{
 };
 
 pub const MATTER_EMPTY: MatterDefinition = MatterDefinition {
-    id: MatterId::Empty,
+    id: MatterId::EMPTY,
     color: 0x0,
     weight: 0.0,
     state: MatterState::Empty,
@@ -20,6 +20,7 @@ pub const MATTER_EMPTY: MatterDefinition = MatterDefinition {
         MatterReaction::zero(),
         MatterReaction::zero(),
     ],
+    emissive: 0.0,
 };
 
 const SAND_CHARACTERISTICS: MatterCharacteristic = MatterCharacteristic::from_bits_truncate(
@@ -28,7 +29,7 @@ const SAND_CHARACTERISTICS: MatterCharacteristic = MatterCharacteristic::from_bi
 );
 
 pub const MATTER_SAND: MatterDefinition = MatterDefinition {
-    id: MatterId::Sand,
+    id: MatterId::SAND,
     color: 0xc2b280ff,
     weight: 1.5,
     state: MatterState::Powder,
@@ -38,66 +39,435 @@ pub const MATTER_SAND: MatterDefinition = MatterDefinition {
             reacts: MatterCharacteristic::CORROSIVE,
             direction: Direction::ALL,
             probability: 0.05,
-            becomes: MatterId::Empty,
+            becomes: MatterId::EMPTY,
         },
+        MatterReaction::becomes_on_touch(0.01, MatterCharacteristic::MELTING, MatterId::LAVA),
         MatterReaction::becomes_on_touch(
             1.0,
             MatterCharacteristic::ERASER,
-            MatterId::Empty,
+            MatterId::EMPTY,
         ),
         MatterReaction::zero(),
         MatterReaction::zero(),
-        MatterReaction::zero(),
     ],
+    emissive: 0.0,
 };
 
 const WATER_CHARACTERISTICS: MatterCharacteristic = MatterCharacteristic::from_bits_truncate(
     MatterCharacteristic::RUSTING.bits()
     | MatterCharacteristic::COOLING.bits()
     | MatterCharacteristic::FREEZES.bits()
-    | MatterCharacteristic::VAPORIZES.bits()
+    | MatterCharacteristic::CONDUCTS.bits()
+);
+
+const HEAT: MatterCharacteristic = MatterCharacteristic::from_bits_truncate(
+    MatterCharacteristic::MELTING.bits()
+    | MatterCharacteristic::BURNING.bits()
 );
 
+const COLD: MatterCharacteristic = MatterCharacteristic::from_bits_truncate(
+    MatterCharacteristic::COOLING.bits()
+    | MatterCharacteristic::FREEZING.bits()
+);
+
+const IGNITES: MatterCharacteristic = MatterCharacteristic::from_bits_truncate(
+    MatterCharacteristic::BURNING.bits()
+    | MatterCharacteristic::EXPLODING.bits()
+);
 
 pub const MATTER_WATER: MatterDefinition = MatterDefinition {
-    id: MatterId::Water,
+    id: MatterId::WATER,
     color: 0x0f5e9cff,
     weight: 1.0,
     state: MatterState::Liquid,
     characteristics: WATER_CHARACTERISTICS,
     reactions: [
+        MatterReaction::becomes_on_touch(0.01, MatterCharacteristic::FREEZING, MatterId::ICE),
+        // Boils
+        MatterReaction::becomes_on_touch(0.2, HEAT, MatterId::STEAM),
+        // Electrolysis
+        MatterReaction::becomes_on_touch(0.5, MatterCharacteristic::ELECTRIFIES, MatterId::STEAM),
         MatterReaction::becomes_on_touch(
             1.0,
             MatterCharacteristic::ERASER,
-            MatterId::Empty,
+            MatterId::EMPTY,
         ),
         MatterReaction::zero(),
-        MatterReaction::zero(),
-        MatterReaction::zero(),
-        MatterReaction::zero(),
     ],
+    emissive: 0.0,
 };
 
+const ROCK_CHARACTERISTICS: MatterCharacteristic = MatterCharacteristic::from_bits_truncate(
+    MatterCharacteristic::CORRODES.bits()
+    | MatterCharacteristic::MELTS.bits()
+);
+
 pub const MATTER_ROCK: MatterDefinition = MatterDefinition {
-    id: MatterId::Rock,
+    id: MatterId::ROCK,
     color: 0x787a79ff,
     weight: 2.5,
     state: MatterState::SolidGravity,
-    characteristics: (MatterCharacteristic::CORRODES),
+    characteristics: ROCK_CHARACTERISTICS,
     reactions: [
         MatterReaction {
             reacts: (MatterCharacteristic::CORROSIVE),
             direction: Direction::ALL,
             probability: 0.05,
-            becomes: MatterId::Empty,
+            becomes: MatterId::EMPTY,
         },
+        MatterReaction::becomes_on_touch(0.005, MatterCharacteristic::MELTING, MatterId::LAVA),
+        MatterReaction::becomes_on_touch(
+            1.0,
+            MatterCharacteristic::ERASER,
+            MatterId::EMPTY,
+        ),
+        MatterReaction::zero(),
+        MatterReaction::zero(),
+    ],
+    emissive: 0.0,
+};
+
+const LAVA_CHARACTERISTICS: MatterCharacteristic = MatterCharacteristic::from_bits_truncate(
+    MatterCharacteristic::MELTING.bits()
+    | MatterCharacteristic::BURNING.bits()
+    | MatterCharacteristic::COOLS.bits()
+);
+
+/// Cools down to rock
+pub const MATTER_LAVA: MatterDefinition = MatterDefinition {
+    id: MatterId::LAVA,
+    color: 0xcf1020ff,
+    weight: 3.0,
+    state: MatterState::Liquid,
+    characteristics: LAVA_CHARACTERISTICS,
+    reactions: [
+        MatterReaction::becomes_on_touch(0.05, COLD, MatterId::ROCK),
+        MatterReaction::becomes_on_touch(
+            1.0,
+            MatterCharacteristic::ERASER,
+            MatterId::EMPTY,
+        ),
+        MatterReaction::zero(),
+        MatterReaction::zero(),
+        MatterReaction::zero(),
+    ],
+    emissive: 1.5,
+};
+
+const ICE_CHARACTERISTICS: MatterCharacteristic = MatterCharacteristic::from_bits_truncate(
+    MatterCharacteristic::FREEZING.bits()
+    | MatterCharacteristic::COOLING.bits()
+    | MatterCharacteristic::MELTS.bits()
+);
+
+/// Freezes water it touches, melts slowly on its own
+pub const MATTER_ICE: MatterDefinition = MatterDefinition {
+    id: MatterId::ICE,
+    color: 0xa5f2f3ff,
+    weight: 0.9,
+    state: MatterState::Solid,
+    characteristics: ICE_CHARACTERISTICS,
+    reactions: [
+        MatterReaction::becomes_on_touch(0.1, HEAT, MatterId::WATER),
+        MatterReaction::becomes_over_time(0.0005, MatterId::WATER),
+        MatterReaction::becomes_on_touch(
+            1.0,
+            MatterCharacteristic::ERASER,
+            MatterId::EMPTY,
+        ),
+        MatterReaction::zero(),
+        MatterReaction::zero(),
+    ],
+    emissive: 0.0,
+};
+
+/// Condenses back to water
+pub const MATTER_STEAM: MatterDefinition = MatterDefinition {
+    id: MatterId::STEAM,
+    color: 0xc7d5e0ff,
+    weight: 0.2,
+    state: MatterState::Gas,
+    characteristics: MatterCharacteristic::COOLS,
+    reactions: [
+        MatterReaction::becomes_on_touch(0.02, COLD, MatterId::WATER),
+        MatterReaction::becomes_over_time(0.002, MatterId::WATER),
+        MatterReaction::becomes_on_touch(
+            1.0,
+            MatterCharacteristic::ERASER,
+            MatterId::EMPTY,
+        ),
+        MatterReaction::zero(),
+        MatterReaction::zero(),
+    ],
+    emissive: 0.0,
+};
+
+/// Eats what corrodes and is used up doing so
+pub const MATTER_ACID: MatterDefinition = MatterDefinition {
+    id: MatterId::ACID,
+    color: 0x8fd400ff,
+    weight: 1.2,
+    state: MatterState::Liquid,
+    characteristics: MatterCharacteristic::CORROSIVE,
+    reactions: [
+        MatterReaction::becomes_on_touch(0.02, MatterCharacteristic::CORRODES, MatterId::EMPTY),
+        MatterReaction::becomes_on_touch(
+            1.0,
+            MatterCharacteristic::ERASER,
+            MatterId::EMPTY,
+        ),
+        MatterReaction::zero(),
+        MatterReaction::zero(),
+        MatterReaction::zero(),
+    ],
+    emissive: 0.0,
+};
+
+/// Burns out to smoke, extinguished by water
+pub const MATTER_FIRE: MatterDefinition = MatterDefinition {
+    id: MatterId::FIRE,
+    color: 0xe25822ff,
+    weight: 0.1,
+    state: MatterState::Gas,
+    characteristics: MatterCharacteristic::BURNING,
+    reactions: [
+        MatterReaction::becomes_on_touch(0.5, MatterCharacteristic::COOLING, MatterId::SMOKE),
+        MatterReaction::becomes_over_time(0.05, MatterId::SMOKE),
+        MatterReaction::becomes_on_touch(
+            1.0,
+            MatterCharacteristic::ERASER,
+            MatterId::EMPTY,
+        ),
+        MatterReaction::zero(),
+        MatterReaction::zero(),
+    ],
+    emissive: 2.0,
+};
+
+pub const MATTER_OIL: MatterDefinition = MatterDefinition {
+    id: MatterId::OIL,
+    color: 0x3b3131ff,
+    weight: 0.8,
+    state: MatterState::Liquid,
+    characteristics: MatterCharacteristic::BURNS,
+    reactions: [
+        MatterReaction::becomes_on_touch(0.3, IGNITES, MatterId::FIRE),
+        MatterReaction::becomes_on_touch(
+            1.0,
+            MatterCharacteristic::ERASER,
+            MatterId::EMPTY,
+        ),
+        MatterReaction::zero(),
+        MatterReaction::zero(),
+        MatterReaction::zero(),
+    ],
+    emissive: 0.0,
+};
+
+const WOOD_CHARACTERISTICS: MatterCharacteristic = MatterCharacteristic::from_bits_truncate(
+    MatterCharacteristic::BURNS.bits()
+    | MatterCharacteristic::CORRODES.bits()
+);
+
+pub const MATTER_WOOD: MatterDefinition = MatterDefinition {
+    id: MatterId::WOOD,
+    color: 0x8b5a2bff,
+    weight: 0.6,
+    state: MatterState::Solid,
+    characteristics: WOOD_CHARACTERISTICS,
+    reactions: [
+        MatterReaction::becomes_on_touch(0.02, IGNITES, MatterId::FIRE),
+        MatterReaction::becomes_on_touch(0.02, MatterCharacteristic::CORROSIVE, MatterId::EMPTY),
+        MatterReaction::becomes_on_touch(
+            1.0,
+            MatterCharacteristic::ERASER,
+            MatterId::EMPTY,
+        ),
+        MatterReaction::zero(),
+        MatterReaction::zero(),
+    ],
+    emissive: 0.0,
+};
+
+/// Fades away
+pub const MATTER_SMOKE: MatterDefinition = MatterDefinition {
+    id: MatterId::SMOKE,
+    color: 0x505050ff,
+    weight: 0.05,
+    state: MatterState::Gas,
+    characteristics: MatterCharacteristic::empty(),
+    reactions: [
+        MatterReaction::becomes_over_time(0.01, MatterId::EMPTY),
+        MatterReaction::becomes_on_touch(
+            1.0,
+            MatterCharacteristic::ERASER,
+            MatterId::EMPTY,
+        ),
+        MatterReaction::zero(),
+        MatterReaction::zero(),
+        MatterReaction::zero(),
+    ],
+    emissive: 0.0,
+};
+
+const METAL_CHARACTERISTICS: MatterCharacteristic = MatterCharacteristic::from_bits_truncate(
+    MatterCharacteristic::RUSTS.bits()
+    | MatterCharacteristic::MELTS.bits()
+    | MatterCharacteristic::CORRODES.bits()
+    | MatterCharacteristic::CONDUCTS.bits()
+);
+
+/// Conducts electricity by turning to charged metal
+pub const MATTER_METAL: MatterDefinition = MatterDefinition {
+    id: MatterId::METAL,
+    color: 0xa8a9adff,
+    weight: 7.8,
+    state: MatterState::Solid,
+    characteristics: METAL_CHARACTERISTICS,
+    reactions: [
+        MatterReaction::becomes_on_touch(0.005, MatterCharacteristic::RUSTING, MatterId::RUST),
+        MatterReaction::becomes_on_touch(0.002, MatterCharacteristic::MELTING, MatterId::LAVA),
+        MatterReaction::becomes_on_touch(0.01, MatterCharacteristic::CORROSIVE, MatterId::EMPTY),
+        MatterReaction::becomes_on_touch(
+            0.5,
+            MatterCharacteristic::ELECTRIFIES,
+            MatterId::CHARGED_METAL,
+        ),
+        MatterReaction::becomes_on_touch(
+            1.0,
+            MatterCharacteristic::ERASER,
+            MatterId::EMPTY,
+        ),
+    ],
+    emissive: 0.0,
+};
+
+const CHARGED_METAL_CHARACTERISTICS: MatterCharacteristic = MatterCharacteristic::from_bits_truncate(
+    MatterCharacteristic::ELECTRIFIES.bits()
+    | MatterCharacteristic::CONDUCTS.bits()
+);
+
+/// Metal carrying electricity, which it passes on to touching metal before it discharges
+pub const MATTER_CHARGED_METAL: MatterDefinition = MatterDefinition {
+    id: MatterId::CHARGED_METAL,
+    color: 0xd0e8ffff,
+    weight: 7.8,
+    state: MatterState::Solid,
+    characteristics: CHARGED_METAL_CHARACTERISTICS,
+    reactions: [
+        MatterReaction::becomes_over_time(0.5, MatterId::METAL),
+        MatterReaction::becomes_on_touch(
+            1.0,
+            MatterCharacteristic::ERASER,
+            MatterId::EMPTY,
+        ),
+        MatterReaction::zero(),
+        MatterReaction::zero(),
+        MatterReaction::zero(),
+    ],
+    emissive: 1.0,
+};
+
+pub const MATTER_RUST: MatterDefinition = MatterDefinition {
+    id: MatterId::RUST,
+    color: 0xb7410eff,
+    weight: 5.0,
+    state: MatterState::Powder,
+    characteristics: MatterCharacteristic::CORRODES,
+    reactions: [
+        MatterReaction::becomes_on_touch(0.05, MatterCharacteristic::CORROSIVE, MatterId::EMPTY),
+        MatterReaction::becomes_on_touch(
+            1.0,
+            MatterCharacteristic::ERASER,
+            MatterId::EMPTY,
+        ),
+        MatterReaction::zero(),
+        MatterReaction::zero(),
+        MatterReaction::zero(),
+    ],
+    emissive: 0.0,
+};
+
+const GUNPOWDER_CHARACTERISTICS: MatterCharacteristic = MatterCharacteristic::from_bits_truncate(
+    MatterCharacteristic::EXPLODES.bits()
+    | MatterCharacteristic::BURNS.bits()
+);
+
+const DETONATES: MatterCharacteristic = MatterCharacteristic::from_bits_truncate(
+    MatterCharacteristic::BURNING.bits()
+    | MatterCharacteristic::EXPLODING.bits()
+    | MatterCharacteristic::ELECTRIFIES.bits()
+);
+
+pub const MATTER_GUNPOWDER: MatterDefinition = MatterDefinition {
+    id: MatterId::GUNPOWDER,
+    color: 0x2f2f2fff,
+    weight: 1.7,
+    state: MatterState::Powder,
+    characteristics: GUNPOWDER_CHARACTERISTICS,
+    reactions: [
+        MatterReaction::becomes_on_touch(0.9, DETONATES, MatterId::EXPLOSION),
+        MatterReaction::becomes_on_touch(
+            1.0,
+            MatterCharacteristic::ERASER,
+            MatterId::EMPTY,
+        ),
+        MatterReaction::zero(),
+        MatterReaction::zero(),
+        MatterReaction::zero(),
+    ],
+    emissive: 0.0,
+};
+
+const EXPLOSION_CHARACTERISTICS: MatterCharacteristic = MatterCharacteristic::from_bits_truncate(
+    MatterCharacteristic::EXPLODING.bits()
+    | MatterCharacteristic::BURNING.bits()
+    | MatterCharacteristic::MELTING.bits()
+);
+
+/// Sets off explosives around it and turns to fire
+pub const MATTER_EXPLOSION: MatterDefinition = MatterDefinition {
+    id: MatterId::EXPLOSION,
+    color: 0xffa500ff,
+    weight: 0.0,
+    state: MatterState::Energy,
+    characteristics: EXPLOSION_CHARACTERISTICS,
+    reactions: [
+        MatterReaction::becomes_over_time(0.3, MatterId::FIRE),
+        MatterReaction::becomes_on_touch(
+            1.0,
+            MatterCharacteristic::ERASER,
+            MatterId::EMPTY,
+        ),
+        MatterReaction::zero(),
+        MatterReaction::zero(),
+        MatterReaction::zero(),
+    ],
+    emissive: 3.0,
+};
+
+const SPARK_CHARACTERISTICS: MatterCharacteristic = MatterCharacteristic::from_bits_truncate(
+    MatterCharacteristic::ELECTRIFIES.bits()
+    | MatterCharacteristic::BURNING.bits()
+);
+
+/// Short lived electricity
+pub const MATTER_SPARK: MatterDefinition = MatterDefinition {
+    id: MatterId::SPARK,
+    color: 0xfff200ff,
+    weight: 0.0,
+    state: MatterState::Energy,
+    characteristics: SPARK_CHARACTERISTICS,
+    reactions: [
+        MatterReaction::becomes_over_time(0.3, MatterId::EMPTY),
         MatterReaction::becomes_on_touch(
             1.0,
             MatterCharacteristic::ERASER,
-            MatterId::Empty,
+            MatterId::EMPTY,
         ),
         MatterReaction::zero(),
         MatterReaction::zero(),
         MatterReaction::zero(),
     ],
+    emissive: 2.5,
 };
\ No newline at end of file
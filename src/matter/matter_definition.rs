@@ -1,4 +1,3 @@
-use strum_macros::EnumIter;
 use serde::{Deserialize, Serialize};
 
 use crate::{
@@ -7,35 +6,49 @@ use crate::{
     EMPTY_COLOR, GREY_SCALE,
 };
 
-use super::{MATTER_EMPTY, MATTER_ROCK, MATTER_SAND, MATTER_WATER};
-
 pub const MAX_TRANSITIONS: u8 = 5;
-
-/// Matter Id representing matter that we simulate
-#[repr(u8)]
-#[derive(Serialize, Deserialize, EnumIter, Debug, Copy, Clone, Eq, PartialEq)]
-pub enum MatterId {
-    Empty = 0,
-    Sand = 1,
-    Rock = 2,
-    Water = 3,
-}
-
-impl Default for MatterId {
-    fn default() -> Self {
-        MatterId::Empty
-    }
+/// Matter id is stored in 8 bits of a cell
+pub const MAX_MATTERS: usize = 256;
+/// Size of `MatterDefinition` in the simulator's definitions buffer (see
+/// `compute_shaders/matter.glsl`)
+pub const MATTER_DEFINITION_GPU_WORDS: usize = 5 + 4 * MAX_TRANSITIONS as usize;
+
+/// Matter Id representing matter that we simulate. Ids are assigned at runtime by `MatterRegistry`, any
+/// 8 bit value is a valid id, unknown ids behave like empty.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+pub struct MatterId(pub u8);
+
+impl MatterId {
+    pub const EMPTY: MatterId = MatterId(0);
+    // Ids of the built-in matters, only valid with the built-in registry
+    pub const SAND: MatterId = MatterId(1);
+    pub const ROCK: MatterId = MatterId(2);
+    pub const WATER: MatterId = MatterId(3);
+    pub const LAVA: MatterId = MatterId(4);
+    pub const ICE: MatterId = MatterId(5);
+    pub const STEAM: MatterId = MatterId(6);
+    pub const ACID: MatterId = MatterId(7);
+    pub const FIRE: MatterId = MatterId(8);
+    pub const OIL: MatterId = MatterId(9);
+    pub const WOOD: MatterId = MatterId(10);
+    pub const SMOKE: MatterId = MatterId(11);
+    pub const METAL: MatterId = MatterId(12);
+    pub const RUST: MatterId = MatterId(13);
+    pub const GUNPOWDER: MatterId = MatterId(14);
+    pub const EXPLOSION: MatterId = MatterId(15);
+    pub const SPARK: MatterId = MatterId(16);
+    pub const CHARGED_METAL: MatterId = MatterId(17);
 }
 
 impl From<u8> for MatterId {
     fn from(item: u8) -> Self {
-        unsafe { std::mem::transmute(item) }
+        MatterId(item)
     }
 }
 
-impl Into<u8> for MatterId {
-    fn into(self) -> u8 {
-        self as u8
+impl From<MatterId> for u8 {
+    fn from(id: MatterId) -> Self {
+        id.0
     }
 }
 
@@ -53,16 +66,21 @@ impl MatterReaction {
             reacts: MatterCharacteristic::empty(),
             direction: Direction::NONE,
             probability: 0.0,
-            becomes: MatterId::Empty,
+            becomes: MatterId::EMPTY,
         }
     }
 
-    pub fn dies(p: f32) -> Self {
+    pub const fn dies(p: f32) -> Self {
+        MatterReaction::becomes_over_time(p, MatterId::EMPTY)
+    }
+
+    /// Reaction without characteristics, happens on its own with probability p each step
+    pub const fn becomes_over_time(p: f32, becomes_matter: MatterId) -> Self {
         MatterReaction {
             reacts: MatterCharacteristic::empty(),
             direction: Direction::ALL,
             probability: p,
-            becomes: MatterId::Empty,
+            becomes: becomes_matter,
         }
     }
 
@@ -92,7 +110,7 @@ impl MatterReaction {
                 | Direction::RIGHT
                 | Direction::LEFT),
             probability: p,
-            becomes: MatterId::from(becomes_matter),
+            becomes: becomes_matter,
         }
     }
 }
@@ -113,13 +131,18 @@ pub struct MatterDefinition {
     /// Reactions defines how the matter reacts with it's neighbors and their chararesticts'
     /// - Example: "Water becomes ice on probability x if touches one that freezes".
     /// - Example: "Acid might become empty on probability x if touches a material it corroded (corroding)".
+    /// - Example: "Smoke becomes empty on probability x" (a reaction without characteristics).
     pub reactions: [MatterReaction; MAX_TRANSITIONS as usize],
+    /// How brightly the matter glows, 0 for matter that doesn't. Glowing matter lights up its
+    /// surroundings through bloom.
+    #[serde(default)]
+    pub emissive: f32,
 }
 
 impl MatterDefinition {
     pub fn zero() -> Self {
         MatterDefinition {
-            id: MatterId::Empty,
+            id: MatterId::EMPTY,
             color: 0x0,
             weight: 0.0,
             state: MatterState::Empty,
@@ -131,6 +154,7 @@ impl MatterDefinition {
                 MatterReaction::zero(),
                 MatterReaction::zero(),
             ],
+            emissive: 0.0,
         }
     }
 
@@ -142,19 +166,27 @@ impl MatterDefinition {
         }
     }
 
-    /// Creates a new matter from matter id
-    pub fn new(matter_id: MatterId) -> MatterDefinition {
-        match matter_id {
-            MatterId::Empty => MATTER_EMPTY,
-            MatterId::Sand => MATTER_SAND,
-            MatterId::Rock => MATTER_ROCK,
-            MatterId::Water => MATTER_WATER,
-        }
-    }
-
     pub fn to_matter_with_color(&self) -> u32 {
         let color = self.color_rgba_u8();
-        u8_rgba_to_u32_rgba(color[0], color[1], color[2], self.id.into())
+        u8_rgba_to_u32_rgba(color[0], color[1], color[2], self.id.0)
+    }
+
+    /// Definition as laid out in the simulator's definitions buffer
+    pub fn to_gpu_words(&self) -> [u32; MATTER_DEFINITION_GPU_WORDS] {
+        let mut words = [0; MATTER_DEFINITION_GPU_WORDS];
+        words[0] = self.to_matter_with_color();
+        words[1] = self.state as u32;
+        words[2] = self.weight.to_bits();
+        words[3] = self.characteristics.bits();
+        for (i, reaction) in self.reactions.iter().enumerate() {
+            let offset = 4 + i * 4;
+            words[offset] = reaction.reacts.bits();
+            words[offset + 1] = reaction.direction.bits();
+            words[offset + 2] = reaction.probability.to_bits();
+            words[offset + 3] = reaction.becomes.0 as u32;
+        }
+        words[4 + 4 * MAX_TRANSITIONS as usize] = self.emissive.to_bits();
+        words
     }
 
     pub fn get_id_from_u32(color_and_id: u32) -> MatterId {
@@ -0,0 +1,347 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::matter::{
+    Direction, MatterCharacteristic, MatterDefinition, MatterId, MatterReaction, MatterRegistry,
+    MatterState, ValidationError, EMPTY_NAME, MAX_MATTERS, MAX_TRANSITIONS,
+};
+
+/// A reaction as written in a matter pack. Matters are referenced by name.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PackReaction {
+    pub reacts: MatterCharacteristic,
+    pub direction: Direction,
+    pub probability: f32,
+    pub becomes: String,
+}
+
+/// A matter as written in a matter pack
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PackMatter {
+    pub name: String,
+    pub color: u32,
+    pub weight: f32,
+    pub state: MatterState,
+    pub characteristics: MatterCharacteristic,
+    #[serde(default)]
+    pub reactions: Vec<PackReaction>,
+    #[serde(default)]
+    pub emissive: f32,
+}
+
+/// A set of matter definitions loaded from a RON file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MatterPack {
+    pub name: String,
+    pub matters: Vec<PackMatter>,
+}
+
+/// A registry saved as the pack of its matters & the id of each, so that it is restored with the same
+/// ids, e.g. when replayed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RegistrySnapshot {
+    pub pack: MatterPack,
+    pub ids: BTreeMap<String, MatterId>,
+}
+
+impl RegistrySnapshot {
+    pub fn new(pack_name: &str, registry: &MatterRegistry) -> RegistrySnapshot {
+        RegistrySnapshot {
+            pack: MatterPack::from_registry(pack_name, registry),
+            ids: registry
+                .iter()
+                .map(|(id, name)| (name.to_string(), id))
+                .collect(),
+        }
+    }
+
+    /// The registry the snapshot was taken of
+    pub fn resolve(&self) -> Result<MatterRegistry, MatterPackError> {
+        self.pack.resolve_with(|name| self.ids.get(name).copied())
+    }
+}
+
+/// The matter pack the simulator is running with
+pub struct ActiveMatterPack {
+    pub pack: MatterPack,
+    /// File the pack was loaded from, None for the built-in pack
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum MatterPackError {
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+    Serialize(ron::Error),
+    /// More matters than fit in a cell's 8 bit id
+    TooManyMatters,
+    DuplicateMatter(String),
+    /// A matter was registered under an id another matter has
+    IdTaken(MatterId),
+    /// Pack failed validation, see `MatterPack::validate`
+    Invalid(Vec<ValidationError>),
+}
+
+impl fmt::Display for MatterPackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatterPackError::Io(e) => write!(f, "{}", e),
+            MatterPackError::Parse(e) => write!(f, "{}", e),
+            MatterPackError::Serialize(e) => write!(f, "{}", e),
+            MatterPackError::TooManyMatters => {
+                write!(f, "too many matters, at most {} are supported", MAX_MATTERS)
+            }
+            MatterPackError::DuplicateMatter(name) => {
+                write!(f, "matter '{}' is defined more than once", name)
+            }
+            MatterPackError::IdTaken(id) => write!(f, "matter id {} is taken", id.0),
+            MatterPackError::Invalid(errors) => {
+                write!(f, "{} invalid matter definitions", errors.len())?;
+                for error in errors {
+                    write!(f, "\n{}", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for MatterPackError {}
+
+impl From<io::Error> for MatterPackError {
+    fn from(e: io::Error) -> Self {
+        MatterPackError::Io(e)
+    }
+}
+
+impl PackMatter {
+    pub fn from_definition(
+        name: &str,
+        definition: &MatterDefinition,
+        registry: &MatterRegistry,
+    ) -> PackMatter {
+        PackMatter {
+            name: name.to_string(),
+            color: definition.color,
+            weight: definition.weight,
+            state: definition.state,
+            characteristics: definition.characteristics,
+            reactions: definition
+                .reactions
+                .iter()
+                .filter(|reaction| reaction.probability > 0.0)
+                .map(|reaction| PackReaction {
+                    reacts: reaction.reacts,
+                    direction: reaction.direction,
+                    probability: reaction.probability,
+                    becomes: registry.name(reaction.becomes).to_string(),
+                })
+                .collect(),
+            emissive: definition.emissive,
+        }
+    }
+
+    /// Definition with reactions resolved to ids. The id of the definition is assigned by the registry.
+    /// Expects a validated pack.
+    fn to_definition(&self, ids: &HashMap<&str, MatterId>) -> MatterDefinition {
+        let mut reactions = [MatterReaction::zero(); MAX_TRANSITIONS as usize];
+        for (reaction, pack_reaction) in reactions.iter_mut().zip(&self.reactions) {
+            *reaction = MatterReaction {
+                reacts: pack_reaction.reacts,
+                direction: pack_reaction.direction,
+                probability: pack_reaction.probability,
+                becomes: ids[pack_reaction.becomes.as_str()],
+            };
+        }
+        MatterDefinition {
+            id: MatterId::EMPTY,
+            color: self.color,
+            weight: self.weight,
+            state: self.state,
+            characteristics: self.characteristics,
+            reactions,
+            emissive: self.emissive,
+        }
+    }
+}
+
+impl MatterPack {
+    /// The built in matters from `example_matter_definitions.rs`
+    pub fn builtin() -> MatterPack {
+        MatterPack::from_registry("Built-in", &MatterRegistry::builtin())
+    }
+
+    /// Pack of all matters in a registry
+    pub fn from_registry(name: &str, registry: &MatterRegistry) -> MatterPack {
+        MatterPack {
+            name: name.to_string(),
+            matters: registry
+                .iter()
+                .map(|(id, name)| {
+                    PackMatter::from_definition(name, registry.definition(id), registry)
+                })
+                .collect(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<MatterPack, MatterPackError> {
+        let text = fs::read_to_string(path)?;
+        MatterPack::from_ron(&text)
+    }
+
+    pub fn from_ron(text: &str) -> Result<MatterPack, MatterPackError> {
+        ron::from_str(text).map_err(MatterPackError::Parse)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), MatterPackError> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(MatterPackError::Serialize)?;
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// Validate the pack, then assign ids to its matters and resolve reactions. A matter named
+    /// `EMPTY_NAME` replaces the empty matter (id 0). Matters of the previous registry keep their
+    /// ids, so that cells in the grid stay the same matter when the pack is edited or reloaded.
+    /// Other matters get the lowest free ids in pack order.
+    pub fn resolve(
+        &self,
+        previous: Option<&MatterRegistry>,
+    ) -> Result<MatterRegistry, MatterPackError> {
+        self.resolve_with(|name| previous.and_then(|previous| previous.id(name)))
+    }
+
+    /// `resolve` keeping the ids that matters are known by
+    fn resolve_with(
+        &self,
+        known_id: impl Fn(&str) -> Option<MatterId>,
+    ) -> Result<MatterRegistry, MatterPackError> {
+        let errors = self.validate();
+        if !errors.is_empty() {
+            return Err(MatterPackError::Invalid(errors));
+        }
+        // Ids are assigned first so that reactions can reference matters defined later in the pack
+        let mut ids = HashMap::from([(EMPTY_NAME, MatterId::EMPTY)]);
+        for matter in &self.matters {
+            if let Some(id) = known_id(&matter.name) {
+                ids.insert(matter.name.as_str(), id);
+            }
+        }
+        let used = ids.values().copied().collect::<HashSet<_>>();
+        let mut free = (0..MAX_MATTERS)
+            .map(|id| MatterId(id as u8))
+            .filter(|id| !used.contains(id));
+        for matter in &self.matters {
+            if !ids.contains_key(matter.name.as_str()) {
+                let id = free.next().ok_or(MatterPackError::TooManyMatters)?;
+                ids.insert(matter.name.as_str(), id);
+            }
+        }
+        let mut registry = MatterRegistry::new();
+        for matter in &self.matters {
+            let definition = matter.to_definition(&ids);
+            if matter.name == EMPTY_NAME {
+                registry.set_empty(definition);
+            } else {
+                registry.register_as(ids[matter.name.as_str()], &matter.name, definition)?;
+            }
+        }
+        Ok(registry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::matter::{MatterId, MatterPack, MatterRegistry, RegistrySnapshot};
+
+    #[test]
+    fn test_builtin_pack_roundtrip() {
+        let pack = MatterPack::builtin();
+        let text = ron::ser::to_string(&pack).unwrap();
+        let loaded = MatterPack::from_ron(&text).unwrap();
+        assert_eq!(loaded, pack);
+        let registry = loaded.resolve(None).unwrap();
+        let builtin = MatterRegistry::builtin();
+        assert_eq!(registry.len(), builtin.len());
+        for (id, name) in builtin.iter() {
+            assert_eq!(registry.id(name), Some(id));
+            assert_eq!(
+                registry.definition(id).to_gpu_words(),
+                builtin.definition(id).to_gpu_words()
+            );
+        }
+        assert_eq!(registry.id("Sand"), Some(MatterId::SAND));
+    }
+
+    #[test]
+    fn test_resolve_keeps_ids() {
+        let builtin = MatterRegistry::builtin();
+        let mut pack = MatterPack::builtin();
+        // Delete a matter from the middle & the reactions turning into it
+        let water = pack
+            .matters
+            .iter()
+            .position(|matter| matter.name == "Water")
+            .unwrap();
+        pack.matters.remove(water);
+        for matter in &mut pack.matters {
+            matter
+                .reactions
+                .retain(|reaction| reaction.becomes != "Water");
+        }
+        let mut glass = pack.matters[1].clone();
+        glass.name = "Glass".to_string();
+        pack.matters.push(glass);
+        let registry = pack.resolve(Some(&builtin)).unwrap();
+        for (id, name) in builtin.iter() {
+            if name == "Water" {
+                assert_eq!(registry.id(name), None);
+            } else {
+                assert_eq!(registry.id(name), Some(id));
+            }
+        }
+        // The new matter takes the freed id
+        assert_eq!(registry.id("Glass"), Some(MatterId::WATER));
+        // Without a previous registry ids follow the pack order
+        let registry = pack.resolve(None).unwrap();
+        assert_eq!(registry.id("Lava"), Some(MatterId::WATER));
+    }
+
+    #[test]
+    fn test_registry_snapshot() {
+        let mut pack = MatterPack::builtin();
+        pack.matters.retain(|matter| matter.name != "Water");
+        for matter in &mut pack.matters {
+            matter
+                .reactions
+                .retain(|reaction| reaction.becomes != "Water");
+        }
+        // Water's id is free
+        let registry = pack.resolve(Some(&MatterRegistry::builtin())).unwrap();
+        let snapshot = RegistrySnapshot::new("Without water", &registry);
+        let text = ron::ser::to_string(&snapshot).unwrap();
+        let restored = ron::from_str::<RegistrySnapshot>(&text)
+            .unwrap()
+            .resolve()
+            .unwrap();
+        assert_eq!(restored.len(), registry.len());
+        assert_eq!(restored.get(MatterId::WATER), None);
+        for (id, name) in registry.iter() {
+            assert_eq!(restored.id(name), Some(id));
+            assert_eq!(
+                restored.definition(id).to_gpu_words(),
+                registry.definition(id).to_gpu_words()
+            );
+        }
+        // Two matters can't share an id
+        let mut shared = snapshot;
+        shared.ids.insert("Lava".to_string(), MatterId::SAND);
+        assert!(shared.resolve().is_err());
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use crate::matter::{
+    MatterDefinition, MatterId, MatterPackError, MATTER_ACID, MATTER_CHARGED_METAL, MATTER_EMPTY,
+    MATTER_EXPLOSION, MATTER_FIRE, MATTER_GUNPOWDER, MATTER_ICE, MATTER_LAVA, MATTER_METAL,
+    MATTER_OIL, MATTER_ROCK, MATTER_RUST, MATTER_SAND, MATTER_SMOKE, MATTER_SPARK, MATTER_STEAM,
+    MATTER_WATER, MATTER_WOOD, MAX_MATTERS,
+};
+
+pub const EMPTY_NAME: &str = "Empty";
+/// Name shown for ids that have no definition
+pub const UNKNOWN_NAME: &str = "Unknown";
+
+/// Matters known at runtime. Assigns ids to matters in registration order, id 0 is always empty.
+#[derive(Debug, Clone)]
+pub struct MatterRegistry {
+    /// None for ids that are free, e.g. those of matters removed from a pack
+    names: Vec<Option<String>>,
+    definitions: Vec<MatterDefinition>,
+    ids: HashMap<String, MatterId>,
+}
+
+impl MatterRegistry {
+    /// A registry with only the empty matter
+    pub fn new() -> MatterRegistry {
+        MatterRegistry {
+            names: vec![Some(EMPTY_NAME.to_string())],
+            definitions: vec![MATTER_EMPTY],
+            ids: HashMap::from([(EMPTY_NAME.to_string(), MatterId::EMPTY)]),
+        }
+    }
+
+    /// The built in matters from `example_matter_definitions.rs`
+    pub fn builtin() -> MatterRegistry {
+        let mut registry = MatterRegistry::new();
+        for (name, definition) in [
+            ("Sand", MATTER_SAND),
+            ("Rock", MATTER_ROCK),
+            ("Water", MATTER_WATER),
+            ("Lava", MATTER_LAVA),
+            ("Ice", MATTER_ICE),
+            ("Steam", MATTER_STEAM),
+            ("Acid", MATTER_ACID),
+            ("Fire", MATTER_FIRE),
+            ("Oil", MATTER_OIL),
+            ("Wood", MATTER_WOOD),
+            ("Smoke", MATTER_SMOKE),
+            ("Metal", MATTER_METAL),
+            ("Rust", MATTER_RUST),
+            ("Gunpowder", MATTER_GUNPOWDER),
+            ("Explosion", MATTER_EXPLOSION),
+            ("Spark", MATTER_SPARK),
+            ("Charged Metal", MATTER_CHARGED_METAL),
+        ] {
+            let id = definition.id;
+            assert_eq!(registry.register(name, definition).unwrap(), id);
+        }
+        registry
+    }
+
+    /// Id that the next registered matter gets
+    pub fn next_id(&self) -> Option<MatterId> {
+        if self.definitions.len() < MAX_MATTERS {
+            Some(MatterId(self.definitions.len() as u8))
+        } else {
+            None
+        }
+    }
+
+    /// Register a new matter and assign it an id. The definition's id is overwritten.
+    pub fn register(
+        &mut self,
+        name: &str,
+        mut definition: MatterDefinition,
+    ) -> Result<MatterId, MatterPackError> {
+        if self.ids.contains_key(name) {
+            return Err(MatterPackError::DuplicateMatter(name.to_string()));
+        }
+        let id = self.next_id().ok_or(MatterPackError::TooManyMatters)?;
+        definition.id = id;
+        self.names.push(Some(name.to_string()));
+        self.definitions.push(definition);
+        self.ids.insert(name.to_string(), id);
+        Ok(id)
+    }
+
+    /// Register a new matter under a given id, e.g. the id it has in another registry. Ids skipped
+    /// over stay free.
+    pub fn register_as(
+        &mut self,
+        id: MatterId,
+        name: &str,
+        mut definition: MatterDefinition,
+    ) -> Result<MatterId, MatterPackError> {
+        if self.ids.contains_key(name) {
+            return Err(MatterPackError::DuplicateMatter(name.to_string()));
+        }
+        let index = id.0 as usize;
+        if self.names.get(index).map_or(false, Option::is_some) {
+            return Err(MatterPackError::IdTaken(id));
+        }
+        while self.names.len() <= index {
+            // Free ids behave like empty
+            let mut free = MATTER_EMPTY;
+            free.id = MatterId(self.names.len() as u8);
+            self.names.push(None);
+            self.definitions.push(free);
+        }
+        definition.id = id;
+        self.names[index] = Some(name.to_string());
+        self.definitions[index] = definition;
+        self.ids.insert(name.to_string(), id);
+        Ok(id)
+    }
+
+    /// Give a matter a new name, keeping its id. Returns false if there is no matter named `from` or
+    /// the new name is taken.
+    pub fn rename(&mut self, from: &str, to: &str) -> bool {
+        if self.ids.contains_key(to) {
+            return false;
+        }
+        match self.ids.remove(from) {
+            Some(id) => {
+                self.names[id.0 as usize] = Some(to.to_string());
+                self.ids.insert(to.to_string(), id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replace the definition of the empty matter (keeps id 0)
+    pub fn set_empty(&mut self, mut definition: MatterDefinition) {
+        definition.id = MatterId::EMPTY;
+        self.definitions[0] = definition;
+    }
+
+    /// Number of ids, including free ones
+    pub fn len(&self) -> usize {
+        self.definitions.len()
+    }
+
+    pub fn id(&self, name: &str) -> Option<MatterId> {
+        self.ids.get(name).copied()
+    }
+
+    /// Name of matter, `UNKNOWN_NAME` for ids that have no definition
+    pub fn name(&self, id: MatterId) -> &str {
+        self.names
+            .get(id.0 as usize)
+            .and_then(|name| name.as_deref())
+            .unwrap_or(UNKNOWN_NAME)
+    }
+
+    pub fn get(&self, id: MatterId) -> Option<&MatterDefinition> {
+        self.names
+            .get(id.0 as usize)?
+            .as_ref()
+            .map(|_| &self.definitions[id.0 as usize])
+    }
+
+    /// Definition of matter, the empty definition for ids that have no definition
+    pub fn definition(&self, id: MatterId) -> &MatterDefinition {
+        self.get(id).unwrap_or(&self.definitions[0])
+    }
+
+    /// All definitions, indexed by id. Free ids have the empty definition.
+    pub fn definitions(&self) -> &[MatterDefinition] {
+        &self.definitions
+    }
+
+    /// Ids & names of all matters in id order
+    pub fn iter(&self) -> impl Iterator<Item = (MatterId, &str)> {
+        self.names
+            .iter()
+            .enumerate()
+            .filter_map(|(i, name)| Some((MatterId(i as u8), name.as_deref()?)))
+    }
+}
+
+impl Default for MatterRegistry {
+    fn default() -> Self {
+        MatterRegistry::builtin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::matter::{MatterId, MatterRegistry, MATTER_SAND, UNKNOWN_NAME};
+
+    #[test]
+    fn test_registry_lookups() {
+        let mut registry = MatterRegistry::builtin();
+        assert_eq!(registry.id("Sand"), Some(MatterId::SAND));
+        assert_eq!(registry.name(MatterId::WATER), "Water");
+        let id = registry.register("Glass", MATTER_SAND).unwrap();
+        assert_eq!(id, MatterId(18));
+        assert_eq!(registry.definition(id).id, id);
+        assert!(registry.register("Glass", MATTER_SAND).is_err());
+        // Unknown ids fall back to empty
+        assert_eq!(registry.name(MatterId(200)), UNKNOWN_NAME);
+        assert_eq!(registry.definition(MatterId(200)).id, MatterId::EMPTY);
+        // Ids skipped by `register_as` stay free
+        let id = registry
+            .register_as(MatterId(20), "Glass Wool", MATTER_SAND)
+            .unwrap();
+        assert_eq!(registry.get(MatterId(19)), None);
+        assert_eq!(registry.name(MatterId(19)), UNKNOWN_NAME);
+        assert_eq!(registry.iter().last(), Some((id, "Glass Wool")));
+        assert!(registry
+            .register_as(MatterId::SAND, "Glass Fiber", MATTER_SAND)
+            .is_err());
+        assert!(registry.rename("Glass Wool", "Rock Wool"));
+        assert!(!registry.rename("Rock Wool", "Sand"));
+        assert_eq!(registry.id("Rock Wool"), Some(id));
+        assert_eq!(registry.id("Glass Wool"), None);
+    }
+}
@@ -58,7 +58,7 @@ pub enum MatterState {
 
 bitflags! {
     /// Reaction cause defines whether a matter causes a reaction
-    #[derive(Clone, Copy, Debug)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
     pub struct Direction: u32 {
         const UP_LEFT = 1 << 0;
         const UP = 1 << 1;
@@ -88,7 +88,8 @@ impl<'de> Deserialize<'de> for Direction {
         D: Deserializer<'de>,
     {
         let res = deserializer.deserialize_u32(U32Visitor)?;
-        Ok(Direction::from_bits(res).unwrap())
+        Direction::from_bits(res)
+            .ok_or_else(|| de::Error::custom(format!("unknown direction bits: {:#b}", res)))
     }
 }
 
@@ -105,7 +106,7 @@ pub const ALL_DIRECTIONS: [(Direction, &str); 8] = [
 
 bitflags! {
     /// Reaction cause defines whether a matter causes a reaction
-    #[derive(Clone, Copy, Debug)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
     pub struct MatterCharacteristic: u32 {
         /// A material that is corrosive
         const CORROSIVE = 1 << 0;
@@ -169,7 +170,8 @@ impl<'de> Deserialize<'de> for MatterCharacteristic {
         D: Deserializer<'de>,
     {
         let res = deserializer.deserialize_u32(U32Visitor)?;
-        Ok(MatterCharacteristic::from_bits(res).unwrap())
+        MatterCharacteristic::from_bits(res)
+            .ok_or_else(|| de::Error::custom(format!("unknown characteristic bits: {:#b}", res)))
     }
 }
 
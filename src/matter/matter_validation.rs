@@ -0,0 +1,228 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::{
+    matter::{MatterPack, EMPTY_NAME, MATTER_EMPTY, MAX_MATTERS, MAX_TRANSITIONS},
+    utils::u32_rgba_to_u8_rgba,
+};
+
+/// What is wrong with a matter definition
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationErrorKind {
+    /// Another matter earlier in the pack has the same name
+    DuplicateName,
+    /// Matter does not fit in a cell's 8 bit id
+    TooManyMatters,
+    TooManyReactions { count: usize },
+    /// Reaction probability is not in [0, 1]
+    ProbabilityOutOfRange(f32),
+    /// Reaction becomes a matter that is not in the pack
+    UnknownMatter(String),
+    /// Another matter has the same color, matters could not be told apart on screen
+    DuplicateColor { other: String },
+    /// The empty matter's color or state differs from the built-in empty matter
+    EmptyRedefined,
+}
+
+/// A problem found in a matter of a pack
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    /// Name of the offending matter
+    pub matter: String,
+    /// Offending field, e.g. `reactions[1].probability`
+    pub field: String,
+    pub kind: ValidationErrorKind,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "matter '{}', {}: ", self.matter, self.field)?;
+        match &self.kind {
+            ValidationErrorKind::DuplicateName => write!(f, "name is used more than once"),
+            ValidationErrorKind::TooManyMatters => {
+                write!(f, "too many matters, at most {} are supported", MAX_MATTERS)
+            }
+            ValidationErrorKind::TooManyReactions { count } => write!(
+                f,
+                "{} reactions, at most {} are supported",
+                count, MAX_TRANSITIONS
+            ),
+            ValidationErrorKind::ProbabilityOutOfRange(probability) => {
+                write!(f, "probability {} is not in [0, 1]", probability)
+            }
+            ValidationErrorKind::UnknownMatter(name) => write!(f, "unknown matter '{}'", name),
+            ValidationErrorKind::DuplicateColor { other } => {
+                write!(f, "same color as matter '{}'", other)
+            }
+            ValidationErrorKind::EmptyRedefined => {
+                write!(f, "the empty matter can't be redefined")
+            }
+        }
+    }
+}
+
+impl MatterPack {
+    /// Check the pack's matters, returns every problem found. An empty list means the pack can be
+    /// resolved.
+    pub fn validate(&self) -> Vec<ValidationError> {
+        let mut errors = vec![];
+        let mut error = |matter: &str, field: String, kind| {
+            errors.push(ValidationError {
+                matter: matter.to_string(),
+                field,
+                kind,
+            })
+        };
+        let mut names = HashSet::from([EMPTY_NAME]);
+        let mut colors = HashMap::new();
+        let mut num_matters = 1;
+        let mut has_empty = false;
+        for matter in &self.matters {
+            let name = matter.name.as_str();
+            if name == EMPTY_NAME {
+                if has_empty {
+                    error(name, "name".to_string(), ValidationErrorKind::DuplicateName);
+                }
+                has_empty = true;
+                if matter.color != MATTER_EMPTY.color {
+                    error(
+                        name,
+                        "color".to_string(),
+                        ValidationErrorKind::EmptyRedefined,
+                    );
+                }
+                if matter.state != MATTER_EMPTY.state {
+                    error(
+                        name,
+                        "state".to_string(),
+                        ValidationErrorKind::EmptyRedefined,
+                    );
+                }
+            } else {
+                if !names.insert(name) {
+                    error(name, "name".to_string(), ValidationErrorKind::DuplicateName);
+                }
+                num_matters += 1;
+                if num_matters == MAX_MATTERS + 1 {
+                    error(name, "name".to_string(), ValidationErrorKind::TooManyMatters);
+                }
+            }
+            // Alpha is not stored in cells
+            let rgba = u32_rgba_to_u8_rgba(matter.color);
+            let rgb = [rgba[0], rgba[1], rgba[2]];
+            match colors.get(&rgb) {
+                Some(&other) => error(
+                    name,
+                    "color".to_string(),
+                    ValidationErrorKind::DuplicateColor {
+                        other: String::from(other),
+                    },
+                ),
+                None => {
+                    colors.insert(rgb, name);
+                }
+            }
+            if matter.reactions.len() > MAX_TRANSITIONS as usize {
+                error(
+                    name,
+                    "reactions".to_string(),
+                    ValidationErrorKind::TooManyReactions {
+                        count: matter.reactions.len(),
+                    },
+                );
+            }
+        }
+        // Reactions may reference matters defined later in the pack
+        for matter in &self.matters {
+            for (i, reaction) in matter.reactions.iter().enumerate() {
+                if !(0.0..=1.0).contains(&reaction.probability) {
+                    error(
+                        &matter.name,
+                        format!("reactions[{}].probability", i),
+                        ValidationErrorKind::ProbabilityOutOfRange(reaction.probability),
+                    );
+                }
+                if !names.contains(reaction.becomes.as_str()) {
+                    error(
+                        &matter.name,
+                        format!("reactions[{}].becomes", i),
+                        ValidationErrorKind::UnknownMatter(reaction.becomes.clone()),
+                    );
+                }
+            }
+        }
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::matter::{
+        Direction, MatterPack, MatterState, PackReaction, ValidationErrorKind, EMPTY_NAME,
+    };
+
+    #[test]
+    fn test_validate_pack() {
+        assert!(MatterPack::builtin().validate().is_empty());
+        let mut pack = MatterPack::builtin();
+        let mut sand = pack.matters[1].clone();
+        sand.reactions = vec![PackReaction {
+            reacts: sand.characteristics,
+            direction: Direction::ALL,
+            probability: 1.5,
+            becomes: "Glass".to_string(),
+        }];
+        pack.matters.push(sand);
+        let errors = pack.validate();
+        let kinds = errors.iter().map(|e| (e.field.as_str(), &e.kind)).collect::<Vec<_>>();
+        assert_eq!(kinds.len(), 4);
+        assert!(kinds.contains(&("name", &ValidationErrorKind::DuplicateName)));
+        assert!(kinds.contains(&(
+            "color",
+            &ValidationErrorKind::DuplicateColor {
+                other: "Sand".to_string()
+            }
+        )));
+        assert!(kinds.contains(&(
+            "reactions[0].probability",
+            &ValidationErrorKind::ProbabilityOutOfRange(1.5)
+        )));
+        assert!(kinds.contains(&(
+            "reactions[0].becomes",
+            &ValidationErrorKind::UnknownMatter("Glass".to_string())
+        )));
+    }
+
+    #[test]
+    fn test_validate_empty_matter() {
+        let mut pack = MatterPack::builtin();
+        let mut empty = pack.matters[0].clone();
+        assert_eq!(empty.name, EMPTY_NAME);
+        pack.matters.push(empty.clone());
+        let errors = pack.validate();
+        let kinds = errors
+            .iter()
+            .map(|e| (e.field.as_str(), &e.kind))
+            .collect::<Vec<_>>();
+        assert!(kinds.contains(&("name", &ValidationErrorKind::DuplicateName)));
+        // The empty matter's color & state can't change
+        let mut pack = MatterPack::builtin();
+        empty.color = 0x123456ff;
+        empty.state = MatterState::Powder;
+        pack.matters[0] = empty;
+        let errors = pack.validate();
+        let kinds = errors
+            .iter()
+            .map(|e| (e.field.as_str(), &e.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                ("color", &ValidationErrorKind::EmptyRedefined),
+                ("state", &ValidationErrorKind::EmptyRedefined)
+            ]
+        );
+    }
+}
@@ -1,7 +1,15 @@
 pub mod matter_definition;
+pub mod matter_pack;
+pub mod matter_registry;
 pub mod matter_state;
+pub mod matter_validation;
+pub mod reaction_graph;
 pub mod example_matter_definitions;
 
 pub use matter_definition::*;
+pub use matter_pack::*;
+pub use matter_registry::*;
 pub use matter_state::*;
+pub use matter_validation::*;
+pub use reaction_graph::*;
 pub use example_matter_definitions::*;
\ No newline at end of file
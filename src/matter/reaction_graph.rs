@@ -0,0 +1,304 @@
+use std::fmt::Write;
+
+use crate::{
+    matter::{MatterCharacteristic, MatterId, MatterRegistry, ALL_CHARACTERISTICS},
+    utils::u32_rgba_to_u8_rgba,
+};
+
+/// A reaction turning one matter into another
+#[derive(Debug, Clone, Copy)]
+pub struct ReactionEdge {
+    pub from: MatterId,
+    pub to: MatterId,
+    pub reacts: MatterCharacteristic,
+    pub probability: f32,
+    /// False if no matter has a characteristic the reaction reacts to
+    pub can_fire: bool,
+}
+
+/// Which matter can turn into which, built from the reaction tables of a registry. Reactions
+/// without characteristics happen on their own.
+pub struct ReactionGraph<'a> {
+    registry: &'a MatterRegistry,
+    pub edges: Vec<ReactionEdge>,
+    /// Characteristics of all matters combined
+    present: MatterCharacteristic,
+}
+
+/// Names of set characteristics separated by commas
+pub fn characteristic_names(characteristics: MatterCharacteristic) -> String {
+    ALL_CHARACTERISTICS
+        .iter()
+        .filter(|(characteristic, _, _)| characteristics.contains(*characteristic))
+        .map(|(_, name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn dot_escape(name: &str) -> String {
+    name.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl<'a> ReactionGraph<'a> {
+    pub fn new(registry: &'a MatterRegistry) -> ReactionGraph<'a> {
+        let present = registry
+            .definitions()
+            .iter()
+            .fold(MatterCharacteristic::empty(), |all, definition| {
+                all | definition.characteristics
+            });
+        let edges = registry
+            .definitions()
+            .iter()
+            .flat_map(|definition| {
+                definition
+                    .reactions
+                    .iter()
+                    // Unused reaction slots have zero probability
+                    .filter(|reaction| reaction.probability > 0.0)
+                    .map(move |reaction| ReactionEdge {
+                        from: definition.id,
+                        to: reaction.becomes,
+                        reacts: reaction.reacts,
+                        probability: reaction.probability,
+                        can_fire: reaction.reacts.is_empty()
+                            || reaction.reacts.intersects(present),
+                    })
+            })
+            .collect();
+        ReactionGraph {
+            registry,
+            edges,
+            present,
+        }
+    }
+
+    fn successors(&self, matter: MatterId) -> impl Iterator<Item = MatterId> + '_ {
+        self.edges
+            .iter()
+            .filter(move |edge| edge.can_fire && edge.from == matter)
+            .map(|edge| edge.to)
+    }
+
+    /// Groups of matters that can turn into each other (strongly connected components, including
+    /// matters that become themselves). Only reactions that can fire are followed.
+    pub fn cycles(&self) -> Vec<Vec<MatterId>> {
+        // Tarjan's algorithm, at most 256 matters so recursion is fine
+        struct Tarjan<'g, 'r> {
+            graph: &'g ReactionGraph<'r>,
+            index: Vec<Option<usize>>,
+            low_link: Vec<usize>,
+            on_stack: Vec<bool>,
+            stack: Vec<MatterId>,
+            next_index: usize,
+            cycles: Vec<Vec<MatterId>>,
+        }
+        impl Tarjan<'_, '_> {
+            fn visit(&mut self, matter: MatterId) {
+                let v = matter.0 as usize;
+                self.index[v] = Some(self.next_index);
+                self.low_link[v] = self.next_index;
+                self.next_index += 1;
+                self.stack.push(matter);
+                self.on_stack[v] = true;
+                for next in self.graph.successors(matter).collect::<Vec<_>>() {
+                    let w = next.0 as usize;
+                    match self.index[w] {
+                        None => {
+                            self.visit(next);
+                            self.low_link[v] = self.low_link[v].min(self.low_link[w]);
+                        }
+                        Some(index) if self.on_stack[w] => {
+                            self.low_link[v] = self.low_link[v].min(index);
+                        }
+                        _ => {}
+                    }
+                }
+                if Some(self.low_link[v]) == self.index[v] {
+                    let mut component = vec![];
+                    while let Some(member) = self.stack.pop() {
+                        self.on_stack[member.0 as usize] = false;
+                        component.push(member);
+                        if member == matter {
+                            break;
+                        }
+                    }
+                    let is_cycle = component.len() > 1
+                        || self.graph.successors(matter).any(|next| next == matter);
+                    if is_cycle {
+                        component.sort();
+                        self.cycles.push(component);
+                    }
+                }
+            }
+        }
+        let num_matters = self.registry.len();
+        let mut tarjan = Tarjan {
+            graph: self,
+            index: vec![None; num_matters],
+            low_link: vec![0; num_matters],
+            on_stack: vec![false; num_matters],
+            stack: vec![],
+            next_index: 0,
+            cycles: vec![],
+        };
+        for (matter, _) in self.registry.iter() {
+            if tarjan.index[matter.0 as usize].is_none() {
+                tarjan.visit(matter);
+            }
+        }
+        let mut cycles = tarjan.cycles;
+        cycles.sort();
+        cycles
+    }
+
+    /// Matters no other matter turns into, they only appear when drawn
+    pub fn unreachable(&self) -> Vec<MatterId> {
+        self.registry
+            .iter()
+            .map(|(matter, _)| matter)
+            .filter(|&matter| matter != MatterId::EMPTY)
+            .filter(|&matter| {
+                !self
+                    .edges
+                    .iter()
+                    .any(|edge| edge.can_fire && edge.to == matter && edge.from != matter)
+            })
+            .collect()
+    }
+
+    /// Characteristics some matter has but no reaction reacts to
+    pub fn unused_characteristics(&self) -> MatterCharacteristic {
+        let reacted = self
+            .edges
+            .iter()
+            .fold(MatterCharacteristic::empty(), |all, edge| all | edge.reacts);
+        self.present - reacted
+    }
+
+    /// Reactions that can never happen as no matter has a characteristic they react to
+    pub fn dead_reactions(&self) -> impl Iterator<Item = &ReactionEdge> {
+        self.edges.iter().filter(|edge| !edge.can_fire)
+    }
+
+    /// Human readable summary of the analysis
+    pub fn report(&self) -> String {
+        let name = |matter: MatterId| self.registry.name(matter);
+        let mut report = String::new();
+        writeln!(
+            report,
+            "{} matters, {} reactions",
+            self.registry.iter().count(),
+            self.edges.len()
+        )
+        .unwrap();
+        let cycles = self.cycles();
+        writeln!(report, "Cycles ({}):", cycles.len()).unwrap();
+        for cycle in cycles {
+            let names = cycle.iter().map(|&matter| name(matter)).collect::<Vec<_>>();
+            writeln!(report, "    {}", names.join(" <-> ")).unwrap();
+        }
+        let unreachable = self.unreachable();
+        writeln!(report, "Only drawable, no reaction produces them ({}):", unreachable.len())
+            .unwrap();
+        for matter in unreachable {
+            writeln!(report, "    {}", name(matter)).unwrap();
+        }
+        writeln!(
+            report,
+            "Characteristics no matter reacts to: {}",
+            characteristic_names(self.unused_characteristics())
+        )
+        .unwrap();
+        writeln!(report, "Reactions that never happen:").unwrap();
+        for edge in self.dead_reactions() {
+            writeln!(
+                report,
+                "    {} -> {} (reacts to {})",
+                name(edge.from),
+                name(edge.to),
+                characteristic_names(edge.reacts)
+            )
+            .unwrap();
+        }
+        report
+    }
+
+    /// Graphviz DOT of the graph. Nodes are colored like their matter, reactions that never happen
+    /// are dashed.
+    pub fn to_dot(&self, title: &str) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph \"{}\" {{", dot_escape(title)).unwrap();
+        writeln!(dot, "    node [style=filled];").unwrap();
+        for (matter, name) in self.registry.iter() {
+            let rgba = u32_rgba_to_u8_rgba(self.registry.definition(matter).color);
+            writeln!(
+                dot,
+                "    \"{}\" [fillcolor=\"#{:02x}{:02x}{:02x}\"];",
+                dot_escape(name),
+                rgba[0],
+                rgba[1],
+                rgba[2]
+            )
+            .unwrap();
+        }
+        for edge in &self.edges {
+            let cause = if edge.reacts.is_empty() {
+                "spontaneous".to_string()
+            } else {
+                characteristic_names(edge.reacts)
+            };
+            writeln!(
+                dot,
+                "    \"{}\" -> \"{}\" [label=\"{} ({})\"{}];",
+                dot_escape(self.registry.name(edge.from)),
+                dot_escape(self.registry.name(edge.to)),
+                cause,
+                edge.probability,
+                if edge.can_fire { "" } else { ", style=dashed" }
+            )
+            .unwrap();
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::matter::{
+        MatterCharacteristic, MatterDefinition, MatterId, MatterReaction, MatterRegistry,
+        ReactionGraph,
+    };
+
+    #[test]
+    fn test_reaction_graph() {
+        let mut registry = MatterRegistry::new();
+        // Nothing is corrosive, so sand never reacts
+        let mut sand = MatterDefinition::zero();
+        sand.characteristics = MatterCharacteristic::MELTS;
+        sand.reactions[0] =
+            MatterReaction::becomes_on_touch(0.1, MatterCharacteristic::CORROSIVE, MatterId::EMPTY);
+        let sand = registry.register("Sand", sand).unwrap();
+        // Water freezes to ice when touching ice, ice melts back on its own
+        let mut water = MatterDefinition::zero();
+        water.characteristics = MatterCharacteristic::FREEZES;
+        water.reactions[0] =
+            MatterReaction::becomes_on_touch(0.1, MatterCharacteristic::FREEZING, MatterId(3));
+        let water = registry.register("Water", water).unwrap();
+        let mut ice = MatterDefinition::zero();
+        ice.characteristics = MatterCharacteristic::FREEZING;
+        ice.reactions[0] = MatterReaction::becomes_over_time(0.01, water);
+        let ice = registry.register("Ice", ice).unwrap();
+
+        let graph = ReactionGraph::new(&registry);
+        assert_eq!(graph.cycles(), vec![vec![water, ice]]);
+        assert_eq!(graph.unreachable(), vec![sand]);
+        let unused = graph.unused_characteristics();
+        assert!(unused.contains(MatterCharacteristic::MELTS));
+        assert!(!unused.contains(MatterCharacteristic::FREEZING));
+        // Only sand's corrosion is dead, these definitions carry no erase reactions anymore
+        assert_eq!(graph.dead_reactions().count(), 1);
+        assert!(graph.to_dot("Test").contains("\"Water\" -> \"Ice\""));
+    }
+}
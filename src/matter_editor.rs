@@ -0,0 +1,335 @@
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use bevy_vulkano::{
+    egui_winit_vulkano::{egui, egui::Ui},
+    BevyVulkanoWindows,
+};
+use strum::IntoEnumIterator;
+
+use crate::{
+    ca_simulator::CASimulator,
+    matter::{
+        ActiveMatterPack, Direction, MatterCharacteristic, MatterState, PackMatter, PackReaction,
+        RegistrySnapshot, ALL_CHARACTERISTICS, ALL_DIRECTIONS, EMPTY_NAME, MAX_TRANSITIONS,
+    },
+    replay::{ReplayState, SimCommand},
+    utils::{u32_rgba_to_u8_rgba, u8_rgba_to_u32_rgba},
+    DynamicSettings,
+};
+
+/// Where packs edited from the built-in matters are saved
+const DEFAULT_PACK_PATH: &str = "matter_pack.ron";
+
+/// Compass layout of `ALL_DIRECTIONS` indices, None is the center
+const COMPASS: [[Option<usize>; 3]; 3] = [
+    [Some(0), Some(1), Some(2)],
+    [Some(7), None, Some(3)],
+    [Some(6), Some(5), Some(4)],
+];
+const COMPASS_ARROWS: [&str; 8] = ["↖", "↑", "↗", "→", "↘", "↓", "↙", "←"];
+
+/// State of the matter editor window
+pub struct MatterEditor {
+    pub open: bool,
+    selected: usize,
+    /// Name being typed for the selected matter
+    name: String,
+    /// Why the edited pack could not be applied
+    error: Option<String>,
+}
+
+impl Default for MatterEditor {
+    fn default() -> Self {
+        MatterEditor {
+            open: false,
+            selected: 0,
+            name: String::new(),
+            error: None,
+        }
+    }
+}
+
+/// Checkboxes for each characteristic, returns whether any changed
+fn characteristics_edit(ui: &mut Ui, characteristics: &mut MatterCharacteristic) -> bool {
+    let mut changed = false;
+    for (characteristic, name, description) in ALL_CHARACTERISTICS {
+        let mut is_set = characteristics.contains(characteristic);
+        if ui.checkbox(&mut is_set, name).on_hover_text(description).changed() {
+            characteristics.set(characteristic, is_set);
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// 3x3 compass of direction toggles, center toggles all directions
+fn direction_compass(ui: &mut Ui, id: impl std::hash::Hash, direction: &mut Direction) -> bool {
+    let mut changed = false;
+    egui::Grid::new(id).show(ui, |ui| {
+        for row in COMPASS {
+            for cell in row {
+                match cell {
+                    Some(i) => {
+                        let (dir, name) = ALL_DIRECTIONS[i];
+                        let is_set = direction.contains(dir);
+                        if ui
+                            .selectable_label(is_set, COMPASS_ARROWS[i])
+                            .on_hover_text(name)
+                            .clicked()
+                        {
+                            direction.set(dir, !is_set);
+                            changed = true;
+                        }
+                    }
+                    None => {
+                        let is_all = direction.contains(Direction::ALL);
+                        if ui.selectable_label(is_all, "•").on_hover_text("All").clicked() {
+                            *direction = if is_all {
+                                Direction::NONE
+                            } else {
+                                Direction::ALL
+                            };
+                            changed = true;
+                        }
+                    }
+                }
+            }
+            ui.end_row();
+        }
+    });
+    changed
+}
+
+/// Edit a reaction, returns (changed, remove)
+fn reaction_edit(
+    ui: &mut Ui,
+    index: usize,
+    reaction: &mut PackReaction,
+    matter_names: &[String],
+) -> (bool, bool) {
+    let mut changed = false;
+    let mut remove = false;
+    ui.horizontal(|ui| {
+        ui.menu_button(format!("Reacts to ({})", reaction.reacts.bits().count_ones()), |ui| {
+            changed |= characteristics_edit(ui, &mut reaction.reacts);
+        });
+        changed |= direction_compass(ui, ("reaction direction", index), &mut reaction.direction);
+        ui.vertical(|ui| {
+            changed |= ui
+                .add(egui::Slider::new(&mut reaction.probability, 0.0..=1.0).text("Probability"))
+                .changed();
+            egui::ComboBox::from_id_source(("reaction becomes", index))
+                .selected_text(format!("Becomes {}", reaction.becomes))
+                .show_ui(ui, |ui| {
+                    for name in matter_names {
+                        changed |= ui
+                            .selectable_value(&mut reaction.becomes, name.clone(), name.as_str())
+                            .changed();
+                    }
+                });
+            remove = ui.button("Remove reaction").clicked();
+        });
+    });
+    (changed, remove)
+}
+
+/// Edit a matter, returns whether it changed & its previous name if it was renamed
+fn matter_edit(
+    ui: &mut Ui,
+    matter: &mut PackMatter,
+    name: &mut String,
+    matter_names: &[String],
+) -> (bool, Option<String>) {
+    let mut changed = false;
+    let mut renamed = None;
+    ui.horizontal(|ui| {
+        ui.label("Name");
+        // Renames apply once the name is entered, a name that is empty or taken is reverted
+        let response = ui.text_edit_singleline(name);
+        if response.lost_focus() && *name != matter.name {
+            let is_valid = !name.is_empty()
+                && name.as_str() != EMPTY_NAME
+                && matter.name != EMPTY_NAME
+                && !matter_names.contains(name);
+            if is_valid {
+                renamed = Some(std::mem::replace(&mut matter.name, name.clone()));
+                changed = true;
+            } else {
+                *name = matter.name.clone();
+            }
+        } else if !response.has_focus() {
+            *name = matter.name.clone();
+        }
+    });
+    ui.horizontal(|ui| {
+        let rgba = u32_rgba_to_u8_rgba(matter.color);
+        let mut rgb = [rgba[0], rgba[1], rgba[2]];
+        if ui.color_edit_button_srgb(&mut rgb).changed() {
+            matter.color = u8_rgba_to_u32_rgba(rgb[0], rgb[1], rgb[2], rgba[3]);
+            changed = true;
+        }
+        egui::ComboBox::from_label("State")
+            .selected_text(format!("{:?}", matter.state))
+            .show_ui(ui, |ui| {
+                for state in MatterState::iter() {
+                    changed |= ui
+                        .selectable_value(&mut matter.state, state, format!("{:?}", state))
+                        .changed();
+                }
+            });
+    });
+    changed |= ui
+        .add(egui::Slider::new(&mut matter.weight, 0.0..=10.0).text("Weight"))
+        .changed();
+    changed |= ui
+        .add(egui::Slider::new(&mut matter.emissive, 0.0..=4.0).text("Emissive"))
+        .changed();
+    ui.collapsing("Characteristics", |ui| {
+        changed |= characteristics_edit(ui, &mut matter.characteristics);
+    });
+    ui.collapsing("Reactions", |ui| {
+        let mut remove = None;
+        for (i, reaction) in matter.reactions.iter_mut().enumerate() {
+            let (reaction_changed, reaction_removed) =
+                reaction_edit(ui, i, reaction, matter_names);
+            changed |= reaction_changed;
+            if reaction_removed {
+                remove = Some(i);
+            }
+            ui.separator();
+        }
+        if let Some(i) = remove {
+            matter.reactions.remove(i);
+            changed = true;
+        }
+        let can_add = matter.reactions.len() < MAX_TRANSITIONS as usize;
+        if can_add && ui.button("Add reaction").clicked() {
+            matter.reactions.push(PackReaction {
+                reacts: MatterCharacteristic::empty(),
+                direction: Direction::ALL,
+                probability: 0.0,
+                becomes: matter_names[0].clone(),
+            });
+            changed = true;
+        }
+    });
+    (changed, renamed)
+}
+
+/// Egui window to create & edit the matters of the active pack. Changes are applied live & recorded.
+pub fn matter_editor_interface(
+    vulkano_windows: NonSend<BevyVulkanoWindows>,
+    mut editor: ResMut<MatterEditor>,
+    mut matter_pack: ResMut<ActiveMatterPack>,
+    mut simulator: ResMut<CASimulator>,
+    mut settings: ResMut<DynamicSettings>,
+    mut replay: ResMut<ReplayState>,
+) {
+    if !editor.open {
+        return;
+    }
+    let (_, gui) = vulkano_windows.get_primary_window_renderer().unwrap();
+    let ctx = gui.context();
+    let mut open = editor.open;
+    let mut changed = false;
+    egui::Window::new("Matter Editor")
+        .open(&mut open)
+        .vscroll(true)
+        .show(&ctx, |ui| {
+            // Edits would not be applied
+            if replay.is_playing() {
+                ui.label("Matters can't be edited while a replay plays");
+                return;
+            }
+            let matter_names = matter_pack
+                .pack
+                .matters
+                .iter()
+                .map(|matter| matter.name.clone())
+                .collect::<Vec<_>>();
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_label("Matter")
+                    .selected_text(
+                        matter_names
+                            .get(editor.selected)
+                            .cloned()
+                            .unwrap_or_default(),
+                    )
+                    .show_ui(ui, |ui| {
+                        for (i, name) in matter_names.iter().enumerate() {
+                            ui.selectable_value(&mut editor.selected, i, name.as_str());
+                        }
+                    });
+                if ui.button("New").clicked() {
+                    matter_pack.pack.matters.push(PackMatter {
+                        name: format!("Matter {}", matter_names.len()),
+                        color: 0xffffffff,
+                        weight: 1.0,
+                        state: MatterState::Powder,
+                        characteristics: MatterCharacteristic::empty(),
+                        reactions: vec![],
+                        emissive: 0.0,
+                    });
+                    editor.selected = matter_pack.pack.matters.len() - 1;
+                    changed = true;
+                }
+                // Empty is kept as the first matter
+                if editor.selected > 0 && ui.button("Delete").clicked() {
+                    matter_pack.pack.matters.remove(editor.selected);
+                    editor.selected -= 1;
+                    changed = true;
+                }
+            });
+            let selected = editor.selected;
+            let mut renamed = None;
+            if let Some(matter) = matter_pack.pack.matters.get_mut(selected) {
+                let (matter_changed, matter_renamed) =
+                    matter_edit(ui, matter, &mut editor.name, &matter_names);
+                changed |= matter_changed;
+                renamed = matter_renamed.map(|from| (from, matter.name.clone()));
+            }
+            // Keep the matter's id & what turns into it
+            if let Some((from, to)) = renamed {
+                for matter in &mut matter_pack.pack.matters {
+                    for reaction in &mut matter.reactions {
+                        if reaction.becomes == from {
+                            reaction.becomes = to.clone();
+                        }
+                    }
+                }
+                let command = SimCommand::RenameMatter { from, to };
+                replay.execute(command, &mut simulator, &mut settings);
+            }
+            ui.separator();
+            if let Some(error) = &editor.error {
+                ui.colored_label(egui::Color32::RED, error.as_str());
+            }
+            let path = matter_pack
+                .path
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_PACK_PATH));
+            if ui.button(format!("Save pack to {:?}", path)).clicked() {
+                match matter_pack.pack.save(&path) {
+                    Ok(()) => {
+                        info!("Saved matter pack to {:?}", path);
+                        matter_pack.path = Some(path);
+                    }
+                    Err(e) => editor.error = Some(format!("Failed to save {:?}: {}", path, e)),
+                }
+            }
+        });
+    editor.open = open;
+    if changed {
+        // Apply live, keep the previous definitions if the edited pack is not valid
+        match matter_pack.pack.resolve(Some(simulator.registry())) {
+            Ok(registry) => {
+                let snapshot = RegistrySnapshot::new(&matter_pack.pack.name, &registry);
+                let command = SimCommand::SetMatterPack(snapshot);
+                replay.execute(command, &mut simulator, &mut settings);
+                editor.error = None;
+            }
+            Err(e) => editor.error = Some(e.to_string()),
+        }
+    }
+}
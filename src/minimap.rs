@@ -0,0 +1,110 @@
+use bevy::prelude::*;
+use bevy_vulkano::{egui_winit_vulkano::egui, BevyVulkanoWindows};
+
+use crate::{
+    ca_simulator::CASimulator,
+    camera::{CameraController, OrthographicCamera},
+    DynamicSettings, CANVAS_SIZE_X, CANVAS_SIZE_Y,
+};
+
+/// Side of the minimap in egui points
+pub const MINIMAP_SIZE: f32 = 200.0;
+
+/// The canvas image registered with egui for the minimap
+#[derive(Default)]
+pub struct Minimap {
+    texture: Option<egui::TextureId>,
+}
+
+/// Canvas size in cells
+fn canvas_size() -> Vec2 {
+    Vec2::new(CANVAS_SIZE_X as f32, CANVAS_SIZE_Y as f32)
+}
+
+/// Position within the minimap (0..1, y down like egui) of a world position
+pub fn world_to_minimap(world: Vec2) -> Vec2 {
+    let canvas = (world + canvas_size() / 2.0) / canvas_size();
+    Vec2::new(canvas.x, 1.0 - canvas.y)
+}
+
+/// World position at a position within the minimap (0..1, y down like egui)
+pub fn minimap_to_world(minimap: Vec2) -> Vec2 {
+    Vec2::new(minimap.x, 1.0 - minimap.y) * canvas_size() - canvas_size() / 2.0
+}
+
+/// Egui window with the whole canvas scaled down & the area in view. Clicking or dragging on it moves
+/// the view there.
+pub fn minimap_interface(
+    mut vulkano_windows: NonSendMut<BevyVulkanoWindows>,
+    mut settings: ResMut<DynamicSettings>,
+    mut minimap: ResMut<Minimap>,
+    mut camera_controller: ResMut<CameraController>,
+    camera: Res<OrthographicCamera>,
+    simulator: Res<CASimulator>,
+) {
+    if !settings.show_minimap {
+        return;
+    }
+    let (_, gui) = vulkano_windows.get_primary_window_renderer_mut().unwrap();
+    // The full size canvas image is drawn scaled down. Egui samples it bilinearly without mipmaps,
+    // so matter only a cell or two across may flicker or not show at all.
+    let texture = *minimap
+        .texture
+        .get_or_insert_with(|| gui.register_user_image_view(simulator.color_image()));
+    let ctx = gui.context();
+    egui::Window::new("Minimap")
+        .open(&mut settings.show_minimap)
+        .resizable(false)
+        .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10.0, -10.0))
+        .show(&ctx, |ui| {
+            // Canvas rows are drawn bottom up
+            let uv = egui::Rect::from_min_max(egui::pos2(0.0, 1.0), egui::pos2(1.0, 0.0));
+            let response = ui.add(
+                egui::Image::new(texture, egui::vec2(MINIMAP_SIZE, MINIMAP_SIZE))
+                    .uv(uv)
+                    .sense(egui::Sense::click_and_drag()),
+            );
+            let rect = response.rect;
+            let to_screen = |world: Vec2| {
+                let minimap = world_to_minimap(world);
+                rect.min + egui::vec2(minimap.x, minimap.y) * rect.size()
+            };
+            let (min, max) = camera.visible_world();
+            let viewport = egui::Rect::from_two_pos(to_screen(min), to_screen(max));
+            ui.painter().with_clip_rect(rect).rect_stroke(
+                viewport,
+                0.0,
+                egui::Stroke::new(1.5, egui::Color32::WHITE),
+            );
+            if response.clicked() || response.dragged() {
+                if let Some(pointer) = response.interact_pointer_pos() {
+                    let minimap = (pointer - rect.min) / rect.size();
+                    camera_controller.look_at(minimap_to_world(Vec2::new(minimap.x, minimap.y)));
+                }
+            }
+            ui.checkbox(
+                &mut camera_controller.clamp_to_canvas,
+                "Keep canvas on screen",
+            );
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+
+    use crate::{
+        minimap::{minimap_to_world, world_to_minimap},
+        CANVAS_SIZE_X, CANVAS_SIZE_Y,
+    };
+
+    #[test]
+    fn test_minimap_positions() {
+        assert_eq!(world_to_minimap(Vec2::ZERO), Vec2::splat(0.5));
+        // Bottom left of the canvas is at the bottom left of the minimap
+        let bottom_left = -Vec2::new(CANVAS_SIZE_X as f32, CANVAS_SIZE_Y as f32) / 2.0;
+        assert_eq!(world_to_minimap(bottom_left), Vec2::new(0.0, 1.0));
+        let world = Vec2::new(100.0, -200.0);
+        assert_eq!(minimap_to_world(world_to_minimap(world)), world);
+    }
+}
@@ -0,0 +1,77 @@
+use std::{fs, path::Path, time::SystemTime};
+
+use bevy::prelude::*;
+
+use crate::{
+    ca_simulator::CASimulator,
+    matter::{ActiveMatterPack, MatterPack, RegistrySnapshot},
+    replay::{ReplayState, SimCommand},
+    DynamicSettings,
+};
+
+/// How often the matter pack file is checked for changes
+const WATCH_INTERVAL_SECONDS: f32 = 0.5;
+
+/// Watches the active matter pack file and reloads it on change
+pub struct MatterPackWatcher {
+    timer: Timer,
+    modified: Option<SystemTime>,
+    /// Why the latest reload failed, shown in the gui
+    pub error: Option<String>,
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+impl MatterPackWatcher {
+    pub fn new(matter_pack: &ActiveMatterPack) -> MatterPackWatcher {
+        MatterPackWatcher {
+            timer: Timer::from_seconds(WATCH_INTERVAL_SECONDS, true),
+            modified: matter_pack.path.as_deref().and_then(modified_time),
+            error: None,
+        }
+    }
+}
+
+/// Reload the matter pack when its file changes. The grid is kept, cells take on the new definitions.
+/// Errors are kept for the gui and the previous definitions stay in use. Changes are picked up once a
+/// playing replay has finished.
+pub fn watch_matter_pack(
+    time: Res<Time>,
+    mut watcher: ResMut<MatterPackWatcher>,
+    mut matter_pack: ResMut<ActiveMatterPack>,
+    mut simulator: ResMut<CASimulator>,
+    mut settings: ResMut<DynamicSettings>,
+    mut replay: ResMut<ReplayState>,
+) {
+    if !watcher.timer.tick(time.delta()).just_finished() || replay.is_playing() {
+        return;
+    }
+    let path = match &matter_pack.path {
+        Some(path) => path.clone(),
+        None => return,
+    };
+    let modified = modified_time(&path);
+    if modified.is_none() || modified == watcher.modified {
+        return;
+    }
+    watcher.modified = modified;
+    let reloaded = MatterPack::load(&path).and_then(|pack| {
+        pack.resolve(Some(simulator.registry()))
+            .map(|registry| (pack, registry))
+    });
+    match reloaded {
+        Ok((pack, registry)) => {
+            info!("Reloaded matter pack '{}' from {:?}", pack.name, path);
+            let command = SimCommand::SetMatterPack(RegistrySnapshot::new(&pack.name, &registry));
+            replay.execute(command, &mut simulator, &mut settings);
+            matter_pack.pack = pack;
+            watcher.error = None;
+        }
+        Err(e) => {
+            error!("Failed to reload matter pack {:?}: {}", path, e);
+            watcher.error = Some(e.to_string());
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use std::{collections::VecDeque, fmt::Write, fs, io, path::Path};
+
+use bevy::prelude::*;
+use bevy_vulkano::{egui_winit_vulkano::egui, BevyVulkanoWindows};
+
+use crate::{
+    ca_simulator::CASimulator,
+    matter::{MatterId, MatterRegistry},
+    selection::Selection,
+    DynamicSettings, CANVAS_SIZE_X, CANVAS_SIZE_Y,
+};
+
+/// Counts kept for the time series plot
+pub const POPULATION_HISTORY_CAPACITY: usize = 600;
+/// Where the population history is exported to
+pub const POPULATION_EXPORT_PATH: &str = "population.csv";
+
+/// Cells of each matter within a region of the grid, counted on the gpu
+#[derive(Debug, Clone, PartialEq)]
+pub struct Population {
+    /// Step after which the cells were counted
+    pub sim_step: u32,
+    pub min: IVec2,
+    pub size: UVec2,
+    /// Cell counts indexed by matter id
+    pub counts: Vec<u32>,
+}
+
+impl Population {
+    pub fn count(&self, matter: MatterId) -> u32 {
+        self.counts[matter.0 as usize]
+    }
+
+    /// Ids & counts of the matters that have cells, most common first
+    pub fn present(&self) -> Vec<(MatterId, u32)> {
+        let mut present = self
+            .counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(id, &count)| (MatterId(id as u8), count))
+            .collect::<Vec<_>>();
+        present.sort_by(|a, b| b.1.cmp(&a.1));
+        present
+    }
+}
+
+/// Periodic population counts shown in the population window
+pub struct PopulationStats {
+    /// Steps between counts
+    pub interval: u32,
+    /// Count the selected region instead of the whole canvas
+    pub selection_only: bool,
+    pub history: VecDeque<Population>,
+    /// Step at which counting was last requested
+    last_request: Option<u32>,
+    /// Result of the last export
+    message: Option<String>,
+}
+
+impl Default for PopulationStats {
+    fn default() -> Self {
+        PopulationStats {
+            interval: 10,
+            selection_only: false,
+            history: VecDeque::new(),
+            last_request: None,
+            message: None,
+        }
+    }
+}
+
+impl PopulationStats {
+    pub fn push(&mut self, population: Population) {
+        // Rewound, counts after this step no longer happened
+        while self
+            .history
+            .back()
+            .map_or(false, |last| last.sim_step >= population.sim_step)
+        {
+            self.history.pop_back();
+        }
+        self.history.push_back(population);
+        while self.history.len() > POPULATION_HISTORY_CAPACITY {
+            self.history.pop_front();
+        }
+    }
+
+    /// Is it time to count again at sim_step
+    fn is_due(&self, sim_step: u32) -> bool {
+        match self.last_request {
+            Some(last) => sim_step < last || sim_step >= last + self.interval.max(1),
+            None => true,
+        }
+    }
+
+    /// History as CSV, a row per count & a column per matter present in any count
+    pub fn to_csv(&self, registry: &MatterRegistry) -> String {
+        let mut ids = self
+            .history
+            .iter()
+            .flat_map(|population| population.present().into_iter().map(|(id, _)| id))
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids.dedup();
+        let mut csv = String::from("sim_step");
+        for &id in &ids {
+            write!(csv, ",{}", registry.name(id)).unwrap();
+        }
+        csv.push('\n');
+        for population in &self.history {
+            write!(csv, "{}", population.sim_step).unwrap();
+            for &id in &ids {
+                write!(csv, ",{}", population.count(id)).unwrap();
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+
+    pub fn export(&self, path: &Path, registry: &MatterRegistry) -> io::Result<()> {
+        fs::write(path, self.to_csv(registry))
+    }
+}
+
+/// Collect counts from the simulator & request new ones while the population window is open
+pub fn population_stats(
+    mut simulator: ResMut<CASimulator>,
+    mut stats: ResMut<PopulationStats>,
+    settings: Res<DynamicSettings>,
+    selection: Res<Selection>,
+) {
+    if !settings.show_population {
+        return;
+    }
+    if let Some(population) = simulator.take_population() {
+        stats.push(population);
+    }
+    if stats.is_due(simulator.sim_step) {
+        let (min, size) = selection
+            .region()
+            .filter(|_| stats.selection_only)
+            .unwrap_or((IVec2::ZERO, UVec2::new(CANVAS_SIZE_X, CANVAS_SIZE_Y)));
+        simulator.request_population(min, size);
+        stats.last_request = Some(simulator.sim_step);
+    }
+}
+
+/// Egui window with a table of the latest counts & a plot of their history
+pub fn population_interface(
+    vulkano_windows: NonSend<BevyVulkanoWindows>,
+    mut settings: ResMut<DynamicSettings>,
+    mut stats: ResMut<PopulationStats>,
+    simulator: Res<CASimulator>,
+) {
+    if !settings.show_population {
+        return;
+    }
+    let (_, gui) = vulkano_windows.get_primary_window_renderer().unwrap();
+    let ctx = gui.context();
+    let registry = simulator.registry();
+    egui::Window::new("Population")
+        .open(&mut settings.show_population)
+        .vscroll(true)
+        .show(&ctx, |ui| {
+            ui.add(egui::Slider::new(&mut stats.interval, 1..=120).text("Steps between counts"));
+            ui.checkbox(&mut stats.selection_only, "Count selection only");
+            ui.horizontal(|ui| {
+                if ui.button("Export CSV").clicked() {
+                    let path = Path::new(POPULATION_EXPORT_PATH);
+                    stats.message = Some(match stats.export(path, registry) {
+                        Ok(()) => format!("Exported to {:?}", path),
+                        Err(e) => format!("Failed to export {:?}: {}", path, e),
+                    });
+                }
+                if ui.button("Clear").clicked() {
+                    stats.history.clear();
+                }
+            });
+            if let Some(message) = &stats.message {
+                ui.label(message.as_str());
+            }
+            let latest = match stats.history.back() {
+                Some(latest) => latest,
+                None => {
+                    ui.label("Counting...");
+                    return;
+                }
+            };
+            ui.separator();
+            let total = latest.size.x * latest.size.y;
+            ui.label(format!("Step {}, {} cells", latest.sim_step, total));
+            egui::Grid::new("Population table")
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.strong("Matter");
+                    ui.strong("Cells");
+                    ui.strong("Share");
+                    ui.end_row();
+                    for (id, count) in latest.present() {
+                        ui.label(registry.name(id));
+                        ui.label(count.to_string());
+                        ui.label(format!("{:.2} %", 100.0 * count as f64 / total as f64));
+                        ui.end_row();
+                    }
+                });
+            ui.separator();
+            let mut ids = stats
+                .history
+                .iter()
+                .flat_map(|population| population.present().into_iter().map(|(id, _)| id))
+                .filter(|&id| id != MatterId::EMPTY)
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids.dedup();
+            egui::plot::Plot::new("Population plot")
+                .height(200.0)
+                .include_y(0.0)
+                .legend(egui::plot::Legend::default())
+                .show(ui, |plot_ui| {
+                    for id in ids {
+                        let values = stats.history.iter().map(|population| {
+                            egui::plot::Value::new(population.sim_step, population.count(id))
+                        });
+                        let line =
+                            egui::plot::Line::new(egui::plot::Values::from_values_iter(values))
+                                .name(registry.name(id));
+                        plot_ui.line(line);
+                    }
+                });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{IVec2, UVec2};
+
+    use crate::{
+        matter::{MatterId, MatterRegistry, MAX_MATTERS},
+        population::{Population, PopulationStats},
+    };
+
+    fn population(sim_step: u32, sand: u32) -> Population {
+        let mut counts = vec![0; MAX_MATTERS];
+        counts[MatterId::EMPTY.0 as usize] = 4 - sand;
+        counts[MatterId::SAND.0 as usize] = sand;
+        Population {
+            sim_step,
+            min: IVec2::ZERO,
+            size: UVec2::new(2, 2),
+            counts,
+        }
+    }
+
+    #[test]
+    fn test_population_history() {
+        let registry = MatterRegistry::builtin();
+        let mut stats = PopulationStats::default();
+        stats.push(population(10, 1));
+        stats.push(population(20, 3));
+        assert_eq!(
+            stats.history.back().unwrap().present(),
+            vec![(MatterId::SAND, 3), (MatterId::EMPTY, 1)]
+        );
+        assert_eq!(
+            stats.to_csv(&registry),
+            "sim_step,Empty,Sand\n10,3,1\n20,1,3\n"
+        );
+        // Counts after a rewound step are dropped
+        stats.push(population(15, 2));
+        assert_eq!(
+            stats.to_csv(&registry),
+            "sim_step,Empty,Sand\n10,3,1\n15,2,2\n"
+        );
+    }
+}
@@ -6,19 +6,24 @@ use vulkano::{
     },
     device::Queue,
     format::Format,
-    image::ImageAccess,
+    image::{ImageAccess, ImageViewAbstract},
     render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
     sync::GpuFuture,
 };
-use vulkano_util::renderer::{DeviceImageView, SwapchainImageView};
+use vulkano_util::renderer::DeviceImageView;
 
-use crate::{camera::OrthographicCamera, quad_pipeline::DrawQuadPipeline};
+use crate::{
+    bloom::{BloomPass, BloomSettings},
+    camera::OrthographicCamera,
+    quad_pipeline::DrawQuadPipeline,
+};
 
-/// A render pass which places an image over screen frame
+/// A render pass which places an image over screen frame, or an offscreen image of the same format
 pub struct FillScreenRenderPass {
     gfx_queue: Arc<Queue>,
     render_pass: Arc<RenderPass>,
     quad_pipeline: DrawQuadPipeline,
+    bloom: BloomPass,
 }
 
 impl FillScreenRenderPass {
@@ -41,21 +46,27 @@ impl FillScreenRenderPass {
         .unwrap();
         let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
         let quad_pipeline = DrawQuadPipeline::new(gfx_queue.clone(), subpass);
+        let bloom = BloomPass::new(gfx_queue.clone());
         FillScreenRenderPass {
             gfx_queue,
             render_pass,
             quad_pipeline,
+            bloom,
         }
     }
 
-    /// Place view exactly over swapchain image target.
-    /// Texture draw pipeline uses a quad onto which it places the view.
+    /// Place view exactly over target image (swapchain or offscreen image of the output format, any
+    /// size). Texture draw pipeline uses a quad onto which it places the view.
+    /// If given an emission image & bloom is enabled, its bloom is added over the image first.
+    #[allow(clippy::too_many_arguments)]
     pub fn draw<F>(
         &mut self,
         before_future: F,
         camera: OrthographicCamera,
         image: DeviceImageView,
-        target: SwapchainImageView,
+        emission: Option<DeviceImageView>,
+        bloom: &BloomSettings,
+        target: Arc<dyn ImageViewAbstract>,
         clear_color: [f32; 4],
         flip_x: bool,
         flip_y: bool,
@@ -78,6 +89,13 @@ impl FillScreenRenderPass {
             CommandBufferUsage::OneTimeSubmit,
         )
         .unwrap();
+        let image = match emission {
+            Some(emission) if bloom.enabled => {
+                self.bloom
+                    .record(&mut command_buffer_builder, image, emission, bloom)
+            }
+            _ => image,
+        };
         command_buffer_builder
             .begin_render_pass(
                 RenderPassBeginInfo {
@@ -0,0 +1,562 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::math::{IVec2, UVec2, Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    brush::{Brush, BrushMode},
+    ca_simulator::CASimulator,
+    flood_fill::FloodFill,
+    matter::{MatterId, RegistrySnapshot},
+    selection::{CellRegion, PasteMode},
+    shapes::{Shape, ShapeStyle},
+    DynamicSettings, CANVAS_SIZE_X, CANVAS_SIZE_Y,
+};
+
+/// A command that mutates the simulated world. Everything that changes the world goes through these, so that
+/// the world can be recorded and reproduced exactly.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum SimCommand {
+    /// Draw a line of matter from start to end (canvas coordinates)
+    Draw {
+        start: [f32; 2],
+        end: [f32; 2],
+        radius: f32,
+        matter: MatterId,
+        #[serde(default)]
+        brush: Brush,
+    },
+    /// Replace the connected region of same matter cells at pos with matter
+    FloodFill {
+        pos: [i32; 2],
+        matter: MatterId,
+        fill: FloodFill,
+    },
+    /// Draw a line, rectangle, ellipse or polygon of matter, cells are restricted by mode
+    DrawShape {
+        shape: Shape,
+        style: ShapeStyle,
+        matter: MatterId,
+        mode: BrushMode,
+    },
+    /// Empty a rectangular region of cells
+    ClearRegion {
+        min: [i32; 2],
+        size: [u32; 2],
+    },
+    /// Paste a block of cells with its min corner at pos
+    Paste {
+        pos: [i32; 2],
+        region: CellRegion,
+        mode: PasteMode,
+    },
+    /// Overwrite a rectangular region of cells (used by undo & redo)
+    WriteRegion {
+        min: [i32; 2],
+        size: [u32; 2],
+        cells: Vec<u32>,
+    },
+    /// Switch to other matter definitions, e.g. a reloaded matter pack. Cells of removed matters
+    /// are emptied.
+    SetMatterPack(RegistrySnapshot),
+    /// Give a matter a new name, keeping its id
+    RenameMatter {
+        from: String,
+        to: String,
+    },
+    SetMoveSteps(u32),
+    SetPaused(bool),
+    /// Step once even if paused
+    StepOnce,
+}
+
+/// A command and the `sim_step` at which it was applied (before that step was simulated)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecordedCommand {
+    pub sim_step: u32,
+    pub command: SimCommand,
+}
+
+/// The world a recording started from
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReplayStart {
+    pub sim_step: u32,
+    pub move_step: u32,
+    pub move_steps: u32,
+    pub is_paused: bool,
+    pub matters: RegistrySnapshot,
+    /// Grid as runs of (count, cell), most of the grid is long runs of empty cells
+    pub grid: Vec<(u32, u32)>,
+}
+
+impl ReplayStart {
+    pub fn new(simulator: &CASimulator, settings: &DynamicSettings) -> ReplayStart {
+        let (sim_step, move_step) = simulator.step_counters();
+        ReplayStart {
+            sim_step,
+            move_step,
+            move_steps: settings.move_steps,
+            is_paused: settings.is_paused,
+            matters: RegistrySnapshot::new("Recorded", simulator.registry()),
+            grid: run_length_encode(&simulator.read_grid()),
+        }
+    }
+
+    /// Grid cells, none if the runs don't cover the grid exactly
+    fn cells(&self) -> Option<Vec<u32>> {
+        let len = (CANVAS_SIZE_X * CANVAS_SIZE_Y) as usize;
+        let mut cells = Vec::with_capacity(len);
+        for &(count, cell) in &self.grid {
+            if cells.len() + count as usize > len {
+                return None;
+            }
+            cells.extend(std::iter::repeat(cell).take(count as usize));
+        }
+        Some(cells).filter(|cells| cells.len() == len)
+    }
+
+    /// Put the world back the way it was when recording started
+    pub fn restore(&self, simulator: &mut CASimulator, settings: &mut DynamicSettings) {
+        match self.matters.resolve() {
+            Ok(registry) => simulator.set_registry(registry),
+            Err(e) => bevy::log::error!("Failed to restore the replay's matters: {}", e),
+        }
+        if let Some(cells) = self.cells() {
+            let size = UVec2::new(CANVAS_SIZE_X, CANVAS_SIZE_Y);
+            simulator.write_region(IVec2::ZERO, size, &cells);
+        }
+        simulator.set_step_counters(self.sim_step, self.move_step);
+        settings.move_steps = self.move_steps;
+        settings.is_paused = self.is_paused;
+        settings.step_once = false;
+    }
+}
+
+/// Runs of equal cells as (count, cell)
+fn run_length_encode(cells: &[u32]) -> Vec<(u32, u32)> {
+    let mut runs: Vec<(u32, u32)> = vec![];
+    for &cell in cells {
+        match runs.last_mut() {
+            Some((count, last)) if *last == cell => *count += 1,
+            _ => runs.push((1, cell)),
+        }
+    }
+    runs
+}
+
+/// Recorded world mutating commands
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Replay {
+    /// None for replays recorded from a new world
+    #[serde(default)]
+    pub start: Option<ReplayStart>,
+    pub commands: Vec<RecordedCommand>,
+    /// The step at which recording ended, replays run until here
+    pub last_step: u32,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> io::Result<Replay> {
+        let text = fs::read_to_string(path)?;
+        let replay: Replay =
+            ron::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if let Some(start) = &replay.start {
+            if start.cells().is_none() {
+                let message = "the start grid does not match the canvas size";
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            }
+        }
+        Ok(replay)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, text)
+    }
+}
+
+pub enum ReplayMode {
+    Idle,
+    Recording(Replay),
+    Playing { replay: Replay, next: usize },
+}
+
+/// Records and plays back world mutating commands
+pub struct ReplayState {
+    pub mode: ReplayMode,
+    /// Where recordings are saved
+    pub path: PathBuf,
+}
+
+impl ReplayState {
+    pub fn new(path: PathBuf) -> ReplayState {
+        ReplayState {
+            mode: ReplayMode::Idle,
+            path,
+        }
+    }
+
+    /// Play a replay, starting from the world it was recorded from
+    pub fn playing(
+        replay: Replay,
+        path: PathBuf,
+        simulator: &mut CASimulator,
+        settings: &mut DynamicSettings,
+    ) -> ReplayState {
+        if let Some(start) = &replay.start {
+            start.restore(simulator, settings);
+        }
+        ReplayState {
+            mode: ReplayMode::Playing { replay, next: 0 },
+            path,
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        matches!(self.mode, ReplayMode::Recording(_))
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(self.mode, ReplayMode::Playing { .. })
+    }
+
+    /// Start recording from the current world, which is saved along with the commands
+    pub fn start_recording(&mut self, simulator: &CASimulator, settings: &DynamicSettings) {
+        self.mode = ReplayMode::Recording(Replay {
+            start: Some(ReplayStart::new(simulator, settings)),
+            commands: vec![],
+            last_step: simulator.sim_step,
+        });
+    }
+
+    /// Stop recording and save the replay to `path`
+    pub fn stop_recording(&mut self, simulator: &CASimulator) -> io::Result<()> {
+        match self.finish_recording(simulator) {
+            Some(replay) => replay.save(&self.path),
+            None => Ok(()),
+        }
+    }
+
+    /// Stop recording, none if we weren't recording
+    pub fn finish_recording(&mut self, simulator: &CASimulator) -> Option<Replay> {
+        let mode = std::mem::replace(&mut self.mode, ReplayMode::Idle);
+        match mode {
+            ReplayMode::Recording(mut replay) => {
+                replay.last_step = simulator.sim_step;
+                Some(replay)
+            }
+            mode => {
+                self.mode = mode;
+                None
+            }
+        }
+    }
+
+    /// Apply a user command to the world and record it if we're recording. User commands are ignored
+    /// while a replay is playing.
+    pub fn execute(
+        &mut self,
+        command: SimCommand,
+        simulator: &mut CASimulator,
+        settings: &mut DynamicSettings,
+    ) {
+        if self.record(&command, simulator) {
+            apply_command(&command, simulator, settings);
+        }
+    }
+
+    /// Record a user command if we're recording, for callers that apply it themselves. Returns false
+    /// while a replay is playing, when the command must not be applied.
+    pub fn record(&mut self, command: &SimCommand, simulator: &CASimulator) -> bool {
+        match &mut self.mode {
+            ReplayMode::Playing { .. } => return false,
+            ReplayMode::Recording(replay) => replay.commands.push(RecordedCommand {
+                sim_step: simulator.sim_step,
+                command: command.clone(),
+            }),
+            ReplayMode::Idle => (),
+        }
+        true
+    }
+
+    /// Apply commands that are due (if playing) and step the simulation
+    pub fn step(&mut self, simulator: &mut CASimulator, settings: &mut DynamicSettings) {
+        self.apply_due(simulator, settings);
+        let is_paused = settings.is_paused && !settings.step_once;
+        settings.step_once = false;
+        simulator.step(settings.move_steps, is_paused);
+    }
+
+    /// Apply replayed commands recorded at or before the current `sim_step`
+    pub fn apply_due(&mut self, simulator: &mut CASimulator, settings: &mut DynamicSettings) {
+        if let ReplayMode::Playing { replay, next } = &mut self.mode {
+            while let Some(recorded) = replay.commands.get(*next) {
+                if recorded.sim_step > simulator.sim_step {
+                    break;
+                }
+                apply_command(&recorded.command, simulator, settings);
+                *next += 1;
+            }
+            if *next >= replay.commands.len() && simulator.sim_step >= replay.last_step {
+                bevy::log::info!("Replay finished at step {}", simulator.sim_step);
+                self.mode = ReplayMode::Idle;
+            }
+        }
+    }
+}
+
+/// Apply a command to the world
+pub fn apply_command(
+    command: &SimCommand,
+    simulator: &mut CASimulator,
+    settings: &mut DynamicSettings,
+) {
+    match command {
+        SimCommand::Draw {
+            start,
+            end,
+            radius,
+            matter,
+            brush,
+        } => simulator.draw_matter(
+            Vec2::from(*start),
+            Vec2::from(*end),
+            *radius,
+            *matter,
+            *brush,
+        ),
+        SimCommand::FloodFill {
+            pos,
+            matter,
+            fill,
+        } => simulator.flood_fill(IVec2::from(*pos), *matter, fill),
+        SimCommand::DrawShape {
+            shape,
+            style,
+            matter,
+            mode,
+        } => simulator.draw_shape(shape, style, *matter, *mode),
+        SimCommand::ClearRegion {
+            min,
+            size,
+        } => simulator.clear_region(IVec2::from(*min), UVec2::from(*size)),
+        SimCommand::Paste {
+            pos,
+            region,
+            mode,
+        } => simulator.paste_region(IVec2::from(*pos), region, *mode),
+        SimCommand::WriteRegion {
+            min,
+            size,
+            cells,
+        } => simulator.write_region(IVec2::from(*min), UVec2::from(*size), cells),
+        SimCommand::SetMatterPack(snapshot) => match snapshot.resolve() {
+            Ok(registry) => simulator.set_registry(registry),
+            Err(e) => {
+                bevy::log::error!("Failed to set matter pack '{}': {}", snapshot.pack.name, e)
+            }
+        },
+        SimCommand::RenameMatter { from, to } => {
+            simulator.rename_matter(from, to);
+        }
+        SimCommand::SetMoveSteps(move_steps) => settings.move_steps = *move_steps,
+        SimCommand::SetPaused(is_paused) => settings.is_paused = *is_paused,
+        SimCommand::StepOnce => settings.step_once = true,
+    }
+}
+
+/// Play a replay to its end without a window
+pub fn play_headless(simulator: &mut CASimulator, replay: &Replay) {
+    let mut settings = DynamicSettings::default();
+    let mut state = ReplayState::playing(replay.clone(), PathBuf::new(), simulator, &mut settings);
+    while simulator.sim_step < replay.last_step {
+        state.step(simulator, &mut settings);
+    }
+    // Commands recorded after the last step
+    state.apply_due(simulator, &mut settings);
+}
+
+/// A simple FNV-1a hash of the grid so that replayed worlds can be compared
+pub fn grid_checksum(grid: &[u32]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for cell in grid {
+        for byte in cell.to_le_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::IVec2;
+    use vulkano_util::context::VulkanoContext;
+
+    use std::path::PathBuf;
+
+    use crate::{
+        brush::{Brush, BrushMode, BrushShape},
+        ca_simulator::CASimulator,
+        matter::{MatterId, MatterPack, MatterRegistry, RegistrySnapshot},
+        replay::{play_headless, RecordedCommand, Replay, ReplayState, SimCommand},
+        shapes::{Shape, ShapeStyle},
+        DynamicSettings,
+    };
+
+    fn test_replay() -> Replay {
+        Replay {
+            start: None,
+            commands: vec![
+                RecordedCommand {
+                    sim_step: 0,
+                    command: SimCommand::Draw {
+                        start: [10.0, 50.0],
+                        end: [40.0, 50.0],
+                        radius: 3.0,
+                        matter: MatterId::SAND,
+                        brush: Brush::default(),
+                    },
+                },
+                RecordedCommand {
+                    sim_step: 5,
+                    command: SimCommand::SetPaused(true),
+                },
+                RecordedCommand {
+                    sim_step: 8,
+                    command: SimCommand::Draw {
+                        start: [20.0, 30.0],
+                        end: [20.0, 30.0],
+                        radius: 5.0,
+                        matter: MatterId::WATER,
+                        brush: Brush {
+                            shape: BrushShape::Spray,
+                            ..Brush::default()
+                        },
+                    },
+                },
+                RecordedCommand {
+                    sim_step: 10,
+                    command: SimCommand::SetPaused(false),
+                },
+                RecordedCommand {
+                    sim_step: 12,
+                    command: SimCommand::DrawShape {
+                        shape: Shape::Ellipse {
+                            corner_a: [30, 10],
+                            corner_b: [60, 25],
+                        },
+                        style: ShapeStyle::default(),
+                        matter: MatterId::ROCK,
+                        mode: BrushMode::ReplaceEmpty,
+                    },
+                },
+            ],
+            last_step: 40,
+        }
+    }
+
+    #[test]
+    fn test_replay_roundtrip() {
+        let replay = test_replay();
+        let text = ron::ser::to_string(&replay).unwrap();
+        assert_eq!(ron::from_str::<Replay>(&text).unwrap(), replay);
+    }
+
+    #[test]
+    fn test_replay_is_deterministic() {
+        let ctx = VulkanoContext::default();
+        let replay = test_replay();
+        let mut first = CASimulator::new(ctx.compute_queue());
+        play_headless(&mut first, &replay);
+        let mut second = CASimulator::new(ctx.compute_queue());
+        play_headless(&mut second, &replay);
+        assert_eq!(first.sim_step, replay.last_step);
+        assert_eq!(first.read_grid(), second.read_grid());
+    }
+    #[test]
+    fn test_replay_from_mid_session() {
+        let ctx = VulkanoContext::default();
+        let mut simulator = CASimulator::new(ctx.compute_queue());
+        let mut settings = DynamicSettings::default();
+        let mut state = ReplayState::new(PathBuf::new());
+        // Edits, steps & settings before the recording starts
+        let sand = SimCommand::Draw {
+            start: [10.0, 20.0],
+            end: [60.0, 20.0],
+            radius: 4.0,
+            matter: MatterId::SAND,
+            brush: Brush::default(),
+        };
+        state.execute(sand, &mut simulator, &mut settings);
+        state.execute(SimCommand::SetMoveSteps(2), &mut simulator, &mut settings);
+        for _ in 0..7 {
+            state.step(&mut simulator, &mut settings);
+        }
+        state.start_recording(&simulator, &settings);
+        let water = SimCommand::Draw {
+            start: [30.0, 5.0],
+            end: [30.0, 5.0],
+            radius: 3.0,
+            matter: MatterId::WATER,
+            brush: Brush::default(),
+        };
+        state.execute(water, &mut simulator, &mut settings);
+        for _ in 0..10 {
+            state.step(&mut simulator, &mut settings);
+        }
+        let replay = state.finish_recording(&simulator).unwrap();
+        let text = ron::ser::to_string(&replay).unwrap();
+        let replay = ron::from_str::<Replay>(&text).unwrap();
+        // Replayed in a new world
+        let mut replayed = CASimulator::new(ctx.compute_queue());
+        play_headless(&mut replayed, &replay);
+        assert_eq!(replayed.step_counters(), simulator.step_counters());
+        assert_eq!(replayed.read_grid(), simulator.read_grid());
+    }
+
+    #[test]
+    fn test_replay_sets_matter_pack() {
+        let ctx = VulkanoContext::default();
+        let mut pack = MatterPack::builtin();
+        pack.matters.retain(|matter| matter.name != "Rock");
+        for matter in &mut pack.matters {
+            matter
+                .reactions
+                .retain(|reaction| reaction.becomes != "Rock");
+        }
+        let registry = pack.resolve(Some(&MatterRegistry::builtin())).unwrap();
+        let replay = Replay {
+            start: None,
+            commands: vec![
+                RecordedCommand {
+                    sim_step: 0,
+                    command: SimCommand::Draw {
+                        start: [10.0, 50.0],
+                        end: [40.0, 50.0],
+                        radius: 3.0,
+                        matter: MatterId::ROCK,
+                        brush: Brush::default(),
+                    },
+                },
+                RecordedCommand {
+                    sim_step: 2,
+                    command: SimCommand::SetMatterPack(RegistrySnapshot::new("No rock", &registry)),
+                },
+            ],
+            last_step: 4,
+        };
+        let mut simulator = CASimulator::new(ctx.compute_queue());
+        play_headless(&mut simulator, &replay);
+        // Rock was removed by the pack change
+        assert_eq!(simulator.registry().id("Rock"), None);
+        assert_eq!(
+            simulator.query_matter(IVec2::new(20, 50)),
+            Some(MatterId::EMPTY)
+        );
+    }
+}
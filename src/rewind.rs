@@ -0,0 +1,86 @@
+use std::collections::VecDeque;
+
+use crate::ca_simulator::{CASimulator, GridSnapshot};
+
+/// Take a snapshot every this many steps
+pub const DEFAULT_SNAPSHOT_INTERVAL: u32 = 30;
+/// With the default interval, one minute of history at `SIM_FPS`
+pub const DEFAULT_HISTORY_CAPACITY: usize = 120;
+
+/// Ring buffer of periodic gpu-side snapshots of the grid, to scrub back through recent history
+pub struct History {
+    snapshots: VecDeque<GridSnapshot>,
+    pub interval: u32,
+    pub capacity: usize,
+    /// Snapshot we have rewound to and are inspecting
+    pub inspecting: Option<usize>,
+}
+
+impl History {
+    pub fn new(interval: u32, capacity: usize) -> History {
+        History {
+            snapshots: VecDeque::new(),
+            interval,
+            capacity,
+            inspecting: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// Step of the snapshot at index
+    pub fn sim_step(&self, index: usize) -> Option<u32> {
+        self.snapshots.get(index).map(|snapshot| snapshot.sim_step)
+    }
+
+    /// Take a snapshot if it's time to
+    pub fn record(&mut self, simulator: &CASimulator) {
+        if self.interval == 0 || simulator.sim_step % self.interval != 0 {
+            return;
+        }
+        let reuse = if self.snapshots.len() >= self.capacity {
+            self.snapshots.pop_front()
+        } else {
+            None
+        };
+        self.snapshots.push_back(simulator.snapshot(reuse));
+        self.shrink_to_capacity();
+    }
+
+    /// Rewind simulation to snapshot at index
+    pub fn rewind(&mut self, simulator: &mut CASimulator, index: usize) {
+        if let Some(snapshot) = self.snapshots.get(index) {
+            simulator.restore(snapshot);
+            self.inspecting = Some(index);
+        }
+    }
+
+    /// Continue simulating from the snapshot we had rewound to. History after that point is dropped, as
+    /// are the steps counted while paused on it.
+    pub fn resume(&mut self, simulator: &mut CASimulator) {
+        if let Some(index) = self.inspecting.take() {
+            self.snapshots.truncate(index + 1);
+            simulator.restore_steps(&self.snapshots[index]);
+        }
+    }
+
+    /// Drop oldest snapshots if capacity was lowered
+    pub fn shrink_to_capacity(&mut self) {
+        while self.snapshots.len() > self.capacity.max(1) {
+            self.snapshots.pop_front();
+            self.inspecting = self.inspecting.and_then(|index| index.checked_sub(1));
+        }
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new(DEFAULT_SNAPSHOT_INTERVAL, DEFAULT_HISTORY_CAPACITY)
+    }
+}
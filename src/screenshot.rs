@@ -0,0 +1,339 @@
+use std::{error::Error, fmt, fs, io, path::Path, sync::Arc};
+
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer},
+    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, CopyImageToBufferInfo},
+    device::Queue,
+    format::Format,
+    image::{view::ImageView, AttachmentImage, ImageUsage},
+    sync::{self, GpuFuture},
+    DeviceSize,
+};
+
+use crate::{
+    bloom::BloomSettings, ca_simulator::CASimulator, camera::OrthographicCamera,
+    render::FillScreenRenderPass, CANVAS_SIZE_Y, CLEAR_COLOR,
+};
+
+/// Format of offscreen render targets, sRGB like the swapchain so that screenshots look like the
+/// window
+pub const OFFSCREEN_FORMAT: Format = Format::R8G8B8A8_SRGB;
+/// Largest difference of a color channel allowed between a screenshot & its golden image
+pub const GOLDEN_TOLERANCE: u8 = 2;
+
+#[derive(Debug)]
+pub enum ScreenshotError {
+    Io(io::Error),
+    InvalidPpm(String),
+}
+
+impl fmt::Display for ScreenshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScreenshotError::Io(e) => write!(f, "{}", e),
+            ScreenshotError::InvalidPpm(reason) => write!(f, "invalid PPM image: {}", reason),
+        }
+    }
+}
+
+impl Error for ScreenshotError {}
+
+impl From<io::Error> for ScreenshotError {
+    fn from(e: io::Error) -> Self {
+        ScreenshotError::Io(e)
+    }
+}
+
+/// A rendered frame read back to the cpu
+#[derive(Debug, Clone, PartialEq)]
+pub struct Screenshot {
+    pub width: u32,
+    pub height: u32,
+    /// RGB, rows top down
+    pub pixels: Vec<u8>,
+}
+
+impl Screenshot {
+    #[cfg(test)]
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 3] {
+        let i = 3 * (y * self.width + x) as usize;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]]
+    }
+
+    /// Image as binary PPM (P6)
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut ppm = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        ppm.extend_from_slice(&self.pixels);
+        ppm
+    }
+
+    /// Parses a binary PPM (P6) with 8 bit channels
+    pub fn from_ppm(bytes: &[u8]) -> Result<Screenshot, ScreenshotError> {
+        let invalid = |reason: &str| ScreenshotError::InvalidPpm(reason.to_string());
+        // Header fields are separated by whitespace & may be followed by comments
+        let mut fields = vec![];
+        let mut pos = 0;
+        while fields.len() < 4 {
+            while pos < bytes.len() && (bytes[pos].is_ascii_whitespace() || bytes[pos] == b'#') {
+                if bytes[pos] == b'#' {
+                    while pos < bytes.len() && bytes[pos] != b'\n' {
+                        pos += 1;
+                    }
+                } else {
+                    pos += 1;
+                }
+            }
+            let start = pos;
+            while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if start == pos {
+                return Err(invalid("header ends early"));
+            }
+            fields.push(&bytes[start..pos]);
+        }
+        if fields[0] != b"P6" {
+            return Err(invalid("not a binary PPM (P6)"));
+        }
+        let number = |field: &[u8]| {
+            std::str::from_utf8(field)
+                .ok()
+                .and_then(|field| field.parse::<u32>().ok())
+                .ok_or_else(|| invalid("header field is not a number"))
+        };
+        let (width, height) = (number(fields[1])?, number(fields[2])?);
+        if number(fields[3])? != 255 {
+            return Err(invalid("only 8 bit channels are supported"));
+        }
+        // A single whitespace separates the header from the pixels
+        let len = 3 * (width * height) as usize;
+        let pixels = bytes
+            .get(pos + 1..pos + 1 + len)
+            .ok_or_else(|| invalid("fewer pixels than its size"))?;
+        Ok(Screenshot {
+            width,
+            height,
+            pixels: pixels.to_vec(),
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_ppm())
+    }
+
+    pub fn load(path: &Path) -> Result<Screenshot, ScreenshotError> {
+        Screenshot::from_ppm(&fs::read(path)?)
+    }
+
+    /// Largest difference of any color channel, None if the sizes differ
+    pub fn max_difference(&self, other: &Screenshot) -> Option<u8> {
+        if (self.width, self.height) != (other.width, other.height) {
+            return None;
+        }
+        Some(
+            self.pixels
+                .iter()
+                .zip(&other.pixels)
+                .map(|(a, b)| a.abs_diff(*b))
+                .max()
+                .unwrap_or(0),
+        )
+    }
+}
+
+/// Outcome of comparing a screenshot to its golden image
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GoldenResult {
+    /// There was no golden image, the screenshot was saved as one
+    Created,
+    Matches,
+    /// Largest channel difference, None if the sizes differ
+    Differs(Option<u8>),
+}
+
+/// Compare screenshot to the golden image at path within `GOLDEN_TOLERANCE`, saving it as the golden
+/// image if there is none yet
+pub fn check_golden(screenshot: &Screenshot, path: &Path) -> Result<GoldenResult, ScreenshotError> {
+    if !path.exists() {
+        screenshot.save(path)?;
+        return Ok(GoldenResult::Created);
+    }
+    let golden = Screenshot::load(path)?;
+    Ok(match screenshot.max_difference(&golden) {
+        Some(difference) if difference <= GOLDEN_TOLERANCE => GoldenResult::Matches,
+        difference => GoldenResult::Differs(difference),
+    })
+}
+
+/// Camera fitting the canvas height in an image of size, like the window on start
+pub fn canvas_camera(size: [u32; 2]) -> OrthographicCamera {
+    let mut camera = OrthographicCamera::default();
+    camera.update(size[0] as f32, size[1] as f32);
+    camera.zoom_to_fit_vertical_pixels(CANVAS_SIZE_Y, size[1]);
+    camera
+}
+
+/// Renders frames like the window does into offscreen images, without a window
+pub struct OffscreenRenderer {
+    gfx_queue: Arc<Queue>,
+    fill_screen: FillScreenRenderPass,
+}
+
+impl OffscreenRenderer {
+    pub fn new(gfx_queue: Arc<Queue>) -> OffscreenRenderer {
+        let fill_screen = FillScreenRenderPass::new(gfx_queue.clone(), OFFSCREEN_FORMAT);
+        OffscreenRenderer {
+            gfx_queue,
+            fill_screen,
+        }
+    }
+
+    /// Render the simulator's canvas as framed by camera into an image of size & read it back. The
+    /// camera's transform is used as is, so a window's camera frames the same view at any size.
+    pub fn render(
+        &mut self,
+        simulator: &CASimulator,
+        camera: OrthographicCamera,
+        bloom: &BloomSettings,
+        size: [u32; 2],
+    ) -> Screenshot {
+        let device = self.gfx_queue.device().clone();
+        let image = AttachmentImage::with_usage(
+            device.clone(),
+            size,
+            OFFSCREEN_FORMAT,
+            ImageUsage {
+                transfer_src: true,
+                ..ImageUsage::none()
+            },
+        )
+        .unwrap();
+        let target = ImageView::new_default(image.clone()).unwrap();
+        let after_draw = self.fill_screen.draw(
+            sync::now(device.clone()),
+            camera,
+            simulator.color_image(),
+            Some(simulator.emission_image()),
+            bloom,
+            target,
+            CLEAR_COLOR,
+            false,
+            true,
+        );
+        let readback = unsafe {
+            CpuAccessibleBuffer::<[u8]>::uninitialized_array(
+                device.clone(),
+                (4 * size[0] * size[1]) as DeviceSize,
+                BufferUsage::transfer_dst(),
+                true,
+            )
+        }
+        .unwrap();
+        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+            device,
+            self.gfx_queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        command_buffer_builder
+            .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(image, readback.clone()))
+            .unwrap();
+        let command_buffer = command_buffer_builder.build().unwrap();
+        after_draw
+            .then_execute(self.gfx_queue.clone(), command_buffer)
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+        let rgba = readback.read().unwrap();
+        Screenshot {
+            width: size[0],
+            height: size[1],
+            pixels: rgba
+                .chunks(4)
+                .flat_map(|pixel| pixel[..3].iter().copied())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+    use vulkano_util::context::VulkanoContext;
+
+    use crate::{
+        bloom::BloomSettings,
+        brush::Brush,
+        ca_simulator::CASimulator,
+        matter::MatterId,
+        screenshot::{canvas_camera, OffscreenRenderer, Screenshot},
+        CANVAS_SIZE_X, CANVAS_SIZE_Y, CLEAR_COLOR,
+    };
+
+    #[test]
+    fn test_ppm_roundtrip() {
+        let screenshot = Screenshot {
+            width: 2,
+            height: 1,
+            pixels: vec![255, 0, 0, 10, 20, 30],
+        };
+        let ppm = screenshot.to_ppm();
+        assert!(ppm.starts_with(b"P6\n2 1\n255\n"));
+        assert_eq!(Screenshot::from_ppm(&ppm).unwrap(), screenshot);
+        // Comments in the header are skipped
+        let commented = [
+            b"P6\n# golden\n2 1\n255\n".as_slice(),
+            screenshot.pixels.as_slice(),
+        ]
+        .concat();
+        assert_eq!(Screenshot::from_ppm(&commented).unwrap(), screenshot);
+        assert!(Screenshot::from_ppm(&ppm[..ppm.len() - 1]).is_err());
+        assert!(Screenshot::from_ppm(b"P3\n2 1\n255\n").is_err());
+        let mut brighter = screenshot.clone();
+        brighter.pixels[3] += 3;
+        assert_eq!(screenshot.max_difference(&brighter), Some(3));
+    }
+
+    #[test]
+    fn test_offscreen_render() {
+        let vulkano_context = VulkanoContext::default();
+        let mut simulator = CASimulator::new(vulkano_context.compute_queue());
+        let center = Vec2::new(CANVAS_SIZE_X as f32 / 2.0, CANVAS_SIZE_Y as f32 / 2.0);
+        simulator.draw_matter(center, center, 100.0, MatterId::ROCK, Brush::default());
+        simulator.color();
+        let mut renderer = OffscreenRenderer::new(vulkano_context.graphics_queue());
+        let bloom = BloomSettings {
+            enabled: false,
+            ..BloomSettings::default()
+        };
+        // Clear color as written to the srgb image
+        let clear = CLEAR_COLOR.map(|c| {
+            let srgb = if c <= 0.003_130_8 {
+                c * 12.92
+            } else {
+                1.055 * c.powf(1.0 / 2.4) - 0.055
+            };
+            (srgb * 255.0).round() as u8
+        });
+        let empty_canvas = [0, 0, 0];
+        for size in [[64, 64], [256, 128]] {
+            let screenshot = renderer.render(&simulator, canvas_camera(size), &bloom, size);
+            assert_eq!(screenshot.pixels.len(), (3 * size[0] * size[1]) as usize);
+            // Rock in the middle of the frame
+            assert_ne!(screenshot.pixel(size[0] / 2, size[1] / 2), empty_canvas);
+            // The canvas fits the height, so a square frame shows only canvas while the corner of a
+            // wide frame is outside of it
+            let corner = screenshot.pixel(0, 0);
+            if size[0] == size[1] {
+                assert_eq!(corner, empty_canvas);
+            } else {
+                assert_eq!(corner, [clear[0], clear[1], clear[2]]);
+            }
+            // Rendering is deterministic
+            let again = renderer.render(&simulator, canvas_camera(size), &bloom, size);
+            assert_eq!(again.max_difference(&screenshot), Some(0));
+        }
+    }
+}
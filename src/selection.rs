@@ -0,0 +1,321 @@
+use bevy::math::{IVec2, UVec2};
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+use crate::{
+    ca_simulator::CASimulator,
+    replay::{ReplayState, SimCommand},
+    undo::UndoStack,
+    utils::clamp_to_canvas,
+    DynamicSettings,
+};
+
+/// Rectangular block of cells (row by row, packed matter & color)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CellRegion {
+    pub width: u32,
+    pub height: u32,
+    pub cells: Vec<u32>,
+}
+
+impl CellRegion {
+    /// Read a region of the grid, the region must be inside the canvas
+    pub fn read(simulator: &CASimulator, min: IVec2, size: UVec2) -> CellRegion {
+        CellRegion {
+            width: size.x,
+            height: size.y,
+            cells: simulator.read_region(min, size),
+        }
+    }
+
+    pub fn size(&self) -> UVec2 {
+        UVec2::new(self.width, self.height)
+    }
+
+    pub fn cell(&self, pos: IVec2) -> u32 {
+        self.cells[(pos.y as u32 * self.width + pos.x as u32) as usize]
+    }
+
+    fn map_cells(&self, width: u32, height: u32, source: impl Fn(i32, i32) -> IVec2) -> CellRegion {
+        let cells = (0..height as i32)
+            .flat_map(|y| (0..width as i32).map(move |x| (x, y)))
+            .map(|(x, y)| self.cell(source(x, y)))
+            .collect();
+        CellRegion {
+            width,
+            height,
+            cells,
+        }
+    }
+
+    /// Rotated by 90 degrees, width & height swap
+    pub fn rotated(&self) -> CellRegion {
+        let h = self.height as i32;
+        self.map_cells(self.height, self.width, |x, y| IVec2::new(y, h - 1 - x))
+    }
+
+    /// Mirrored along the x axis
+    pub fn flipped_x(&self) -> CellRegion {
+        let w = self.width as i32;
+        self.map_cells(self.width, self.height, |x, y| IVec2::new(w - 1 - x, y))
+    }
+
+    /// Mirrored along the y axis
+    pub fn flipped_y(&self) -> CellRegion {
+        let h = self.height as i32;
+        self.map_cells(self.width, self.height, |x, y| IVec2::new(x, h - 1 - y))
+    }
+}
+
+/// Which grid cells a paste replaces
+#[derive(EnumIter, Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum PasteMode {
+    /// Replace every cell under the pasted region
+    #[default]
+    Over,
+    /// Replace only empty cells
+    IntoEmpty,
+}
+
+impl PasteMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PasteMode::Over => "Paste over",
+            PasteMode::IntoEmpty => "Paste into empty only",
+        }
+    }
+}
+
+/// Paste cells with their min corner at pos as a single edit
+pub fn paste_edit(
+    pos: IVec2,
+    region: CellRegion,
+    mode: PasteMode,
+    simulator: &mut CASimulator,
+    settings: &mut DynamicSettings,
+    replay: &mut ReplayState,
+    undo: &mut UndoStack,
+) {
+    undo.capture(simulator, pos, pos + region.size().as_ivec2() - IVec2::ONE);
+    let command = SimCommand::Paste {
+        pos: pos.into(),
+        region,
+        mode,
+    };
+    replay.execute(command, simulator, settings);
+    undo.end_edit();
+}
+
+/// Marquee selection & clipboard of the select tool
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    /// Where the selection drag started
+    pub drag_start: Option<IVec2>,
+    /// Inclusive bounds of the selected cells
+    pub bounds: Option<(IVec2, IVec2)>,
+    /// Cursor position relative to the selection's min corner while the selection is dragged
+    pub move_offset: Option<IVec2>,
+    pub clipboard: Option<CellRegion>,
+    /// The clipboard follows the cursor until it's placed
+    pub pasting: bool,
+    pub paste_mode: PasteMode,
+}
+
+impl Selection {
+    /// Selected region clamped to the canvas
+    pub fn region(&self) -> Option<(IVec2, UVec2)> {
+        let (min, max) = self.bounds?;
+        clamp_to_canvas(min, max)
+    }
+
+    /// Clamp the bounds to the canvas, so that moves offset from the min corner of the cells that
+    /// actually get moved. A selection outside the canvas is dropped.
+    pub fn clamp_to_canvas(&mut self) {
+        self.bounds = self
+            .region()
+            .map(|(min, size)| (min, min + size.as_ivec2() - IVec2::ONE));
+    }
+
+    pub fn contains(&self, pos: IVec2) -> bool {
+        match self.bounds {
+            Some((min, max)) => pos.cmpge(min).all() && pos.cmple(max).all(),
+            None => false,
+        }
+    }
+
+    /// Min corner of the clipboard when pasted centered at cursor
+    pub fn paste_pos(&self, cursor: IVec2) -> Option<IVec2> {
+        let clipboard = self.clipboard.as_ref()?;
+        Some(cursor - clipboard.size().as_ivec2() / 2)
+    }
+
+    pub fn deselect(&mut self) {
+        self.drag_start = None;
+        self.bounds = None;
+        self.move_offset = None;
+    }
+
+    pub fn copy(&mut self, simulator: &CASimulator) {
+        if let Some((min, size)) = self.region() {
+            self.clipboard = Some(CellRegion::read(simulator, min, size));
+        }
+    }
+
+    pub fn cut(
+        &mut self,
+        simulator: &mut CASimulator,
+        settings: &mut DynamicSettings,
+        replay: &mut ReplayState,
+        undo: &mut UndoStack,
+    ) {
+        self.copy(simulator);
+        self.clear(simulator, settings, replay, undo);
+    }
+
+    /// Empty the selected cells as a single edit
+    pub fn clear(
+        &self,
+        simulator: &mut CASimulator,
+        settings: &mut DynamicSettings,
+        replay: &mut ReplayState,
+        undo: &mut UndoStack,
+    ) {
+        if let Some((min, size)) = self.region() {
+            undo.capture(simulator, min, min + size.as_ivec2() - IVec2::ONE);
+            let command = SimCommand::ClearRegion {
+                min: min.into(),
+                size: size.into(),
+            };
+            replay.execute(command, simulator, settings);
+            undo.end_edit();
+        }
+    }
+
+    pub fn start_paste(&mut self) {
+        self.pasting = self.clipboard.is_some();
+    }
+
+    /// Paste the clipboard with its min corner at pos as a single edit. The pasted cells become the
+    /// selection.
+    pub fn paste(
+        &mut self,
+        pos: IVec2,
+        simulator: &mut CASimulator,
+        settings: &mut DynamicSettings,
+        replay: &mut ReplayState,
+        undo: &mut UndoStack,
+    ) {
+        let region = match &self.clipboard {
+            Some(region) => region.clone(),
+            None => return,
+        };
+        let max = pos + region.size().as_ivec2() - IVec2::ONE;
+        paste_edit(
+            pos,
+            region,
+            self.paste_mode,
+            simulator,
+            settings,
+            replay,
+            undo,
+        );
+        self.bounds = Some((pos, max));
+        self.pasting = false;
+    }
+
+    /// Move the selected cells so that their min corner is at pos as a single edit. The cells left
+    /// behind are emptied.
+    pub fn move_to(
+        &mut self,
+        pos: IVec2,
+        simulator: &mut CASimulator,
+        settings: &mut DynamicSettings,
+        replay: &mut ReplayState,
+        undo: &mut UndoStack,
+    ) {
+        let (min, size) = match self.region() {
+            Some(region) => region,
+            None => return,
+        };
+        if pos == min {
+            return;
+        }
+        let region = CellRegion::read(simulator, min, size);
+        let max = pos + size.as_ivec2() - IVec2::ONE;
+        undo.capture(simulator, min, min + size.as_ivec2() - IVec2::ONE);
+        undo.capture(simulator, pos, max);
+        let clear = SimCommand::ClearRegion {
+            min: min.into(),
+            size: size.into(),
+        };
+        replay.execute(clear, simulator, settings);
+        let paste = SimCommand::Paste {
+            pos: pos.into(),
+            region,
+            mode: self.paste_mode,
+        };
+        replay.execute(paste, simulator, settings);
+        undo.end_edit();
+        self.bounds = Some((pos, max));
+    }
+
+    pub fn rotate_clipboard(&mut self) {
+        if let Some(clipboard) = &mut self.clipboard {
+            *clipboard = clipboard.rotated();
+        }
+    }
+
+    /// Flip the clipboard along the x axis, or the y axis if vertical
+    pub fn flip_clipboard(&mut self, vertical: bool) {
+        if let Some(clipboard) = &mut self.clipboard {
+            *clipboard = if vertical {
+                clipboard.flipped_y()
+            } else {
+                clipboard.flipped_x()
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::IVec2;
+
+    use crate::selection::{CellRegion, Selection};
+
+    #[test]
+    fn test_region_transforms() {
+        #[rustfmt::skip]
+        let region = CellRegion {
+            width: 3,
+            height: 2,
+            cells: vec![
+                1, 2, 3,
+                4, 5, 6,
+            ],
+        };
+        let rotated = region.rotated();
+        assert_eq!((rotated.width, rotated.height), (2, 3));
+        assert_eq!(rotated.cells, vec![4, 1, 5, 2, 6, 3]);
+        // Four rotations are the identity
+        assert_eq!(rotated.rotated().rotated().rotated(), region);
+        assert_eq!(region.flipped_x().cells, vec![3, 2, 1, 6, 5, 4]);
+        assert_eq!(region.flipped_y().cells, vec![4, 5, 6, 1, 2, 3]);
+        assert_eq!(region.flipped_x().flipped_x(), region);
+    }
+
+    #[test]
+    fn test_clamp_selection() {
+        let mut selection = Selection {
+            bounds: Some((IVec2::new(-5, -3), IVec2::new(4, 6))),
+            ..Default::default()
+        };
+        selection.clamp_to_canvas();
+        assert_eq!(selection.bounds, Some((IVec2::ZERO, IVec2::new(4, 6))));
+        // The region, and so moves, start at the clamped min corner
+        assert_eq!(selection.region().unwrap().0, IVec2::ZERO);
+        selection.bounds = Some((IVec2::new(-9, -9), IVec2::new(-1, -1)));
+        selection.clamp_to_canvas();
+        assert_eq!(selection.bounds, None);
+    }
+}
@@ -0,0 +1,277 @@
+use bevy::math::{IVec2, Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::{utils::clamp_to_canvas, Tool};
+
+/// Vertices used to preview an ellipse
+const ELLIPSE_PREVIEW_SEGMENTS: usize = 48;
+
+/// Geometric shape in canvas cells, corners & end points are inclusive
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Shape {
+    Line {
+        start: [i32; 2],
+        end: [i32; 2],
+    },
+    /// Axis aligned rectangle between two opposite corners
+    Rectangle {
+        corner_a: [i32; 2],
+        corner_b: [i32; 2],
+    },
+    /// Ellipse inscribed in the rectangle between two opposite corners
+    Ellipse {
+        corner_a: [i32; 2],
+        corner_b: [i32; 2],
+    },
+    /// Closed polygon, the last point connects back to the first
+    Polygon { points: Vec<[i32; 2]> },
+}
+
+/// How shapes are drawn
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct ShapeStyle {
+    /// Width of lines & outlines in cells
+    pub thickness: f32,
+    /// Fill the inside of closed shapes instead of only drawing the outline
+    pub filled: bool,
+}
+
+impl Default for ShapeStyle {
+    fn default() -> Self {
+        ShapeStyle {
+            thickness: 1.0,
+            filled: false,
+        }
+    }
+}
+
+/// Distance from p to line segment a->b
+fn distance_to_segment(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let l2 = ab.length_squared();
+    if l2 == 0.0 {
+        return p.distance(a);
+    }
+    let t = ((p - a).dot(ab) / l2).clamp(0.0, 1.0);
+    p.distance(a + t * ab)
+}
+
+/// Even-odd test whether p is inside the closed polygon
+fn is_inside_polygon(p: Vec2, points: &[Vec2]) -> bool {
+    let mut inside = false;
+    for (i, &a) in points.iter().enumerate() {
+        let b = points[(i + 1) % points.len()];
+        if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+fn corners(a: [i32; 2], b: [i32; 2]) -> (IVec2, IVec2) {
+    let (a, b) = (IVec2::from(a), IVec2::from(b));
+    (a.min(b), a.max(b))
+}
+
+impl Shape {
+    fn points(&self) -> Vec<Vec2> {
+        let points = match self {
+            Shape::Line { start, end } => vec![IVec2::from(*start), IVec2::from(*end)],
+            Shape::Rectangle { corner_a, corner_b } | Shape::Ellipse { corner_a, corner_b } => {
+                vec![IVec2::from(*corner_a), IVec2::from(*corner_b)]
+            }
+            Shape::Polygon { points } => points.iter().map(|p| IVec2::from(*p)).collect(),
+        };
+        points.into_iter().map(|p| p.as_vec2()).collect()
+    }
+
+    /// Inclusive bounds of the cells the shape may cover, not clamped to the canvas
+    pub fn bounds(&self, style: &ShapeStyle) -> (IVec2, IVec2) {
+        let points = self.points();
+        let min = points.iter().fold(Vec2::splat(f32::MAX), |min, p| min.min(*p));
+        let max = points.iter().fold(Vec2::splat(f32::MIN), |max, p| max.max(*p));
+        // Lines & polygon edges extend half their thickness beyond their points
+        let margin = match self {
+            Shape::Line { .. } | Shape::Polygon { .. } => (style.thickness / 2.0).ceil(),
+            _ => 0.0,
+        };
+        (
+            (min - margin).floor().as_ivec2(),
+            (max + margin).ceil().as_ivec2(),
+        )
+    }
+
+    /// Does the shape cover the cell at pos
+    pub fn contains(&self, pos: IVec2, style: &ShapeStyle) -> bool {
+        let p = pos.as_vec2();
+        // At least half a cell so that thin lines have no gaps
+        let half_thickness = (style.thickness / 2.0).max(0.5);
+        match self {
+            Shape::Line { start, end } => {
+                let (a, b) = (IVec2::from(*start).as_vec2(), IVec2::from(*end).as_vec2());
+                distance_to_segment(p, a, b) <= half_thickness
+            }
+            Shape::Rectangle { corner_a, corner_b } => {
+                let (min, max) = corners(*corner_a, *corner_b);
+                if pos.cmplt(min).any() || pos.cmpgt(max).any() {
+                    return false;
+                }
+                let thickness = style.thickness.max(1.0);
+                let to_edge = (pos - min).min(max - pos);
+                style.filled || to_edge.min_element() as f32 <= thickness - 1.0
+            }
+            Shape::Ellipse { corner_a, corner_b } => {
+                let (min, max) = corners(*corner_a, *corner_b);
+                let center = (min + max).as_vec2() / 2.0;
+                // Radii reach the outer edge of the corner cells
+                let radii = (max - min).as_vec2() / 2.0 + 0.5;
+                let is_inside = |radii: Vec2| ((p - center) / radii).length_squared() <= 1.0;
+                let inner_radii = radii - style.thickness.max(1.0);
+                is_inside(radii)
+                    && (style.filled || inner_radii.min_element() <= 0.0 || !is_inside(inner_radii))
+            }
+            Shape::Polygon { .. } => {
+                let points = self.points();
+                let on_edge = (0..points.len()).any(|i| {
+                    let (a, b) = (points[i], points[(i + 1) % points.len()]);
+                    distance_to_segment(p, a, b) <= half_thickness
+                });
+                on_edge || (style.filled && is_inside_polygon(p, &points))
+            }
+        }
+    }
+
+    /// Cells the shape covers within the canvas
+    pub fn rasterize(&self, style: &ShapeStyle) -> Vec<IVec2> {
+        let (min, max) = self.bounds(style);
+        let (min, size) = match clamp_to_canvas(min, max) {
+            Some(region) => region,
+            None => return vec![],
+        };
+        (0..size.y as i32)
+            .flat_map(|y| (0..size.x as i32).map(move |x| min + IVec2::new(x, y)))
+            .filter(|pos| self.contains(*pos, style))
+            .collect()
+    }
+
+    /// Outline through cell centers (canvas coordinates) to preview the shape
+    pub fn outline(&self) -> Vec<Vec2> {
+        let points = self.points();
+        match self {
+            Shape::Rectangle { .. } => {
+                let (min, max) = (points[0].min(points[1]), points[0].max(points[1]) + 1.0);
+                vec![min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)]
+            }
+            Shape::Ellipse { .. } => {
+                let center = (points[0] + points[1]) / 2.0 + 0.5;
+                let radii = (points[0] - points[1]).abs() / 2.0 + 0.5;
+                (0..ELLIPSE_PREVIEW_SEGMENTS)
+                    .map(|i| {
+                        let angle =
+                            i as f32 / ELLIPSE_PREVIEW_SEGMENTS as f32 * std::f32::consts::TAU;
+                        center + radii * Vec2::new(angle.cos(), angle.sin())
+                    })
+                    .collect()
+            }
+            _ => points.into_iter().map(|p| p + 0.5).collect(),
+        }
+    }
+
+    /// Is the outline a closed loop
+    pub fn is_closed(&self) -> bool {
+        !matches!(self, Shape::Line { .. })
+    }
+}
+
+/// Shape in progress with the shape tools
+#[derive(Debug, Clone, Default)]
+pub struct ShapeDraft {
+    /// Where the drag started (line, rectangle & ellipse)
+    pub start: Option<IVec2>,
+    /// Placed polygon vertices
+    pub points: Vec<IVec2>,
+}
+
+impl ShapeDraft {
+    /// Shape the tool would draw with the cursor at end, none if nothing is being drawn
+    pub fn shape(&self, tool: Tool, end: IVec2) -> Option<Shape> {
+        let end: [i32; 2] = end.into();
+        match tool {
+            Tool::Line => self.start.map(|start| Shape::Line {
+                start: start.into(),
+                end,
+            }),
+            Tool::Rectangle => self.start.map(|start| Shape::Rectangle {
+                corner_a: start.into(),
+                corner_b: end,
+            }),
+            Tool::Ellipse => self.start.map(|start| Shape::Ellipse {
+                corner_a: start.into(),
+                corner_b: end,
+            }),
+            Tool::Polygon if !self.points.is_empty() => Some(Shape::Polygon {
+                points: self.points.iter().map(|p| (*p).into()).chain([end]).collect(),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.start = None;
+        self.points.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::IVec2;
+
+    use crate::shapes::{Shape, ShapeStyle};
+
+    #[test]
+    fn test_rasterize_shapes() {
+        let thin = ShapeStyle::default();
+        let filled = ShapeStyle {
+            filled: true,
+            ..ShapeStyle::default()
+        };
+        let line = Shape::Line {
+            start: [10, 10],
+            end: [19, 10],
+        };
+        assert_eq!(line.rasterize(&thin).len(), 10);
+        let thick = ShapeStyle {
+            thickness: 3.0,
+            ..ShapeStyle::default()
+        };
+        // Three rows plus round caps of three cells
+        assert_eq!(line.rasterize(&thick).len(), 30 + 2 * 3);
+        // Corners can be given in any order
+        let rectangle = Shape::Rectangle {
+            corner_a: [14, 13],
+            corner_b: [10, 10],
+        };
+        assert_eq!(rectangle.rasterize(&filled).len(), 5 * 4);
+        assert_eq!(rectangle.rasterize(&thin).len(), 5 * 4 - 3 * 2);
+        let ellipse = Shape::Ellipse {
+            corner_a: [0, 0],
+            corner_b: [20, 10],
+        };
+        let cells = ellipse.rasterize(&filled);
+        assert!(cells.contains(&IVec2::new(10, 5)));
+        assert!(!cells.contains(&IVec2::new(0, 0)));
+        assert!(!ellipse.rasterize(&thin).contains(&IVec2::new(10, 5)));
+        let triangle = Shape::Polygon {
+            points: vec![[0, 0], [20, 0], [0, 20]],
+        };
+        assert!(triangle.contains(IVec2::new(5, 5), &filled));
+        assert!(!triangle.contains(IVec2::new(5, 5), &thin));
+        assert!(!triangle.contains(IVec2::new(15, 15), &filled));
+        // Cells outside the canvas are clipped
+        let off_canvas = Shape::Line {
+            start: [-10, 0],
+            end: [9, 0],
+        };
+        assert_eq!(off_canvas.rasterize(&thin).len(), 10);
+    }
+}
@@ -0,0 +1,404 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use bevy_vulkano::{egui_winit_vulkano::egui, BevyVulkanoWindows};
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+
+use crate::{
+    ca_simulator::CASimulator,
+    matter::{MatterDefinition, MatterId, MatterRegistry, EMPTY_NAME},
+    selection::{CellRegion, PasteMode, Selection},
+    utils::u32_rgba_to_u8_rgba,
+    DynamicSettings, Tool,
+};
+
+/// Longest side of stamp thumbnails in the library window
+const THUMBNAIL_SIZE: f32 = 64.0;
+
+/// A saved block of cells. Cells refer to matters by their index in `matters`, so stamps keep
+/// working with matter packs that assign different ids.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Stamp {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    /// Names of the matters used by the cells
+    pub matters: Vec<String>,
+    /// Row by row, packed color & index into `matters`
+    pub cells: Vec<u32>,
+}
+
+impl Stamp {
+    pub fn from_region(name: &str, region: &CellRegion, registry: &MatterRegistry) -> Stamp {
+        let mut matters: Vec<String> = vec![];
+        let cells = region
+            .cells
+            .iter()
+            .map(|&cell| {
+                let matter = registry.name(MatterDefinition::get_id_from_u32(cell));
+                let index = match matters.iter().position(|name| name == matter) {
+                    Some(index) => index,
+                    None => {
+                        matters.push(matter.to_string());
+                        matters.len() - 1
+                    }
+                };
+                (cell & !0xff) | index as u32
+            })
+            .collect();
+        Stamp {
+            name: name.to_string(),
+            width: region.width,
+            height: region.height,
+            matters,
+            cells,
+        }
+    }
+
+    /// Cells with matters looked up by name in registry, unknown matters become empty
+    pub fn to_region(&self, registry: &MatterRegistry) -> CellRegion {
+        let empty = registry.definition(MatterId::EMPTY).to_matter_with_color();
+        let ids = self
+            .matters
+            .iter()
+            .map(|name| registry.id(name))
+            .collect::<Vec<_>>();
+        let cells = self
+            .cells
+            .iter()
+            .map(
+                |&cell| match ids.get((cell & 0xff) as usize).copied().flatten() {
+                    Some(id) if id != MatterId::EMPTY => (cell & !0xff) | id.0 as u32,
+                    _ => empty,
+                },
+            )
+            .collect();
+        CellRegion {
+            width: self.width,
+            height: self.height,
+            cells,
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Stamp> {
+        let text = fs::read_to_string(path)?;
+        Stamp::from_ron(&text)
+    }
+
+    /// Parse a stamp, the cells must fill its width & height
+    pub fn from_ron(text: &str) -> io::Result<Stamp> {
+        let stamp: Stamp =
+            ron::from_str(text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if stamp.cells.len() as u64 != stamp.width as u64 * stamp.height as u64 {
+            let message = format!(
+                "stamp '{}' has {} cells, expected {}x{}",
+                stamp.name,
+                stamp.cells.len(),
+                stamp.width,
+                stamp.height
+            );
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        Ok(stamp)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, text)
+    }
+
+    /// Thumbnail pixels, top row first. Empty cells are transparent.
+    fn thumbnail(&self) -> egui::ColorImage {
+        let rgba = (0..self.height)
+            .rev()
+            .flat_map(|y| &self.cells[(y * self.width) as usize..((y + 1) * self.width) as usize])
+            .flat_map(|&cell| {
+                let [r, g, b, index] = u32_rgba_to_u8_rgba(cell);
+                let is_empty =
+                    self.matters.get(index as usize).map(|name| name.as_str()) == Some(EMPTY_NAME);
+                [r, g, b, if is_empty { 0 } else { 255 }]
+            })
+            .collect::<Vec<_>>();
+        egui::ColorImage::from_rgba_unmultiplied([self.width as usize, self.height as usize], &rgba)
+    }
+}
+
+/// File name for a stamp, characters that don't belong in file names are replaced
+fn stamp_file_name(name: &str) -> String {
+    let stem = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    format!("{}.ron", stem)
+}
+
+pub struct StampEntry {
+    pub stamp: Stamp,
+    pub path: PathBuf,
+    thumbnail: Option<egui::TextureHandle>,
+}
+
+/// Stamps saved in the library folder & the stamp being placed with the stamp tool
+pub struct StampLibrary {
+    pub open: bool,
+    pub folder: PathBuf,
+    pub entries: Vec<StampEntry>,
+    pub selected: Option<usize>,
+    /// Selected stamp as cells of the current matters, rotated & flipped as it will be placed
+    pub placing: Option<CellRegion>,
+    pub paste_mode: PasteMode,
+    new_name: String,
+    /// Why stamps could not be loaded or saved
+    error: Option<String>,
+}
+
+impl StampLibrary {
+    pub fn new(folder: PathBuf) -> StampLibrary {
+        let mut library = StampLibrary {
+            open: false,
+            folder,
+            entries: vec![],
+            selected: None,
+            placing: None,
+            paste_mode: PasteMode::default(),
+            new_name: String::new(),
+            error: None,
+        };
+        library.reload();
+        library
+    }
+
+    /// Load all stamps (`.ron` files) of the library folder
+    pub fn reload(&mut self) {
+        self.entries.clear();
+        self.selected = None;
+        self.placing = None;
+        self.error = None;
+        let dir = match fs::read_dir(&self.folder) {
+            Ok(dir) => dir,
+            // No folder yet, it's created when the first stamp is saved
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(e) => {
+                self.error = Some(format!("Failed to read {:?}: {}", self.folder, e));
+                return;
+            }
+        };
+        let mut paths = dir
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().map_or(false, |ext| ext == "ron"))
+            .collect::<Vec<_>>();
+        paths.sort();
+        for path in paths {
+            match Stamp::load(&path) {
+                Ok(stamp) => self.entries.push(StampEntry {
+                    stamp,
+                    path,
+                    thumbnail: None,
+                }),
+                Err(e) => self.error = Some(format!("Failed to load stamp {:?}: {}", path, e)),
+            }
+        }
+    }
+
+    /// Save a stamp to the library folder, replacing a stamp of the same name
+    pub fn save_stamp(&mut self, stamp: Stamp) -> io::Result<()> {
+        fs::create_dir_all(&self.folder)?;
+        let path = self.folder.join(stamp_file_name(&stamp.name));
+        stamp.save(&path)?;
+        self.entries.retain(|entry| entry.path != path);
+        self.entries.push(StampEntry {
+            stamp,
+            path,
+            thumbnail: None,
+        });
+        self.selected = None;
+        self.placing = None;
+        Ok(())
+    }
+
+    pub fn select(&mut self, index: usize, registry: &MatterRegistry) {
+        self.selected = Some(index);
+        self.placing = self
+            .entries
+            .get(index)
+            .map(|entry| entry.stamp.to_region(registry));
+    }
+
+    /// Min corner of the stamp when placed centered at cursor
+    pub fn place_pos(&self, cursor: IVec2) -> Option<IVec2> {
+        let placing = self.placing.as_ref()?;
+        Some(cursor - placing.size().as_ivec2() / 2)
+    }
+
+    pub fn rotate(&mut self) {
+        if let Some(placing) = &mut self.placing {
+            *placing = placing.rotated();
+        }
+    }
+
+    /// Flip the stamp along the x axis, or the y axis if vertical
+    pub fn flip(&mut self, vertical: bool) {
+        if let Some(placing) = &mut self.placing {
+            *placing = if vertical {
+                placing.flipped_y()
+            } else {
+                placing.flipped_x()
+            };
+        }
+    }
+}
+
+/// Egui window to browse stamps, pick the one to place & save the selection as a stamp
+pub fn stamp_library_interface(
+    vulkano_windows: NonSend<BevyVulkanoWindows>,
+    mut library: ResMut<StampLibrary>,
+    mut settings: ResMut<DynamicSettings>,
+    simulator: Res<CASimulator>,
+    selection: Res<Selection>,
+) {
+    if !library.open {
+        return;
+    }
+    let (_, gui) = vulkano_windows.get_primary_window_renderer().unwrap();
+    let ctx = gui.context();
+    let mut open = library.open;
+    egui::Window::new("Stamps")
+        .open(&mut open)
+        .vscroll(true)
+        .show(&ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!("Folder: {:?}", library.folder));
+                if ui.button("Reload").clicked() {
+                    library.reload();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut library.new_name);
+                let can_save = selection.region().is_some() && !library.new_name.is_empty();
+                if ui
+                    .add_enabled(can_save, egui::Button::new("Save selection as stamp"))
+                    .clicked()
+                {
+                    if let Some((min, size)) = selection.region() {
+                        let region = CellRegion::read(&simulator, min, size);
+                        let stamp =
+                            Stamp::from_region(&library.new_name, &region, simulator.registry());
+                        library.error = library
+                            .save_stamp(stamp)
+                            .err()
+                            .map(|e| format!("Failed to save stamp: {}", e));
+                    }
+                }
+            });
+            if let Some(error) = &library.error {
+                ui.colored_label(egui::Color32::RED, error.as_str());
+            }
+            egui::ComboBox::from_label("Stamp Paste Mode")
+                .selected_text(library.paste_mode.name())
+                .show_ui(ui, |ui| {
+                    for mode in PasteMode::iter() {
+                        ui.selectable_value(&mut library.paste_mode, mode, mode.name());
+                    }
+                });
+            ui.horizontal(|ui| {
+                if ui.button("Rotate").clicked() {
+                    library.rotate();
+                }
+                if ui.button("Flip Horizontal").clicked() {
+                    library.flip(false);
+                }
+                if ui.button("Flip Vertical").clicked() {
+                    library.flip(true);
+                }
+            });
+            ui.separator();
+            if library.entries.is_empty() {
+                ui.label("No stamps yet, select a region with the select tool and save it");
+            }
+            let mut clicked = None;
+            let selected = library.selected;
+            ui.horizontal_wrapped(|ui| {
+                for (i, entry) in library.entries.iter_mut().enumerate() {
+                    let stamp = &entry.stamp;
+                    let texture = entry
+                        .thumbnail
+                        .get_or_insert_with(|| ctx.load_texture(&stamp.name, stamp.thumbnail()));
+                    let scale = THUMBNAIL_SIZE / stamp.width.max(stamp.height) as f32;
+                    let size = egui::vec2(stamp.width as f32, stamp.height as f32) * scale;
+                    let button =
+                        egui::ImageButton::new(texture.id(), size).selected(selected == Some(i));
+                    if ui.add(button).on_hover_text(stamp.name.as_str()).clicked() {
+                        clicked = Some(i);
+                    }
+                }
+            });
+            if let Some(i) = clicked {
+                library.select(i, simulator.registry());
+                settings.tool = Tool::Stamp;
+            }
+            ui.label("Stamp tool: click to place, R: rotate, F/Shift+F: flip");
+        });
+    library.open = open;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{
+        matter::{MatterDefinition, MatterId, MatterRegistry},
+        selection::CellRegion,
+        stamps::Stamp,
+    };
+
+    #[test]
+    fn test_stamp_matters_by_name() {
+        let registry = MatterRegistry::builtin();
+        let color = |id: MatterId| registry.definition(id).to_matter_with_color();
+        let region = CellRegion {
+            width: 3,
+            height: 1,
+            cells: vec![
+                color(MatterId::WATER),
+                color(MatterId::EMPTY),
+                color(MatterId::WATER),
+            ],
+        };
+        let stamp = Stamp::from_region("pool", &region, &registry);
+        assert_eq!(
+            stamp.matters,
+            vec!["Water".to_string(), "Empty".to_string()]
+        );
+        let text = ron::to_string(&stamp).unwrap();
+        assert_eq!(Stamp::from_ron(&text).unwrap(), stamp);
+        // Cells that don't fill the stamp are rejected
+        let truncated = Stamp {
+            cells: vec![0, 1],
+            ..stamp.clone()
+        };
+        let text = ron::to_string(&truncated).unwrap();
+        let error = Stamp::from_ron(&text).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(stamp.to_region(&registry), region);
+        // Matters missing from the registry become empty
+        let unknown = Stamp {
+            matters: vec!["Unobtainium".to_string(), "Water".to_string()],
+            cells: vec![0, 1, 0],
+            ..stamp
+        };
+        let cells = unknown.to_region(&registry).cells;
+        assert_eq!(MatterDefinition::get_id_from_u32(cells[0]), MatterId::EMPTY);
+        assert_eq!(MatterDefinition::get_id_from_u32(cells[1]), MatterId::WATER);
+    }
+}
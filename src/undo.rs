@@ -0,0 +1,262 @@
+use std::collections::VecDeque;
+
+use bevy::math::{IVec2, UVec2};
+
+use crate::{ca_simulator::CASimulator, replay::SimCommand, utils::clamp_to_canvas};
+
+pub const DEFAULT_UNDO_BUDGET_MB: usize = 64;
+
+/// Cells of a rectangular region of the grid
+#[derive(Debug, Clone)]
+pub struct RegionSnapshot {
+    pub min: IVec2,
+    pub size: UVec2,
+    pub cells: Vec<u32>,
+}
+
+impl RegionSnapshot {
+    pub fn read(simulator: &CASimulator, min: IVec2, size: UVec2) -> RegionSnapshot {
+        RegionSnapshot {
+            min,
+            size,
+            cells: simulator.read_region(min, size),
+        }
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.cells.len() * std::mem::size_of::<u32>()
+    }
+
+    /// Command that writes these cells back to the grid
+    pub fn into_command(self) -> SimCommand {
+        SimCommand::WriteRegion {
+            min: self.min.into(),
+            size: self.size.into(),
+            cells: self.cells,
+        }
+    }
+
+    fn contains(&self, min: IVec2, size: UVec2) -> bool {
+        min.cmpge(self.min).all()
+            && (min + size.as_ivec2())
+                .cmple(self.min + self.size.as_ivec2())
+                .all()
+    }
+
+    /// Copy the cells of other that lie within this snapshot over its own cells
+    fn overlay(&mut self, other: &RegionSnapshot) {
+        let min = self.min.max(other.min);
+        let max = (self.min + self.size.as_ivec2()).min(other.min + other.size.as_ivec2());
+        if min.x >= max.x || min.y >= max.y {
+            return;
+        }
+        let width = (max.x - min.x) as usize;
+        for y in min.y..max.y {
+            let src = ((y - other.min.y) * other.size.x as i32 + min.x - other.min.x) as usize;
+            let dst = ((y - self.min.y) * self.size.x as i32 + min.x - self.min.x) as usize;
+            self.cells[dst..dst + width].copy_from_slice(&other.cells[src..src + width]);
+        }
+    }
+}
+
+/// Grid regions as they were before an edit. Edits growing over several frames, like strokes, capture
+/// only the area of each new segment, the pieces are merged when the edit is reverted.
+#[derive(Debug, Clone, Default)]
+struct Edit {
+    pieces: Vec<RegionSnapshot>,
+}
+
+impl Edit {
+    /// Capture a region unless an earlier piece already holds it
+    fn capture(&mut self, simulator: &CASimulator, min: IVec2, size: UVec2) {
+        if !self.pieces.iter().any(|piece| piece.contains(min, size)) {
+            self.pieces.push(RegionSnapshot::read(simulator, min, size));
+        }
+    }
+
+    /// Min & size of the region covering all pieces
+    fn bounds(&self) -> (IVec2, UVec2) {
+        let min = self
+            .pieces
+            .iter()
+            .map(|piece| piece.min)
+            .reduce(IVec2::min)
+            .unwrap();
+        let max = self
+            .pieces
+            .iter()
+            .map(|piece| piece.min + piece.size.as_ivec2())
+            .reduce(IVec2::max)
+            .unwrap();
+        (min, (max - min).as_uvec2())
+    }
+
+    /// Reads the edited region once. Returns it as it is now & as it was before the edit.
+    fn revert(&self, simulator: &CASimulator) -> (RegionSnapshot, RegionSnapshot) {
+        let (min, size) = self.bounds();
+        let current = RegionSnapshot::read(simulator, min, size);
+        let before = self.merged(current.clone());
+        (current, before)
+    }
+
+    /// Lay the pieces over cells outside the edit. Earlier pieces are laid last, as later ones may
+    /// have captured cells the edit had already changed.
+    fn merged(&self, mut region: RegionSnapshot) -> RegionSnapshot {
+        for piece in self.pieces.iter().rev() {
+            region.overlay(piece);
+        }
+        region
+    }
+
+    fn size_bytes(&self) -> usize {
+        self.pieces.iter().map(|piece| piece.size_bytes()).sum()
+    }
+}
+
+impl From<RegionSnapshot> for Edit {
+    fn from(region: RegionSnapshot) -> Self {
+        Edit {
+            pieces: vec![region],
+        }
+    }
+}
+
+/// Undo & redo stack of grid edits. Each edit stores the region it affected as it was before the edit.
+pub struct UndoStack {
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+    /// Edit in progress, e.g. a stroke that is still being drawn
+    current: Option<Edit>,
+    /// How much memory the stored edits may use, oldest edits are dropped first
+    pub budget_bytes: usize,
+}
+
+impl UndoStack {
+    pub fn new(budget_bytes: usize) -> UndoStack {
+        UndoStack {
+            undo: VecDeque::new(),
+            redo: vec![],
+            current: None,
+            budget_bytes,
+        }
+    }
+
+    /// Capture an inclusive region before it gets edited. Captures are grouped to a single edit until
+    /// `end_edit` is called.
+    pub fn capture(&mut self, simulator: &CASimulator, min: IVec2, max: IVec2) {
+        let (min, size) = match clamp_to_canvas(min, max) {
+            Some(region) => region,
+            None => return,
+        };
+        self.current
+            .get_or_insert_with(Edit::default)
+            .capture(simulator, min, size);
+    }
+
+    /// Capture a region that was read from the grid just before it gets edited
+    pub fn capture_snapshot(&mut self, snapshot: RegionSnapshot) {
+        self.current
+            .get_or_insert_with(Edit::default)
+            .pieces
+            .push(snapshot);
+    }
+
+    /// Finish the edit in progress and push it to the undo stack
+    pub fn end_edit(&mut self) {
+        if let Some(edit) = self.current.take() {
+            self.redo.clear();
+            self.undo.push_back(edit);
+            self.enforce_budget();
+        }
+    }
+
+    /// Forget all edits, e.g. after the whole grid was replaced
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.current = None;
+    }
+
+    /// Returns the command that reverts the latest edit
+    pub fn undo(&mut self, simulator: &CASimulator) -> Option<SimCommand> {
+        self.end_edit();
+        let (current, before) = self.undo.pop_back()?.revert(simulator);
+        self.redo.push(current.into());
+        Some(before.into_command())
+    }
+
+    /// Returns the command that re-applies the latest undone edit
+    pub fn redo(&mut self, simulator: &CASimulator) -> Option<SimCommand> {
+        let (current, after) = self.redo.pop()?.revert(simulator);
+        self.undo.push_back(current.into());
+        Some(after.into_command())
+    }
+
+    pub fn num_undo(&self) -> usize {
+        self.undo.len()
+    }
+
+    pub fn num_redo(&self) -> usize {
+        self.redo.len()
+    }
+
+    pub fn memory_usage_bytes(&self) -> usize {
+        self.undo
+            .iter()
+            .chain(self.redo.iter())
+            .chain(self.current.iter())
+            .map(|edit| edit.size_bytes())
+            .sum()
+    }
+
+    /// Drop oldest edits until we fit the memory budget
+    pub fn enforce_budget(&mut self) {
+        while self.memory_usage_bytes() > self.budget_bytes && !self.undo.is_empty() {
+            self.undo.pop_front();
+        }
+        if self.memory_usage_bytes() > self.budget_bytes {
+            self.redo.clear();
+        }
+    }
+}
+
+impl Default for UndoStack {
+    fn default() -> Self {
+        UndoStack::new(DEFAULT_UNDO_BUDGET_MB * 1024 * 1024)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{IVec2, UVec2};
+
+    use crate::undo::{Edit, RegionSnapshot};
+
+    #[test]
+    fn test_edit_pieces_merge() {
+        // A stroke captured in two overlapping segments, the second after the first was drawn
+        let first = RegionSnapshot {
+            min: IVec2::new(0, 0),
+            size: UVec2::new(2, 2),
+            cells: vec![1, 1, 1, 1],
+        };
+        let second = RegionSnapshot {
+            min: IVec2::new(1, 1),
+            size: UVec2::new(2, 2),
+            cells: vec![9, 2, 2, 2],
+        };
+        let edit = Edit {
+            pieces: vec![first, second],
+        };
+        assert_eq!(edit.bounds(), (IVec2::ZERO, UVec2::new(3, 3)));
+        // Cells outside both pieces keep their current value
+        let current = RegionSnapshot {
+            min: IVec2::ZERO,
+            size: UVec2::new(3, 3),
+            cells: vec![5; 9],
+        };
+        let before = edit.merged(current);
+        assert_eq!(before.cells, vec![1, 1, 5, 1, 1, 2, 5, 2, 2]);
+        assert_eq!(edit.size_bytes(), 8 * std::mem::size_of::<u32>());
+    }
+}
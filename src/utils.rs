@@ -138,6 +138,40 @@ pub fn cursor_to_world(window: &Window, camera_pos: Vec2, camera_scale: f32) ->
         - camera_pos
 }
 
+/// Converts canvas position to window position (origin at top left like egui)
+pub fn canvas_to_window(
+    window: &Window,
+    camera_pos: Vec2,
+    camera_scale: f32,
+    canvas_pos: Vec2,
+) -> Vec2 {
+    let world = canvas_pos - Vec2::new(CANVAS_SIZE_X as f32 / 2.0, CANVAS_SIZE_Y as f32 / 2.0);
+    let cursor = (world + camera_pos) / camera_scale
+        + Vec2::new(window.width() / 2.0, window.height() / 2.0);
+    Vec2::new(cursor.x, window.height() - cursor.y)
+}
+
+/// Clamps an inclusive region to the canvas. Returns min & size, or None if the region is fully outside
+pub fn clamp_to_canvas(min: IVec2, max: IVec2) -> Option<(IVec2, UVec2)> {
+    let min = min.max(IVec2::ZERO);
+    let max = max.min(IVec2::new(
+        CANVAS_SIZE_X as i32 - 1,
+        CANVAS_SIZE_Y as i32 - 1,
+    ));
+    if min.x > max.x || min.y > max.y {
+        None
+    } else {
+        Some((min, (max - min + IVec2::ONE).as_uvec2()))
+    }
+}
+
+/// Inclusive bounds of cells a line drawn with given radius may touch
+pub fn line_bounds(start: Vec2, end: Vec2, radius: f32) -> (IVec2, IVec2) {
+    let min = (start.min(end) - Vec2::splat(radius + 1.0)).floor();
+    let max = (start.max(end) + Vec2::splat(radius + 1.0)).ceil();
+    (min.as_ivec2(), max.as_ivec2())
+}
+
 /// Mouse world position
 #[derive(Debug, Copy, Clone)]
 pub struct MousePos {